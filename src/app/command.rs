@@ -0,0 +1,636 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use validator::Validate;
+
+use crate::{
+    app::recipe::{
+        DeleteRecipeRequest, InsertRecipeRequest, InsertRecipesRequest, ListRecipesRequest,
+        NoteRecipeRequest, RandomRecipeRequest, RateRecipeRequest, RecipeRef, RecipeService,
+        SearchRecipeRequest, SuggestRecipesRequest, TagRecipeRequest,
+    },
+    domain::{link::extract_urls, pantry::is_known_ingredient},
+    infra::line::{LineClient, LineMessage, ReplyTo, respond},
+    prelude::*,
+};
+
+const HELP_HINT: &str = "「ヘルプ」と送ると使い方を確認できるよ💡";
+
+/// A text message interpreted as an instruction to the bot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Saves every recipe linked from the message.
+    Save {
+        urls: Vec<String>,
+    },
+    Help,
+    Search {
+        query: String,
+    },
+    List,
+    Random,
+    /// Suggests recipes using the ingredients at hand.
+    Suggest {
+        ingredients: Vec<String>,
+    },
+    Delete {
+        target: RecipeRef,
+    },
+    Tag {
+        target: RecipeRef,
+        tags: Vec<String>,
+    },
+    Rate {
+        target: RecipeRef,
+        rating: u8,
+    },
+    Note {
+        target: RecipeRef,
+        note: String,
+    },
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    #[error("ごめんね、よくわからなかったよ🤔")]
+    Unknown,
+    #[error("{argument}を指定してね\n使い方: {usage}")]
+    MissingArgument {
+        argument: &'static str,
+        usage: &'static str,
+    },
+    #[error("「{value}」は{argument}として使えないよ\n使い方: {usage}")]
+    InvalidArgument {
+        argument: &'static str,
+        value: String,
+        usage: &'static str,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CommandKind {
+    Help,
+    Search,
+    List,
+    Random,
+    Suggest,
+    Delete,
+    Tag,
+    Rate,
+    Note,
+}
+
+struct CommandSpec {
+    kind: CommandKind,
+    aliases: &'static [&'static str],
+    usage: &'static str,
+    description: &'static str,
+}
+
+/// Every prefixed command. The help message is generated from this table.
+const COMMANDS: [CommandSpec; 9] = [
+    CommandSpec {
+        kind: CommandKind::Search,
+        aliases: &["検索", "search"],
+        usage: "検索 <キーワード>",
+        description: "名前やタグでレシピを探す",
+    },
+    CommandSpec {
+        kind: CommandKind::List,
+        aliases: &["一覧", "list"],
+        usage: "一覧",
+        description: "最近登録したレシピを表示する",
+    },
+    CommandSpec {
+        kind: CommandKind::Random,
+        aliases: &["ランダム", "おすすめ", "random"],
+        usage: "ランダム",
+        description: "登録済みのレシピから1つ選ぶ",
+    },
+    CommandSpec {
+        kind: CommandKind::Suggest,
+        aliases: &["作れる", "冷蔵庫", "suggest"],
+        usage: "作れる <材料>...",
+        description: "手元の材料で作れるレシピを探す",
+    },
+    CommandSpec {
+        kind: CommandKind::Tag,
+        aliases: &["タグ", "tag"],
+        usage: "タグ <レシピ> <タグ>...",
+        description: "レシピにタグを付ける",
+    },
+    CommandSpec {
+        kind: CommandKind::Rate,
+        aliases: &["評価", "rate"],
+        usage: "評価 <レシピ> <1〜5 または ★>",
+        description: "レシピを5段階で評価する",
+    },
+    CommandSpec {
+        kind: CommandKind::Note,
+        aliases: &["メモ", "note"],
+        usage: "メモ <レシピ> <内容>",
+        description: "レシピのページにメモを残す",
+    },
+    CommandSpec {
+        kind: CommandKind::Delete,
+        aliases: &["削除", "delete"],
+        usage: "削除 <レシピ>",
+        description: "レシピを削除する",
+    },
+    CommandSpec {
+        kind: CommandKind::Help,
+        aliases: &["ヘルプ", "使い方", "help"],
+        usage: "ヘルプ",
+        description: "この使い方を表示する",
+    },
+];
+
+/// Characters that may precede a command, e.g. `/search`.
+const COMMAND_MARKERS: [char; 4] = ['/', '／', '#', '＃'];
+/// Endings of natural-language search requests such as `鶏肉を検索して`.
+const SEARCH_SUFFIXES: [&str; 6] = [
+    "を検索して",
+    "を検索",
+    "で検索",
+    "を探して",
+    "探して",
+    "のレシピ",
+];
+const RANDOM_PHRASES: [&str; 4] = ["何作ろう", "なに作ろう", "何つくろう", "なにつくろう"];
+/// Questions asking what the ingredients before them make, as in `鶏肉と玉ねぎで何作れる？`.
+const SUGGEST_PHRASES: [&str; 6] = [
+    "何が作れる",
+    "なにが作れる",
+    "何作れる",
+    "なに作れる",
+    "何がつくれる",
+    "何つくれる",
+];
+/// Words around the ingredients of a suggestion request, as in `冷蔵庫に鶏肉があるけど`.
+const PANTRY_PREFIXES: [&str; 4] = ["冷蔵庫には", "冷蔵庫に", "冷蔵庫の", "家に"];
+const PANTRY_SUFFIXES: [&str; 7] = [
+    "があるんだけど",
+    "があるけど",
+    "があるから",
+    "がある",
+    "あるけど",
+    "から",
+    "で",
+];
+
+impl Command {
+    pub fn parse(text: &str) -> std::result::Result<Self, CommandError> {
+        let text = text.trim();
+        let words = tokenize(text.trim_start_matches(COMMAND_MARKERS));
+        if let Some((head, args)) = words.split_first()
+            && let Some(spec) = find_spec(head)
+        {
+            return parse_args(spec, args);
+        }
+
+        // Links shared from other apps come with their page title or a comment around them.
+        let urls = extract_urls(text);
+        if !urls.is_empty() {
+            return Ok(Self::Save {
+                urls: urls.into_iter().map(String::from).collect(),
+            });
+        }
+
+        parse_natural(text).ok_or(CommandError::Unknown)
+    }
+}
+
+pub fn help_message() -> String {
+    let mut lines = vec![
+        "使い方📖".to_string(),
+        "・レシピのURLを送る\n  レシピを保存する（複数のURLもまとめて送れるよ）".to_string(),
+    ];
+    lines.extend(COMMANDS.iter().map(|spec| {
+        format!(
+            "・{}（{}）\n  {}",
+            spec.usage,
+            spec.aliases.join("/"),
+            spec.description
+        )
+    }));
+    lines.push("<レシピ> には名前かIDを指定してね。空白を含む名前は「」で囲ってね".to_string());
+    lines.join("\n")
+}
+
+fn find_spec(word: &str) -> Option<&'static CommandSpec> {
+    COMMANDS.iter().find(|spec| {
+        spec.aliases
+            .iter()
+            .any(|alias| word.eq_ignore_ascii_case(alias))
+    })
+}
+
+/// Splits a message into words, keeping text in `「」` or quotes together.
+fn tokenize(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut closing = None;
+    for c in text.chars() {
+        match closing {
+            Some(close) if c == close => {
+                words.push(std::mem::take(&mut word));
+                closing = None;
+            }
+            Some(_) => word.push(c),
+            None if c.is_whitespace() => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            None if word.is_empty() && matches!(c, '「' | '"' | '“') => {
+                closing = Some(match c {
+                    '「' => '」',
+                    '“' => '”',
+                    _ => '"',
+                });
+            }
+            None => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn parse_args(spec: &CommandSpec, args: &[String]) -> std::result::Result<Command, CommandError> {
+    let missing = |argument| CommandError::MissingArgument {
+        argument,
+        usage: spec.usage,
+    };
+    let target = || {
+        args.first()
+            .map(|arg| RecipeRef::parse(arg))
+            .ok_or(missing("レシピ"))
+    };
+
+    let command = match spec.kind {
+        CommandKind::Help => Command::Help,
+        CommandKind::List => Command::List,
+        CommandKind::Random => Command::Random,
+        CommandKind::Suggest => {
+            let ingredients = split_ingredients(&args.join(" "));
+            if ingredients.is_empty() {
+                return Err(missing("材料"));
+            }
+            Command::Suggest { ingredients }
+        }
+        CommandKind::Search => {
+            if args.is_empty() {
+                return Err(missing("キーワード"));
+            }
+            Command::Search {
+                query: args.join(" "),
+            }
+        }
+        CommandKind::Delete => Command::Delete { target: target()? },
+        CommandKind::Tag => {
+            let target = target()?;
+            let tags: Vec<String> = args[1..]
+                .iter()
+                .flat_map(|arg| arg.split([',', '、', '，']))
+                .map(|tag| tag.trim().trim_start_matches(['#', '＃']).to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect();
+            if tags.is_empty() {
+                return Err(missing("タグ"));
+            }
+            Command::Tag { target, tags }
+        }
+        CommandKind::Rate => {
+            let target = target()?;
+            let value = args.get(1).ok_or(missing("評価"))?;
+            let rating = parse_rating(value).ok_or_else(|| CommandError::InvalidArgument {
+                argument: "評価",
+                value: value.clone(),
+                usage: spec.usage,
+            })?;
+            Command::Rate { target, rating }
+        }
+        CommandKind::Note => {
+            let target = target()?;
+            if args.len() < 2 {
+                return Err(missing("メモの内容"));
+            }
+            Command::Note {
+                target,
+                note: args[1..].join(" "),
+            }
+        }
+    };
+    Ok(command)
+}
+
+/// Parses a rating written as a digit (`4`, `４`) or as stars (`★★★★`).
+fn parse_rating(value: &str) -> Option<u8> {
+    let rating = if value.chars().all(|c| c == '★') {
+        value.chars().count()
+    } else {
+        let mut chars = value.chars();
+        let digit = chars.next()?;
+        if chars.next().is_some() {
+            return None;
+        }
+        match digit {
+            '０'..='９' => (digit as u32 - '０' as u32) as usize,
+            _ => digit.to_digit(10)? as usize,
+        }
+    };
+    (1..=5).contains(&rating).then_some(rating as u8)
+}
+
+/// Recognizes a few conversational phrasings of the commands.
+fn parse_natural(text: &str) -> Option<Command> {
+    if ["使い方", "ヘルプ", "何ができる"]
+        .iter()
+        .any(|phrase| text.contains(phrase))
+    {
+        return Some(Command::Help);
+    }
+    if let Some((before, _)) = SUGGEST_PHRASES
+        .iter()
+        .find_map(|phrase| text.split_once(*phrase))
+    {
+        let mut list = before.trim();
+        list = PANTRY_PREFIXES
+            .iter()
+            .find_map(|prefix| list.strip_prefix(*prefix))
+            .unwrap_or(list);
+        list = PANTRY_SUFFIXES
+            .iter()
+            .find_map(|suffix| list.strip_suffix(*suffix))
+            .unwrap_or(list);
+        let ingredients = split_ingredients(list);
+        return Some(if ingredients.is_empty() {
+            Command::Random
+        } else {
+            Command::Suggest { ingredients }
+        });
+    }
+    if RANDOM_PHRASES.iter().any(|phrase| text.contains(phrase)) {
+        return Some(Command::Random);
+    }
+    if text.contains("一覧") {
+        return Some(Command::List);
+    }
+
+    let mut query = text.trim_end_matches(['?', '？', '!', '！', '。']);
+    for suffix in SEARCH_SUFFIXES {
+        query = query.strip_suffix(suffix).unwrap_or(query);
+    }
+    let query = query.trim();
+    (query.len() < text.len() && !query.is_empty()).then(|| Command::Search {
+        query: query.to_string(),
+    })
+}
+
+/// Splits a list such as `鶏肉と玉ねぎ、にんじん` into ingredients. `と` and `や` only separate
+/// ingredients the pantry knows, so `さとう`, `もやし` and `やきとり` stay whole.
+fn split_ingredients(text: &str) -> Vec<String> {
+    text.split(|c: char| c.is_whitespace() || matches!(c, '、' | ',' | '，' | '・'))
+        .filter(|part| !part.is_empty())
+        .flat_map(|part| split_known(part).unwrap_or_else(|| vec![part.to_string()]))
+        .collect()
+}
+
+/// Splits the text at `と` or `や` into known ingredients, or returns `None` when it is not made
+/// of them. Splitting is tried first, since a known name also matches as the end of a list.
+fn split_known(text: &str) -> Option<Vec<String>> {
+    text.char_indices()
+        .filter(|&(i, c)| i > 0 && matches!(c, 'と' | 'や'))
+        .find_map(|(i, c)| {
+            let head = &text[..i];
+            if !is_known_ingredient(head) {
+                return None;
+            }
+            let mut ingredients = split_known(&text[i + c.len_utf8()..])?;
+            ingredients.insert(0, head.to_string());
+            Some(ingredients)
+        })
+        .or_else(|| is_known_ingredient(text).then(|| vec![text.to_string()]))
+}
+
+#[derive(Clone)]
+pub struct CommandService {
+    recipe_service: RecipeService,
+    line_client: Arc<dyn LineClient + Send + Sync>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Validate)]
+pub struct CommandRequest {
+    #[validate(length(min = 1))]
+    pub text: String,
+    #[validate(nested)]
+    pub reply_to: ReplyTo,
+}
+
+impl CommandService {
+    pub fn new(
+        recipe_service: RecipeService,
+        line_client: Arc<dyn LineClient + Send + Sync>,
+    ) -> Self {
+        Self {
+            recipe_service,
+            line_client,
+        }
+    }
+
+    pub async fn handle(&self, command_request: CommandRequest) -> Result<()> {
+        command_request.validate()?;
+        let reply_to = command_request.reply_to;
+
+        let command = match Command::parse(&command_request.text) {
+            Ok(command) => command,
+            Err(e) => {
+                return self.reply_text(reply_to, format!("{e}\n{HELP_HINT}")).await;
+            }
+        };
+        match command {
+            Command::Save { mut urls } if urls.len() == 1 => {
+                self.recipe_service
+                    .insert_recipe(InsertRecipeRequest {
+                        recipe_url: urls.remove(0),
+                        reply_to,
+                    })
+                    .await
+            }
+            Command::Save { urls } => {
+                self.recipe_service
+                    .insert_recipes(InsertRecipesRequest {
+                        recipe_urls: urls,
+                        reply_to,
+                    })
+                    .await
+            }
+            Command::Help => self.reply_text(reply_to, help_message()).await,
+            Command::Search { query } => {
+                self.recipe_service
+                    .search_recipes(SearchRecipeRequest { query, reply_to })
+                    .await
+            }
+            Command::List => {
+                self.recipe_service
+                    .list_recipes(ListRecipesRequest { reply_to })
+                    .await
+            }
+            Command::Random => {
+                self.recipe_service
+                    .random_recipe(RandomRecipeRequest { reply_to })
+                    .await
+            }
+            Command::Suggest { ingredients } => {
+                self.recipe_service
+                    .suggest_recipes(SuggestRecipesRequest {
+                        ingredients,
+                        reply_to,
+                    })
+                    .await
+            }
+            Command::Delete { target } => {
+                self.recipe_service
+                    .delete_recipe(DeleteRecipeRequest { target, reply_to })
+                    .await
+            }
+            Command::Tag { target, tags } => {
+                self.recipe_service
+                    .tag_recipe(TagRecipeRequest {
+                        target,
+                        tags,
+                        reply_to,
+                    })
+                    .await
+            }
+            Command::Rate { target, rating } => {
+                self.recipe_service
+                    .rate_recipe(RateRecipeRequest {
+                        target,
+                        rating,
+                        reply_to,
+                    })
+                    .await
+            }
+            Command::Note { target, note } => {
+                self.recipe_service
+                    .note_recipe(NoteRecipeRequest {
+                        target,
+                        note,
+                        reply_to,
+                    })
+                    .await
+            }
+        }
+    }
+
+    async fn reply_text(&self, reply_to: ReplyTo, text: String) -> Result<()> {
+        respond(
+            self.line_client.as_ref(),
+            &reply_to,
+            vec![LineMessage::Text(text)],
+        )
+        .await
+        .with_context(|| "failed to reply message")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use crate::{
+        app::postback::PostbackCodec,
+        infra::{
+            html::MockHtmlClient, line::MockLineClient, repository::recipe::MockRecipeRepository,
+        },
+    };
+
+    use super::*;
+
+    fn name(name: &str) -> RecipeRef {
+        RecipeRef::Name(name.to_string())
+    }
+
+    #[test_case("https://example.com/recipe/1", Command::Save { urls: vec!["https://example.com/recipe/1".to_string()] }; "url")]
+    #[test_case("これとこれ作りたい https://a.example.com/1 https://b.example.com/2", Command::Save { urls: vec!["https://a.example.com/1".to_string(), "https://b.example.com/2".to_string()] }; "urls in text")]
+    #[test_case("メモ カレー https://example.com/tips", Command::Note { target: name("カレー"), note: "https://example.com/tips".to_string() }; "url in command")]
+    #[test_case("ヘルプ", Command::Help; "help")]
+    #[test_case("/help", Command::Help; "slash help")]
+    #[test_case("検索 鶏肉 玉ねぎ", Command::Search { query: "鶏肉 玉ねぎ".to_string() }; "search")]
+    #[test_case("SEARCH chicken", Command::Search { query: "chicken".to_string() }; "search uppercase")]
+    #[test_case("一覧", Command::List; "list")]
+    #[test_case("おすすめ", Command::Random; "random alias")]
+    #[test_case("削除 01ARZ3NDEKTSV4RRFFQ69G5FAV", Command::Delete { target: RecipeRef::Id("01ARZ3NDEKTSV4RRFFQ69G5FAV".parse().unwrap()) }; "delete by id")]
+    #[test_case("タグ 「鶏の 照り焼き」 #和食、お弁当", Command::Tag { target: name("鶏の 照り焼き"), tags: vec!["和食".to_string(), "お弁当".to_string()] }; "tag quoted")]
+    #[test_case("評価 カレー ★★★★", Command::Rate { target: name("カレー"), rating: 4 }; "rate stars")]
+    #[test_case("rate カレー ５", Command::Rate { target: name("カレー"), rating: 5 }; "rate full width")]
+    #[test_case("メモ カレー 辛さ 控えめ", Command::Note { target: name("カレー"), note: "辛さ 控えめ".to_string() }; "note")]
+    #[test_case("鶏肉を検索して", Command::Search { query: "鶏肉".to_string() }; "natural search")]
+    #[test_case("豚肉で検索", Command::Search { query: "豚肉".to_string() }; "natural search with de")]
+    #[test_case("なすのレシピを探して", Command::Search { query: "なす".to_string() }; "natural recipe search")]
+    #[test_case("今日何作ろう？", Command::Random; "natural random")]
+    #[test_case("作れる 鶏肉、玉ねぎ にんじん", Command::Suggest { ingredients: vec!["鶏肉".to_string(), "玉ねぎ".to_string(), "にんじん".to_string()] }; "suggest")]
+    #[test_case("冷蔵庫に鶏肉と玉ねぎがあるけど何作れる？", Command::Suggest { ingredients: vec!["鶏肉".to_string(), "玉ねぎ".to_string()] }; "natural suggest")]
+    #[test_case("にんじんととうもろこしで何が作れる", Command::Suggest { ingredients: vec!["にんじん".to_string(), "とうもろこし".to_string()] }; "natural suggest with to")]
+    #[test_case("にらともやしで何が作れる", Command::Suggest { ingredients: vec!["にら".to_string(), "もやし".to_string()] }; "natural suggest with moyashi")]
+    #[test_case("作れる さといもとししとうとなす", Command::Suggest { ingredients: vec!["さといも".to_string(), "ししとう".to_string(), "なす".to_string()] }; "suggest keeps names with particles")]
+    #[test_case("作れる さとうとなっとう", Command::Suggest { ingredients: vec!["さとう".to_string(), "なっとう".to_string()] }; "suggest keeps sugar and natto whole")]
+    #[test_case("作れる やきとり ひやしちゅうか", Command::Suggest { ingredients: vec!["やきとり".to_string(), "ひやしちゅうか".to_string()] }; "suggest keeps unknown names whole")]
+    #[test_case("何作れる？", Command::Random; "natural suggest without ingredients")]
+    fn test_parse(text: &str, expected: Command) {
+        assert_eq!(Command::parse(text), Ok(expected));
+    }
+
+    #[test_case("こんにちは", CommandError::Unknown; "unknown")]
+    #[test_case("検索", CommandError::MissingArgument { argument: "キーワード", usage: "検索 <キーワード>" }; "search without query")]
+    #[test_case("作れる", CommandError::MissingArgument { argument: "材料", usage: "作れる <材料>..." }; "suggest without ingredients")]
+    #[test_case("タグ カレー", CommandError::MissingArgument { argument: "タグ", usage: "タグ <レシピ> <タグ>..." }; "tag without tags")]
+    #[test_case("評価 カレー 6", CommandError::InvalidArgument { argument: "評価", value: "6".to_string(), usage: "評価 <レシピ> <1〜5 または ★>" }; "rating out of range")]
+    fn test_parse_error(text: &str, expected: CommandError) {
+        assert_eq!(Command::parse(text), Err(expected));
+    }
+
+    #[test]
+    fn test_help_message_lists_every_command() {
+        let help = help_message();
+        for spec in COMMANDS.iter() {
+            assert!(help.contains(spec.usage), "{} is missing", spec.usage);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_unknown_command() {
+        let mut line_client = MockLineClient::new();
+        line_client
+            .expect_reply_messages()
+            .withf(|_, messages| {
+                matches!(
+                    messages.as_slice(),
+                    [LineMessage::Text(text)] if text.ends_with(HELP_HINT)
+                )
+            })
+            .times(1)
+            .returning(|_, _| Ok(()));
+        let line_client = Arc::new(line_client);
+        let recipe_service = RecipeService::new(
+            Arc::new(MockRecipeRepository::new()),
+            line_client.clone(),
+            Arc::new(MockHtmlClient::new()),
+            PostbackCodec::new("channel_secret"),
+        );
+
+        let request = CommandRequest {
+            text: "こんにちは".to_string(),
+            reply_to: ReplyTo::new("reply_token"),
+        };
+
+        let result = CommandService::new(recipe_service, line_client)
+            .handle(request)
+            .await;
+        assert!(result.is_ok());
+    }
+}
@@ -1,2 +1,3 @@
-pub mod echo;
+pub mod command;
+pub mod postback;
 pub mod recipe;
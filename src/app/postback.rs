@@ -0,0 +1,397 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use ulid::Ulid;
+use validator::Validate;
+
+use crate::{
+    app::recipe::{
+        DeleteRecipeRequest, PlanRecipeRequest, RateRecipeRequest, RecipeRef, RecipeService,
+    },
+    infra::line::{LineAction, LineClient, LineMessage, ReplyTo, respond},
+    prelude::*,
+};
+
+const TAG_PROMPT_MESSAGE: &str = "付けたいタグを続けて送ってね🏷️";
+const RATE_PROMPT_MESSAGE: &str = "何点にする？";
+const INVALID_POSTBACK_MESSAGE: &str =
+    "このボタンは使えないみたい。もう一度メッセージから操作してね🙏";
+
+/// Version of the postback payload format, bumped on incompatible changes.
+const POSTBACK_VERSION: &str = "1";
+const SEPARATOR: char = '.';
+/// LINE limits postback data to 300 characters.
+pub(crate) const MAX_POSTBACK_DATA_LENGTH: usize = 300;
+/// Number of HMAC bytes kept in the payload.
+const SIGNATURE_LENGTH: usize = 12;
+
+/// The `RecipeService` operation a postback button triggers, with its arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PostbackAction {
+    Tag,
+    Plan,
+    Delete,
+    /// Asks for a rating when `None`, otherwise applies it.
+    Rate(Option<u8>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Postback {
+    pub action: PostbackAction,
+    pub recipe_id: Ulid,
+}
+
+impl Postback {
+    pub fn new(action: PostbackAction, recipe_id: Ulid) -> Self {
+        Self { action, recipe_id }
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum PostbackError {
+    #[error("malformed postback data")]
+    Malformed,
+    #[error("unsupported postback version: {0}")]
+    UnsupportedVersion(String),
+    #[error("invalid postback signature")]
+    InvalidSignature,
+    #[error("unknown postback action: {0}")]
+    UnknownAction(String),
+}
+
+/// Encodes postbacks as `<version>.<action>.<recipe id>[.<argument>...].<signature>`.
+///
+/// The signature is a truncated HMAC-SHA256 of the rest of the payload, so data that was
+/// not issued by the bot is rejected.
+#[derive(Clone)]
+pub struct PostbackCodec {
+    secret: Arc<str>,
+}
+
+impl PostbackCodec {
+    pub fn new(secret: impl Into<Arc<str>>) -> Self {
+        Self {
+            secret: secret.into(),
+        }
+    }
+
+    pub fn encode(&self, postback: &Postback) -> String {
+        let (action, args) = match postback.action {
+            PostbackAction::Tag => ("tag", vec![]),
+            PostbackAction::Plan => ("plan", vec![]),
+            PostbackAction::Delete => ("delete", vec![]),
+            PostbackAction::Rate(rating) => ("rate", rating.into_iter().collect()),
+        };
+        let mut payload = format!("{POSTBACK_VERSION}.{action}.{}", postback.recipe_id);
+        for arg in args {
+            payload.push(SEPARATOR);
+            payload.push_str(&arg.to_string());
+        }
+        let signature =
+            URL_SAFE_NO_PAD.encode(&self.mac(&payload).finalize().into_bytes()[..SIGNATURE_LENGTH]);
+        format!("{payload}{SEPARATOR}{signature}")
+    }
+
+    pub fn decode(&self, data: &str) -> std::result::Result<Postback, PostbackError> {
+        let (payload, signature) = data
+            .rsplit_once(SEPARATOR)
+            .ok_or(PostbackError::Malformed)?;
+        let mut parts = payload.split(SEPARATOR);
+        let version = parts.next().ok_or(PostbackError::Malformed)?;
+        if version != POSTBACK_VERSION {
+            return Err(PostbackError::UnsupportedVersion(version.to_string()));
+        }
+
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| PostbackError::InvalidSignature)?;
+        if signature.len() != SIGNATURE_LENGTH
+            || self.mac(payload).verify_truncated_left(&signature).is_err()
+        {
+            return Err(PostbackError::InvalidSignature);
+        }
+
+        let action = parts.next().ok_or(PostbackError::Malformed)?;
+        let recipe_id = parts
+            .next()
+            .and_then(|id| Ulid::from_string(id).ok())
+            .ok_or(PostbackError::Malformed)?;
+        let args = parts.collect::<Vec<_>>();
+        let action = match (action, args.as_slice()) {
+            ("tag", []) => PostbackAction::Tag,
+            ("plan", []) => PostbackAction::Plan,
+            ("delete", []) => PostbackAction::Delete,
+            ("rate", []) => PostbackAction::Rate(None),
+            ("rate", [rating]) => PostbackAction::Rate(Some(
+                rating
+                    .parse()
+                    .ok()
+                    .filter(|rating| (1..=5).contains(rating))
+                    .ok_or(PostbackError::Malformed)?,
+            )),
+            ("tag" | "plan" | "delete" | "rate", _) => return Err(PostbackError::Malformed),
+            (action, _) => return Err(PostbackError::UnknownAction(action.to_string())),
+        };
+        Ok(Postback::new(action, recipe_id))
+    }
+
+    fn mac(&self, payload: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+/// Quick-reply buttons offered after a recipe is saved.
+pub fn saved_recipe_actions(codec: &PostbackCodec, recipe_id: Ulid) -> Vec<LineAction> {
+    let data = |action| codec.encode(&Postback::new(action, recipe_id));
+    vec![
+        LineAction::Postback {
+            label: "タグを付ける".to_string(),
+            data: data(PostbackAction::Tag),
+            display_text: None,
+            // Let the user type the tags right after the command.
+            fill_in_text: Some(format!("タグ {recipe_id} ")),
+        },
+        LineAction::Postback {
+            label: "今週作る".to_string(),
+            data: data(PostbackAction::Plan),
+            display_text: Some("今週作る".to_string()),
+            fill_in_text: None,
+        },
+        LineAction::Postback {
+            label: "評価する".to_string(),
+            data: data(PostbackAction::Rate(None)),
+            display_text: Some("評価する".to_string()),
+            fill_in_text: None,
+        },
+        LineAction::Postback {
+            label: "取り消す".to_string(),
+            data: data(PostbackAction::Delete),
+            display_text: Some("取り消す".to_string()),
+            fill_in_text: None,
+        },
+    ]
+}
+
+fn rating_actions(codec: &PostbackCodec, recipe_id: Ulid) -> Vec<LineAction> {
+    (1..=5)
+        .map(|rating: u8| LineAction::Postback {
+            label: "★".repeat(rating.into()),
+            data: codec.encode(&Postback::new(
+                PostbackAction::Rate(Some(rating)),
+                recipe_id,
+            )),
+            display_text: Some("★".repeat(rating.into())),
+            fill_in_text: None,
+        })
+        .collect()
+}
+
+#[derive(Clone)]
+pub struct PostbackService {
+    recipe_service: RecipeService,
+    postback_codec: PostbackCodec,
+    line_client: Arc<dyn LineClient + Send + Sync>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Validate)]
+pub struct PostbackRequest {
+    #[validate(length(min = 1))]
+    pub data: String,
+    #[validate(nested)]
+    pub reply_to: ReplyTo,
+}
+
+impl PostbackService {
+    pub fn new(
+        recipe_service: RecipeService,
+        postback_codec: PostbackCodec,
+        line_client: Arc<dyn LineClient + Send + Sync>,
+    ) -> Self {
+        Self {
+            recipe_service,
+            postback_codec,
+            line_client,
+        }
+    }
+
+    pub async fn handle(&self, postback_request: PostbackRequest) -> Result<()> {
+        postback_request.validate()?;
+        let reply_to = postback_request.reply_to;
+
+        let postback = match self.postback_codec.decode(&postback_request.data) {
+            Ok(postback) => postback,
+            Err(e) => {
+                tracing::warn!(%e, data = %postback_request.data, "rejected postback");
+                return self
+                    .reply(
+                        reply_to,
+                        LineMessage::Text(INVALID_POSTBACK_MESSAGE.to_string()),
+                    )
+                    .await;
+            }
+        };
+        let target = RecipeRef::Id(postback.recipe_id);
+
+        match postback.action {
+            PostbackAction::Tag => {
+                self.reply(reply_to, LineMessage::Text(TAG_PROMPT_MESSAGE.to_string()))
+                    .await
+            }
+            PostbackAction::Plan => {
+                self.recipe_service
+                    .plan_recipe(PlanRecipeRequest { target, reply_to })
+                    .await
+            }
+            PostbackAction::Delete => {
+                self.recipe_service
+                    .delete_recipe(DeleteRecipeRequest { target, reply_to })
+                    .await
+            }
+            PostbackAction::Rate(None) => {
+                self.reply(
+                    reply_to,
+                    LineMessage::QuickReply {
+                        message: Box::new(LineMessage::Text(RATE_PROMPT_MESSAGE.to_string())),
+                        actions: rating_actions(&self.postback_codec, postback.recipe_id),
+                    },
+                )
+                .await
+            }
+            PostbackAction::Rate(Some(rating)) => {
+                self.recipe_service
+                    .rate_recipe(RateRecipeRequest {
+                        target,
+                        rating,
+                        reply_to,
+                    })
+                    .await
+            }
+        }
+    }
+
+    async fn reply(&self, reply_to: ReplyTo, message: LineMessage) -> Result<()> {
+        respond(self.line_client.as_ref(), &reply_to, vec![message])
+            .await
+            .with_context(|| "failed to reply message")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use crate::infra::{
+        html::MockHtmlClient, line::MockLineClient, repository::recipe::MockRecipeRepository,
+    };
+
+    use super::*;
+
+    const RECIPE_ID: &str = "01ARZ3NDEKTSV4RRFFQ69G5FAV";
+
+    fn codec() -> PostbackCodec {
+        PostbackCodec::new("channel_secret")
+    }
+
+    #[test_case(PostbackAction::Tag; "tag")]
+    #[test_case(PostbackAction::Plan; "plan")]
+    #[test_case(PostbackAction::Delete; "delete")]
+    #[test_case(PostbackAction::Rate(None); "rate prompt")]
+    #[test_case(PostbackAction::Rate(Some(3)); "rate")]
+    fn test_postback_round_trip(action: PostbackAction) {
+        let postback = Postback::new(action, RECIPE_ID.parse().unwrap());
+        let data = codec().encode(&postback);
+        assert!(data.len() <= MAX_POSTBACK_DATA_LENGTH);
+        assert_eq!(codec().decode(&data), Ok(postback));
+    }
+
+    #[test]
+    fn test_decode_rejects_tampered_payload() {
+        let data = codec().encode(&Postback::new(
+            PostbackAction::Rate(Some(1)),
+            RECIPE_ID.parse().unwrap(),
+        ));
+        let tampered = data.replacen(".1.", ".5.", 1);
+        assert_eq!(
+            codec().decode(&tampered),
+            Err(PostbackError::InvalidSignature)
+        );
+        assert_eq!(
+            PostbackCodec::new("another_secret").decode(&data),
+            Err(PostbackError::InvalidSignature)
+        );
+    }
+
+    #[test_case("" => PostbackError::Malformed; "empty")]
+    #[test_case("action=plan&id=01ARZ3NDEKTSV4RRFFQ69G5FAV" => PostbackError::Malformed; "legacy format")]
+    #[test_case("2.plan.01ARZ3NDEKTSV4RRFFQ69G5FAV.sig" => PostbackError::UnsupportedVersion("2".to_string()); "future version")]
+    fn test_decode_error(data: &str) -> PostbackError {
+        codec().decode(data).unwrap_err()
+    }
+
+    fn postback_service(line_client: MockLineClient) -> PostbackService {
+        let line_client = Arc::new(line_client);
+        let recipe_service = RecipeService::new(
+            Arc::new(MockRecipeRepository::new()),
+            line_client.clone(),
+            Arc::new(MockHtmlClient::new()),
+            codec(),
+        );
+        PostbackService::new(recipe_service, codec(), line_client)
+    }
+
+    #[tokio::test]
+    async fn test_handle_rate_prompt() {
+        let mut line_client = MockLineClient::new();
+        line_client
+            .expect_reply_messages()
+            .withf(|_, messages| {
+                matches!(
+                    messages.as_slice(),
+                    [LineMessage::QuickReply { actions, .. }] if actions.len() == 5
+                )
+            })
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        let request = PostbackRequest {
+            data: codec().encode(&Postback::new(
+                PostbackAction::Rate(None),
+                RECIPE_ID.parse().unwrap(),
+            )),
+            reply_to: ReplyTo::new("reply_token"),
+        };
+
+        let result = postback_service(line_client).handle(request).await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_handle_invalid_postback() {
+        let mut line_client = MockLineClient::new();
+        line_client
+            .expect_reply_messages()
+            .withf(|_, messages| {
+                matches!(
+                    messages.as_slice(),
+                    [LineMessage::Text(text)] if text == INVALID_POSTBACK_MESSAGE
+                )
+            })
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        let request = PostbackRequest {
+            data: "1.delete.01ARZ3NDEKTSV4RRFFQ69G5FAV.forged".to_string(),
+            reply_to: ReplyTo::new("reply_token"),
+        };
+
+        let result = postback_service(line_client).handle(request).await;
+        assert!(result.is_ok());
+    }
+}
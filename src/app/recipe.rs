@@ -1,11 +1,19 @@
 use anyhow::Context;
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use rand::seq::IndexedRandom;
 use validator::Validate;
 
 use crate::{
-    domain::recipe::Recipe,
+    app::postback::{PostbackCodec, saved_recipe_actions},
+    domain::{
+        link::{canonicalize_url, resolve_canonical_url},
+        pantry::{Suggestion, suggest},
+        recipe::{Ingredient, MAX_SERVINGS, Recipe, RecipeDetails, RecipeField, parse_servings},
+        search::{SearchHit, SearchQuery},
+    },
     infra::{
-        html::HtmlClient,
-        line::{LineClient, LineMessage},
+        html::{HtmlClient, ScrapedRecipe},
+        line::{FlexBubble, LineAction, LineClient, LineMessage, ReplyTo, respond},
     },
     prelude::*,
 };
@@ -14,20 +22,141 @@ use std::sync::Arc;
 use crate::infra::repository::recipe::RecipeRepository;
 
 const INSERT_RECIPE_MESSAGE: &str = "レシピを登録したよ✨";
+const DUPLICATE_RECIPE_MESSAGE: &str = "このレシピはもう登録済みだよ📖";
+const RECIPE_NOT_FOUND_MESSAGE: &str = "レシピが見つからなかったよ🔍";
+const AMBIGUOUS_RECIPE_MESSAGE: &str = "当てはまるレシピが複数あるよ。もう少し詳しく指定してね🙏";
+const NO_RECIPES_MESSAGE: &str = "まだレシピが登録されていないよ。レシピのURLを送ってね🍳";
+const OPEN_RECIPE_LABEL: &str = "開く";
+const OPEN_NOTION_LABEL: &str = "Notionで見る";
+/// Status given to recipes the user plans to cook this week.
+const PLANNED_STATUS: &str = "今週作る";
+/// Number of recipes shown by the list command, matching the size of a LINE carousel.
+const LIST_RECIPES_LIMIT: usize = 10;
+/// Number of search results shown, matching the size of a LINE carousel.
+const SEARCH_RESULTS_LIMIT: usize = 10;
+/// Number of recent recipes a random pick is drawn from.
+const RANDOM_RECIPE_POOL: usize = 100;
+/// Number of recent recipes suggestions are chosen from.
+const SUGGESTION_POOL: usize = 100;
+/// Number of suggestions shown, matching the size of a LINE carousel.
+const SUGGESTIONS_LIMIT: usize = 10;
+/// Number of links saved from one message, so the reply is sent before its token expires.
+const MAX_RECIPES_PER_MESSAGE: usize = 5;
 
 #[derive(Clone)]
 pub struct RecipeService {
     recipe_repository: Arc<dyn RecipeRepository + Send + Sync>,
     line_client: Arc<dyn LineClient + Send + Sync>,
     html_client: Arc<dyn HtmlClient + Send + Sync>,
+    postback_codec: PostbackCodec,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Validate)]
 pub struct InsertRecipeRequest {
     #[validate(url)]
     pub recipe_url: String,
+    #[validate(nested)]
+    pub reply_to: ReplyTo,
+}
+
+/// Several links sent in one message, each saved on its own.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Validate)]
+pub struct InsertRecipesRequest {
+    #[validate(length(min = 1))]
+    pub recipe_urls: Vec<String>,
+    #[validate(nested)]
+    pub reply_to: ReplyTo,
+}
+
+/// What happened to a link sent to be saved.
+enum SaveOutcome {
+    Saved(Recipe),
+    Duplicate(Recipe),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Validate)]
+pub struct SearchRecipeRequest {
+    #[validate(length(min = 1))]
+    pub query: String,
+    #[validate(nested)]
+    pub reply_to: ReplyTo,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Validate)]
+pub struct ListRecipesRequest {
+    #[validate(nested)]
+    pub reply_to: ReplyTo,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Validate)]
+pub struct RandomRecipeRequest {
+    #[validate(nested)]
+    pub reply_to: ReplyTo,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Validate)]
+pub struct SuggestRecipesRequest {
+    #[validate(length(min = 1))]
+    pub ingredients: Vec<String>,
+    #[validate(nested)]
+    pub reply_to: ReplyTo,
+}
+
+/// Identifies a recipe in a command, either by its ID or by its name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RecipeRef {
+    Id(ulid::Ulid),
+    Name(String),
+}
+
+impl RecipeRef {
+    pub fn parse(s: &str) -> Self {
+        match ulid::Ulid::from_string(s) {
+            Ok(id) => Self::Id(id),
+            Err(_) => Self::Name(s.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Validate)]
+pub struct DeleteRecipeRequest {
+    pub target: RecipeRef,
+    #[validate(nested)]
+    pub reply_to: ReplyTo,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Validate)]
+pub struct PlanRecipeRequest {
+    pub target: RecipeRef,
+    #[validate(nested)]
+    pub reply_to: ReplyTo,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Validate)]
+pub struct TagRecipeRequest {
+    pub target: RecipeRef,
     #[validate(length(min = 1))]
-    pub reply_token: String,
+    pub tags: Vec<String>,
+    #[validate(nested)]
+    pub reply_to: ReplyTo,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Validate)]
+pub struct RateRecipeRequest {
+    pub target: RecipeRef,
+    #[validate(range(min = 1, max = 5))]
+    pub rating: u8,
+    #[validate(nested)]
+    pub reply_to: ReplyTo,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Validate)]
+pub struct NoteRecipeRequest {
+    pub target: RecipeRef,
+    #[validate(length(min = 1, max = 2000))]
+    pub note: String,
+    #[validate(nested)]
+    pub reply_to: ReplyTo,
 }
 
 impl RecipeService {
@@ -35,76 +164,1018 @@ impl RecipeService {
         recipe_repository: Arc<dyn RecipeRepository + Send + Sync>,
         line_client: Arc<dyn LineClient + Send + Sync>,
         html_client: Arc<dyn HtmlClient + Send + Sync>,
+        postback_codec: PostbackCodec,
     ) -> Self {
         Self {
             recipe_repository,
             line_client,
             html_client,
+            postback_codec,
         }
     }
 
     pub async fn insert_recipe(&self, insert_recipe_request: InsertRecipeRequest) -> Result<()> {
         insert_recipe_request.validate()?;
 
-        let name = self
-            .html_client
-            .get_title(&insert_recipe_request.recipe_url)
+        let recipe = match self.save_recipe(&insert_recipe_request.recipe_url).await? {
+            SaveOutcome::Saved(recipe) => recipe,
+            SaveOutcome::Duplicate(existing) => {
+                return self
+                    .reply_duplicate(&insert_recipe_request.reply_to, &existing)
+                    .await;
+            }
+        };
+
+        self.reply(
+            &insert_recipe_request.reply_to,
+            vec![LineMessage::QuickReply {
+                message: Box::new(LineMessage::Text(format!(
+                    "{INSERT_RECIPE_MESSAGE}\n{}",
+                    recipe.name
+                ))),
+                actions: saved_recipe_actions(&self.postback_codec, recipe.id),
+            }],
+        )
+        .await
+    }
+
+    /// Saves each link on its own, so one failing does not stop the others, and replies once
+    /// with what happened to each of them. When every failure may pass on a retry, the error is
+    /// returned instead so the whole message is retried; links saved by the failed attempt are
+    /// then reported as saved rather than as duplicates.
+    pub async fn insert_recipes(&self, insert_recipes_request: InsertRecipesRequest) -> Result<()> {
+        insert_recipes_request.validate()?;
+        let urls = &insert_recipes_request.recipe_urls;
+
+        let (mut saved, mut duplicates, mut failed) = (0, 0, 0);
+        let mut lines = Vec::new();
+        let mut transient_error = None;
+        let mut all_transient = true;
+        for url in urls.iter().take(MAX_RECIPES_PER_MESSAGE) {
+            match self.save_recipe(url).await {
+                Ok(SaveOutcome::Saved(recipe)) => {
+                    saved += 1;
+                    lines.push(format!("✅ {}", recipe.name));
+                }
+                Ok(SaveOutcome::Duplicate(existing))
+                    if saved_since(&existing, insert_recipes_request.reply_to.received_at) =>
+                {
+                    saved += 1;
+                    lines.push(format!("✅ {}", existing.name));
+                }
+                Ok(SaveOutcome::Duplicate(existing)) => {
+                    duplicates += 1;
+                    lines.push(format!("📖 {}（登録済み）", existing.name));
+                }
+                Err(e) => {
+                    tracing::warn!(%e, url, error_class = e.kind(), "failed to save recipe");
+                    failed += 1;
+                    lines.push(format!("⚠️ {url}\n{}", e.category().message()));
+                    if e.category().is_retryable() {
+                        transient_error.get_or_insert(e);
+                    } else {
+                        all_transient = false;
+                    }
+                }
+            }
+        }
+        if all_transient && let Some(e) = transient_error {
+            return Err(e);
+        }
+
+        let mut text = format!(
+            "{}件のリンクを確認したよ\n登録 {saved}件・登録済み {duplicates}件・失敗 {failed}件\n\n{}",
+            saved + duplicates + failed,
+            lines.join("\n")
+        );
+        if urls.len() > MAX_RECIPES_PER_MESSAGE {
+            text.push_str(&format!(
+                "\n\nリンクは一度に{MAX_RECIPES_PER_MESSAGE}件までだよ。残りの{}件はもう一度送ってね🙏",
+                urls.len() - MAX_RECIPES_PER_MESSAGE
+            ));
+        }
+        self.reply_text(&insert_recipes_request.reply_to, text)
+            .await
+    }
+
+    pub async fn search_recipes(&self, search_recipe_request: SearchRecipeRequest) -> Result<()> {
+        search_recipe_request.validate()?;
+
+        let query = SearchQuery::parse(&search_recipe_request.query);
+        let hits = self
+            .recipe_repository
+            .search_text(&query, SEARCH_RESULTS_LIMIT)
             .await?;
-        let recipe = Recipe::new(name, url::Url::parse(&insert_recipe_request.recipe_url)?);
-        self.recipe_repository.insert_recipe(recipe).await?;
-
-        self.line_client
-            .reply_messages(
-                &insert_recipe_request.reply_token,
-                vec![LineMessage::Text(INSERT_RECIPE_MESSAGE.to_string())],
-            )
+        let message = if hits.is_empty() {
+            LineMessage::Text(format!(
+                "「{}」に一致するレシピは見つからなかったよ🔍",
+                search_recipe_request.query
+            ))
+        } else {
+            LineMessage::FlexCarousel {
+                alt_text: format!("「{}」の検索結果", search_recipe_request.query),
+                bubbles: hits.into_iter().map(search_card).collect(),
+            }
+        };
+
+        self.reply(&search_recipe_request.reply_to, vec![message])
             .await
-            .with_context(|| "failed to reply message")?;
+    }
+
+    pub async fn list_recipes(&self, list_recipes_request: ListRecipesRequest) -> Result<()> {
+        list_recipes_request.validate()?;
+
+        let recipes = self
+            .recipe_repository
+            .list_recipes(LIST_RECIPES_LIMIT)
+            .await?;
+        let message = if recipes.is_empty() {
+            LineMessage::Text(NO_RECIPES_MESSAGE.to_string())
+        } else {
+            recipe_cards("最近のレシピ".to_string(), &recipes)
+        };
+
+        self.reply(&list_recipes_request.reply_to, vec![message])
+            .await
+    }
+
+    pub async fn random_recipe(&self, random_recipe_request: RandomRecipeRequest) -> Result<()> {
+        random_recipe_request.validate()?;
 
+        let recipes = self
+            .recipe_repository
+            .list_recipes(RANDOM_RECIPE_POOL)
+            .await?;
+        let message = match recipes.choose(&mut rand::rng()) {
+            Some(recipe) => LineMessage::FlexBubble {
+                alt_text: format!("今日は「{}」はどう？", recipe.name),
+                bubble: recipe_card(recipe),
+            },
+            None => LineMessage::Text(NO_RECIPES_MESSAGE.to_string()),
+        };
+
+        self.reply(&random_recipe_request.reply_to, vec![message])
+            .await
+    }
+
+    pub async fn suggest_recipes(
+        &self,
+        suggest_recipes_request: SuggestRecipesRequest,
+    ) -> Result<()> {
+        suggest_recipes_request.validate()?;
+
+        let recipes = self.recipe_repository.list_recipes(SUGGESTION_POOL).await?;
+        let ingredients = suggest_recipes_request.ingredients.join("、");
+        let suggestions = suggest(
+            &suggest_recipes_request.ingredients,
+            recipes,
+            SUGGESTIONS_LIMIT,
+        );
+        let message = if suggestions.is_empty() {
+            LineMessage::Text(format!(
+                "「{ingredients}」を使うレシピは見つからなかったよ🔍"
+            ))
+        } else {
+            LineMessage::FlexCarousel {
+                alt_text: format!("「{ingredients}」で作れるレシピ"),
+                bubbles: suggestions.into_iter().map(suggestion_card).collect(),
+            }
+        };
+
+        self.reply(&suggest_recipes_request.reply_to, vec![message])
+            .await
+    }
+
+    pub async fn delete_recipe(&self, delete_recipe_request: DeleteRecipeRequest) -> Result<()> {
+        delete_recipe_request.validate()?;
+        let reply_to = &delete_recipe_request.reply_to;
+
+        let Some(recipe) = self
+            .find_target(&delete_recipe_request.target, reply_to)
+            .await?
+        else {
+            return Ok(());
+        };
+        self.recipe_repository.delete_recipe(recipe.id).await?;
+
+        self.reply_text(reply_to, format!("「{}」を削除したよ🗑️", recipe.name))
+            .await
+    }
+
+    pub async fn plan_recipe(&self, plan_recipe_request: PlanRecipeRequest) -> Result<()> {
+        plan_recipe_request.validate()?;
+        let reply_to = &plan_recipe_request.reply_to;
+        if !self.check_stored(RecipeField::Status, reply_to).await? {
+            return Ok(());
+        }
+
+        let Some(mut recipe) = self
+            .find_target(&plan_recipe_request.target, reply_to)
+            .await?
+        else {
+            return Ok(());
+        };
+        recipe.status = Some(PLANNED_STATUS.to_string());
+        let recipe = self.recipe_repository.update_recipe(recipe).await?;
+
+        self.reply_text(
+            reply_to,
+            format!("「{}」を{PLANNED_STATUS}リストに入れたよ📅", recipe.name),
+        )
+        .await
+    }
+
+    pub async fn tag_recipe(&self, tag_recipe_request: TagRecipeRequest) -> Result<()> {
+        tag_recipe_request.validate()?;
+        let reply_to = &tag_recipe_request.reply_to;
+        if !self.check_stored(RecipeField::Tags, reply_to).await? {
+            return Ok(());
+        }
+
+        let Some(mut recipe) = self
+            .find_target(&tag_recipe_request.target, reply_to)
+            .await?
+        else {
+            return Ok(());
+        };
+        recipe.add_tags(tag_recipe_request.tags);
+        recipe.validate()?;
+        let recipe = self.recipe_repository.update_recipe(recipe).await?;
+
+        self.reply_text(
+            reply_to,
+            format!(
+                "「{}」のタグを更新したよ🏷️\n{}",
+                recipe.name,
+                recipe.tags.join(" ")
+            ),
+        )
+        .await
+    }
+
+    pub async fn rate_recipe(&self, rate_recipe_request: RateRecipeRequest) -> Result<()> {
+        rate_recipe_request.validate()?;
+        let reply_to = &rate_recipe_request.reply_to;
+        if !self.check_stored(RecipeField::Rating, reply_to).await? {
+            return Ok(());
+        }
+
+        let Some(mut recipe) = self
+            .find_target(&rate_recipe_request.target, reply_to)
+            .await?
+        else {
+            return Ok(());
+        };
+        recipe.rating = Some(rate_recipe_request.rating);
+        let recipe = self.recipe_repository.update_recipe(recipe).await?;
+
+        self.reply_text(
+            reply_to,
+            format!(
+                "「{}」を{}にしたよ",
+                recipe.name,
+                "★".repeat(rate_recipe_request.rating.into())
+            ),
+        )
+        .await
+    }
+
+    pub async fn note_recipe(&self, note_recipe_request: NoteRecipeRequest) -> Result<()> {
+        note_recipe_request.validate()?;
+        let reply_to = &note_recipe_request.reply_to;
+
+        let Some(recipe) = self
+            .find_target(&note_recipe_request.target, reply_to)
+            .await?
+        else {
+            return Ok(());
+        };
+        self.recipe_repository
+            .add_note(recipe.id, &note_recipe_request.note)
+            .await?;
+
+        self.reply_text(reply_to, format!("「{}」にメモを追加したよ📝", recipe.name))
+            .await
+    }
+
+    /// Resolves the recipe a command refers to.
+    ///
+    /// When there is no single match, the user is told so and `None` is returned.
+    async fn find_target(&self, target: &RecipeRef, reply_to: &ReplyTo) -> Result<Option<Recipe>> {
+        let mut candidates = match target {
+            RecipeRef::Id(id) => self
+                .recipe_repository
+                .get_recipe(*id)
+                .await?
+                .into_iter()
+                .collect(),
+            RecipeRef::Name(name) => self.recipe_repository.search(name).await?,
+        };
+        if let RecipeRef::Name(name) = target
+            && let Some(exact) = candidates.iter().position(|r| r.name == *name)
+        {
+            return Ok(Some(candidates.swap_remove(exact)));
+        }
+
+        match candidates.len() {
+            0 => {
+                self.reply_text(reply_to, RECIPE_NOT_FOUND_MESSAGE.to_string())
+                    .await?;
+                Ok(None)
+            }
+            1 => Ok(candidates.pop()),
+            _ => {
+                self.reply(
+                    reply_to,
+                    vec![
+                        LineMessage::Text(AMBIGUOUS_RECIPE_MESSAGE.to_string()),
+                        recipe_cards(AMBIGUOUS_RECIPE_MESSAGE.to_string(), &candidates),
+                    ],
+                )
+                .await?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Saves the recipe a link points to, unless it is already saved under the same URL.
+    async fn save_recipe(&self, recipe_url: &str) -> Result<SaveOutcome> {
+        let request_url = url::Url::parse(recipe_url)?;
+
+        // Check the cleaned-up URL first so already saved links are not fetched again.
+        if let Some(existing) = self
+            .recipe_repository
+            .find_by_url(&canonicalize_url(&request_url))
+            .await?
+        {
+            return Ok(SaveOutcome::Duplicate(existing));
+        }
+
+        let scraped = self
+            .html_client
+            .get_recipe(recipe_url)
+            .await
+            .map_err(|e| Error::RecipeFetchError(Box::new(e)))?;
+        let recipe_url = resolve_canonical_url(&request_url, scraped.canonical_url.as_deref());
+        if recipe_url != canonicalize_url(&request_url)
+            && let Some(existing) = self.recipe_repository.find_by_url(&recipe_url).await?
+        {
+            return Ok(SaveOutcome::Duplicate(existing));
+        }
+
+        let recipe = recipe_from_scraped(recipe_url, scraped)?;
+        let recipe = self.recipe_repository.insert_recipe(recipe).await?;
+        Ok(SaveOutcome::Saved(recipe))
+    }
+
+    /// Replies that the field cannot be saved when the repository would drop it, since the
+    /// update would otherwise look successful. Returns whether the field is stored.
+    async fn check_stored(&self, field: RecipeField, reply_to: &ReplyTo) -> Result<bool> {
+        if self.recipe_repository.stores(field) {
+            return Ok(true);
+        }
+        self.reply_text(
+            reply_to,
+            format!(
+                "{}を保存するプロパティが設定されていないよ⚙️\n`notion_properties.{}` を設定してね",
+                field.label(),
+                field.key()
+            ),
+        )
+        .await?;
+        Ok(false)
+    }
+
+    async fn reply_text(&self, reply_to: &ReplyTo, text: String) -> Result<()> {
+        self.reply(reply_to, vec![LineMessage::Text(text)]).await
+    }
+
+    async fn reply(&self, reply_to: &ReplyTo, messages: Vec<LineMessage>) -> Result<()> {
+        respond(self.line_client.as_ref(), reply_to, messages)
+            .await
+            .with_context(|| "failed to reply message")?;
         Ok(())
     }
+
+    async fn reply_duplicate(&self, reply_to: &ReplyTo, existing: &Recipe) -> Result<()> {
+        let link = existing.page_url.as_ref().unwrap_or(&existing.recipe_url);
+        self.reply_text(
+            reply_to,
+            format!("{DUPLICATE_RECIPE_MESSAGE}\n{}\n{link}", existing.name),
+        )
+        .await
+    }
+}
+
+fn recipe_cards(alt_text: String, recipes: &[Recipe]) -> LineMessage {
+    LineMessage::FlexCarousel {
+        alt_text,
+        bubbles: recipes.iter().map(recipe_card).collect(),
+    }
+}
+
+/// A card showing the recipe photo, name, description and cook time, linking to the recipe
+/// and its Notion page.
+pub(crate) fn recipe_card(recipe: &Recipe) -> FlexBubble {
+    let mut details = Vec::new();
+    if let Some(time) = recipe.total_time.or(recipe.cook_time) {
+        details.push(format!("⏱ {}分", time.as_secs() / 60));
+    }
+    if !recipe.tags.is_empty() {
+        details.push(recipe.tags.join(" "));
+    }
+    if let Some(source) = &recipe.source {
+        details.push(source.clone());
+    }
+
+    let mut actions = vec![LineAction::Uri {
+        label: OPEN_RECIPE_LABEL.to_string(),
+        uri: recipe.recipe_url.to_string(),
+    }];
+    if let Some(page_url) = &recipe.page_url {
+        actions.push(LineAction::Uri {
+            label: OPEN_NOTION_LABEL.to_string(),
+            uri: page_url.to_string(),
+        });
+    }
+
+    FlexBubble {
+        // LINE only loads images over HTTPS.
+        image_url: recipe
+            .image_url
+            .as_ref()
+            .filter(|url| url.scheme() == "https")
+            .map(ToString::to_string),
+        title: recipe.name.clone(),
+        description: recipe.description.clone(),
+        details,
+        actions,
+        highlights: Vec::new(),
+    }
+}
+
+/// A recipe card highlighting what the search matched, with the matched ingredient or step
+/// first among the details.
+fn search_card(hit: SearchHit) -> FlexBubble {
+    let mut bubble = recipe_card(&hit.recipe);
+    if let Some(snippet) = hit.snippet {
+        bubble.details.insert(0, format!("🔍 {snippet}"));
+    }
+    bubble.highlights = hit.highlights;
+    bubble
+}
+
+/// A recipe card listing the ingredients still missing first among the details.
+fn suggestion_card(suggestion: Suggestion) -> FlexBubble {
+    let mut bubble = recipe_card(&suggestion.recipe);
+    let missing = if suggestion.missing.is_empty() {
+        "🛒 材料はそろってるよ".to_string()
+    } else {
+        format!("🛒 足りない: {}", suggestion.missing.join("、"))
+    };
+    bubble.details.splice(
+        0..0,
+        [format!("✅ {}", suggestion.have.join("、")), missing],
+    );
+    bubble.highlights = suggestion.have;
+    bubble
+}
+
+/// Whether a recipe was saved after a message arrived, i.e. by an earlier attempt of the job
+/// handling it. Notion rounds creation times down to the minute, so the minute the message
+/// arrived in counts too.
+fn saved_since(recipe: &Recipe, received_at: DateTime<Utc>) -> bool {
+    received_at
+        .duration_trunc(TimeDelta::minutes(1))
+        .is_ok_and(|minute| recipe.created_at >= minute)
+}
+
+/// Builds a recipe from a scraped page. Fields the page got wrong, such as an empty ingredient
+/// or an unlikely yield, are dropped rather than failing the save.
+fn recipe_from_scraped(recipe_url: url::Url, scraped: ScrapedRecipe) -> Result<Recipe> {
+    let non_empty = |items: Vec<String>| {
+        items
+            .into_iter()
+            .filter(|item| !item.trim().is_empty())
+            .collect::<Vec<_>>()
+    };
+    let details = RecipeDetails {
+        ingredients: scraped
+            .ingredients
+            .iter()
+            .map(|line| Ingredient::parse(line))
+            .filter(|ingredient| !ingredient.name.trim().is_empty())
+            .collect(),
+        steps: non_empty(scraped.instructions),
+        servings: scraped
+            .recipe_yield
+            .as_deref()
+            .and_then(parse_servings)
+            .filter(|servings| (1..=MAX_SERVINGS).contains(servings)),
+        prep_time: scraped.prep_time,
+        cook_time: scraped.cook_time,
+        total_time: scraped.total_time,
+        image_url: scraped.image.and_then(|image| recipe_url.join(&image).ok()),
+        description: scraped.description,
+        source: recipe_url
+            .host_str()
+            .map(|host| host.trim_start_matches("www.").to_string()),
+        tags: non_empty(scraped.keywords),
+    };
+    Recipe::with_details(scraped.name, recipe_url, details)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::infra::{
-        html::MockHtmlClient, line::MockLineClient, repository::recipe::MockRecipeRepository,
+    use crate::{
+        error::ErrorCategory,
+        infra::{
+            html::{MockHtmlClient, ScrapedRecipe},
+            line::MockLineClient,
+            repository::recipe::MockRecipeRepository,
+        },
     };
 
     use super::*;
 
+    fn recipe_service(
+        html_client: MockHtmlClient,
+        recipe_repository: MockRecipeRepository,
+        line_client: MockLineClient,
+    ) -> RecipeService {
+        RecipeService::new(
+            Arc::new(recipe_repository),
+            Arc::new(line_client),
+            Arc::new(html_client),
+            PostbackCodec::new("channel_secret"),
+        )
+    }
+
     #[tokio::test]
     async fn test_insert_recipe() {
         let mut html_client = MockHtmlClient::new();
         html_client
-            .expect_get_title()
+            .expect_get_recipe()
             .times(1)
-            .returning(|_| Ok("test".to_string()));
+            .returning(|_| Ok(ScrapedRecipe::from_name("test")));
 
         let mut recipe_repository = MockRecipeRepository::new();
+        recipe_repository
+            .expect_find_by_url()
+            .times(1)
+            .returning(|_| Ok(None));
         recipe_repository
             .expect_insert_recipe()
             .times(1)
-            .returning(|_| Ok(()));
+            .returning(Ok);
 
         let mut line_client = MockLineClient::new();
         line_client
             .expect_reply_messages()
+            .withf(|_, messages| {
+                matches!(
+                    messages.as_slice(),
+                    [LineMessage::QuickReply { actions, .. }] if actions.len() == 4
+                )
+            })
             .times(1)
             .returning(|_, _| Ok(()));
 
-        let recipe_service = RecipeService::new(
-            Arc::new(recipe_repository),
-            Arc::new(line_client),
-            Arc::new(html_client),
-        );
-
         let request = InsertRecipeRequest {
             recipe_url: "https://example.com".to_string(),
-            reply_token: "reply_token".to_string(),
+            reply_to: ReplyTo::new("reply_token"),
+        };
+
+        let result = recipe_service(html_client, recipe_repository, line_client)
+            .insert_recipe(request)
+            .await;
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_recipe_from_scraped_drops_invalid_fields() {
+        let scraped = ScrapedRecipe {
+            ingredients: vec!["鶏もも肉 300g".to_string(), " ".to_string()],
+            instructions: vec!["焼く".to_string(), String::new()],
+            recipe_yield: Some("120人分".to_string()),
+            keywords: vec![String::new(), "和食".to_string()],
+            ..ScrapedRecipe::from_name("鶏の照り焼き")
+        };
+
+        let recipe = recipe_from_scraped(
+            url::Url::parse("https://example.com/recipe/1").unwrap(),
+            scraped,
+        )
+        .unwrap();
+        assert_eq!(recipe.ingredients, [Ingredient::parse("鶏もも肉 300g")]);
+        assert_eq!(recipe.steps, ["焼く"]);
+        assert_eq!(recipe.servings, None);
+        assert_eq!(recipe.tags, ["和食"]);
+    }
+
+    #[tokio::test]
+    async fn test_insert_recipe_duplicate_canonical_url() {
+        let mut html_client = MockHtmlClient::new();
+        html_client.expect_get_recipe().times(1).returning(|_| {
+            Ok(ScrapedRecipe {
+                canonical_url: Some("https://example.com/recipe/1".to_string()),
+                ..ScrapedRecipe::from_name("test")
+            })
+        });
+
+        let mut recipe_repository = MockRecipeRepository::new();
+        recipe_repository
+            .expect_find_by_url()
+            .with(eq(
+                url::Url::parse("https://example.com/recipe/1?id=1").unwrap()
+            ))
+            .times(1)
+            .returning(|_| Ok(None));
+        recipe_repository
+            .expect_find_by_url()
+            .with(eq(url::Url::parse("https://example.com/recipe/1").unwrap()))
+            .times(1)
+            .returning(|url| Ok(Some(Recipe::new("test".to_string(), url.clone()))));
+        recipe_repository.expect_insert_recipe().never();
+
+        let mut line_client = MockLineClient::new();
+        line_client
+            .expect_reply_messages()
+            .withf(|_, messages| {
+                matches!(
+                    messages.as_slice(),
+                    [LineMessage::Text(text)] if text.starts_with(DUPLICATE_RECIPE_MESSAGE)
+                )
+            })
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        let request = InsertRecipeRequest {
+            recipe_url: "https://example.com/recipe/1?id=1&utm_source=line".to_string(),
+            reply_to: ReplyTo::new("reply_token"),
+        };
+
+        let result = recipe_service(html_client, recipe_repository, line_client)
+            .insert_recipe(request)
+            .await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_insert_recipes_summarizes_each_link() {
+        let mut html_client = MockHtmlClient::new();
+        html_client.expect_get_recipe().returning(|url| match url {
+            "https://a.example.com/1" => Ok(ScrapedRecipe::from_name("鶏の照り焼き")),
+            _ => Err(anyhow::anyhow!("no recipe found in {url}").into()),
+        });
+
+        let mut recipe_repository = MockRecipeRepository::new();
+        recipe_repository.expect_find_by_url().returning(|url| {
+            Ok((url.host_str() == Some("b.example.com")).then(|| Recipe {
+                created_at: Utc::now() - TimeDelta::days(1),
+                ..Recipe::new("肉じゃが".to_string(), url.clone())
+            }))
+        });
+        recipe_repository
+            .expect_insert_recipe()
+            .times(1)
+            .returning(Ok);
+
+        let mut line_client = MockLineClient::new();
+        line_client
+            .expect_reply_messages()
+            .withf(|_, messages| {
+                matches!(
+                    messages.as_slice(),
+                    [LineMessage::Text(text)]
+                        if text.contains("登録 1件・登録済み 1件・失敗 1件")
+                            && text.contains("✅ 鶏の照り焼き")
+                            && text.contains("📖 肉じゃが（登録済み）")
+                            && text.contains("⚠️ https://c.example.com/3")
+                )
+            })
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        let request = InsertRecipesRequest {
+            recipe_urls: vec![
+                "https://a.example.com/1".to_string(),
+                "https://b.example.com/2".to_string(),
+                "https://c.example.com/3".to_string(),
+            ],
+            reply_to: ReplyTo::new("reply_token"),
+        };
+
+        let result = recipe_service(html_client, recipe_repository, line_client)
+            .insert_recipes(request)
+            .await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_insert_recipes_retries_transient_failures() {
+        let mut html_client = MockHtmlClient::new();
+        html_client
+            .expect_get_recipe()
+            .returning(|url| Ok(ScrapedRecipe::from_name(url)));
+
+        let mut recipe_repository = MockRecipeRepository::new();
+        recipe_repository
+            .expect_find_by_url()
+            .returning(|_| Ok(None));
+        recipe_repository
+            .expect_insert_recipe()
+            .returning(|recipe| match recipe.recipe_url.host_str() {
+                Some("a.example.com") => Ok(recipe),
+                _ => Err(Error::Generic("connection reset".to_string())),
+            });
+
+        let mut line_client = MockLineClient::new();
+        line_client.expect_reply_messages().times(0);
+
+        let request = InsertRecipesRequest {
+            recipe_urls: vec![
+                "https://a.example.com/1".to_string(),
+                "https://b.example.com/2".to_string(),
+            ],
+            reply_to: ReplyTo::new("reply_token"),
+        };
+
+        let result = recipe_service(html_client, recipe_repository, line_client)
+            .insert_recipes(request)
+            .await;
+        assert!(matches!(result, Err(e) if e.category() == ErrorCategory::Internal));
+    }
+
+    #[tokio::test]
+    async fn test_insert_recipes_reports_links_saved_by_a_failed_attempt_as_saved() {
+        let html_client = MockHtmlClient::new();
+
+        let reply_to = ReplyTo {
+            received_at: Utc::now() - TimeDelta::minutes(5),
+            ..ReplyTo::new("reply_token")
+        };
+        let mut recipe_repository = MockRecipeRepository::new();
+        recipe_repository.expect_find_by_url().returning(|url| {
+            Ok(Some(match url.host_str() {
+                Some("a.example.com") => Recipe::new("鶏の照り焼き".to_string(), url.clone()),
+                _ => Recipe {
+                    created_at: Utc::now() - TimeDelta::days(1),
+                    ..Recipe::new("肉じゃが".to_string(), url.clone())
+                },
+            }))
+        });
+
+        let mut line_client = MockLineClient::new();
+        line_client
+            .expect_reply_messages()
+            .withf(|_, messages| {
+                matches!(
+                    messages.as_slice(),
+                    [LineMessage::Text(text)]
+                        if text.contains("登録 1件・登録済み 1件・失敗 0件")
+                            && text.contains("✅ 鶏の照り焼き")
+                            && text.contains("📖 肉じゃが（登録済み）")
+                )
+            })
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        let request = InsertRecipesRequest {
+            recipe_urls: vec![
+                "https://a.example.com/1".to_string(),
+                "https://b.example.com/2".to_string(),
+            ],
+            reply_to,
+        };
+
+        let result = recipe_service(html_client, recipe_repository, line_client)
+            .insert_recipes(request)
+            .await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_search_recipes() {
+        let mut recipe_repository = MockRecipeRepository::new();
+        recipe_repository
+            .expect_search_text()
+            .withf(|query, limit| query.text == "鶏肉" && *limit == SEARCH_RESULTS_LIMIT)
+            .times(1)
+            .returning(|_, _| {
+                Ok(vec![SearchHit {
+                    recipe: Recipe::new(
+                        "親子丼".to_string(),
+                        url::Url::parse("https://example.com/recipe/1").unwrap(),
+                    ),
+                    score: 3,
+                    snippet: Some("鶏肉 300g".to_string()),
+                    highlights: vec!["鶏肉".to_string()],
+                }])
+            });
+
+        let mut line_client = MockLineClient::new();
+        line_client
+            .expect_reply_messages()
+            .withf(|_, messages| {
+                matches!(
+                    messages.as_slice(),
+                    [LineMessage::FlexCarousel { bubbles, .. }]
+                        if bubbles.len() == 1
+                            && bubbles[0].details[0] == "🔍 鶏肉 300g"
+                            && bubbles[0].highlights == ["鶏肉"]
+                )
+            })
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        let request = SearchRecipeRequest {
+            query: "鶏肉".to_string(),
+            reply_to: ReplyTo::new("reply_token"),
+        };
+
+        let result = recipe_service(MockHtmlClient::new(), recipe_repository, line_client)
+            .search_recipes(request)
+            .await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_suggest_recipes() {
+        let mut recipe_repository = MockRecipeRepository::new();
+        recipe_repository
+            .expect_list_recipes()
+            .with(eq(SUGGESTION_POOL))
+            .times(1)
+            .returning(|_| {
+                Ok(vec![
+                    Recipe {
+                        ingredients: vec![
+                            Ingredient::parse("鶏もも肉 300g"),
+                            Ingredient::parse("玉ねぎ 1/2個"),
+                            Ingredient::parse("卵 2個"),
+                        ],
+                        ..recipe("親子丼", "1")
+                    },
+                    Recipe {
+                        ingredients: vec![Ingredient::parse("キャベツ 1/4個")],
+                        ..recipe("コールスロー", "2")
+                    },
+                ])
+            });
+
+        let mut line_client = MockLineClient::new();
+        line_client
+            .expect_reply_messages()
+            .withf(|_, messages| {
+                matches!(
+                    messages.as_slice(),
+                    [LineMessage::FlexCarousel { bubbles, .. }]
+                        if bubbles.len() == 1
+                            && bubbles[0].details[..2] == ["✅ 鶏もも肉、玉ねぎ", "🛒 足りない: 卵"]
+                )
+            })
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        let request = SuggestRecipesRequest {
+            ingredients: vec!["鶏肉".to_string(), "玉ねぎ".to_string()],
+            reply_to: ReplyTo::new("reply_token"),
         };
 
-        let result = recipe_service.insert_recipe(request).await;
+        let result = recipe_service(MockHtmlClient::new(), recipe_repository, line_client)
+            .suggest_recipes(request)
+            .await;
         assert!(result.is_ok());
     }
+
+    fn recipe(name: &str, path: &str) -> Recipe {
+        Recipe::new(
+            name.to_string(),
+            url::Url::parse(&format!("https://example.com/{path}")).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_rate_recipe_prefers_exact_name() {
+        let mut recipe_repository = MockRecipeRepository::new();
+        recipe_repository
+            .expect_search()
+            .with(eq("カレー"))
+            .times(1)
+            .returning(|_| Ok(vec![recipe("キーマカレー", "1"), recipe("カレー", "2")]));
+        recipe_repository.expect_stores().return_const(true);
+        recipe_repository
+            .expect_update_recipe()
+            .withf(|recipe| recipe.name == "カレー" && recipe.rating == Some(4))
+            .times(1)
+            .returning(Ok);
+
+        let mut line_client = MockLineClient::new();
+        line_client
+            .expect_reply_messages()
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        let request = RateRecipeRequest {
+            target: RecipeRef::Name("カレー".to_string()),
+            rating: 4,
+            reply_to: ReplyTo::new("reply_token"),
+        };
+
+        let result = recipe_service(MockHtmlClient::new(), recipe_repository, line_client)
+            .rate_recipe(request)
+            .await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_tag_recipe_without_tags_property() {
+        let mut recipe_repository = MockRecipeRepository::new();
+        recipe_repository
+            .expect_stores()
+            .with(eq(RecipeField::Tags))
+            .return_const(false);
+        recipe_repository.expect_search().never();
+        recipe_repository.expect_update_recipe().never();
+
+        let mut line_client = MockLineClient::new();
+        line_client
+            .expect_reply_messages()
+            .withf(|_, messages| {
+                matches!(
+                    messages.as_slice(),
+                    [LineMessage::Text(text)] if text.contains("notion_properties.tags")
+                )
+            })
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        let request = TagRecipeRequest {
+            target: RecipeRef::Name("カレー".to_string()),
+            tags: vec!["和食".to_string()],
+            reply_to: ReplyTo::new("reply_token"),
+        };
+
+        let result = recipe_service(MockHtmlClient::new(), recipe_repository, line_client)
+            .tag_recipe(request)
+            .await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_delete_recipe_ambiguous() {
+        let mut recipe_repository = MockRecipeRepository::new();
+        recipe_repository.expect_search().times(1).returning(|_| {
+            Ok(vec![
+                recipe("キーマカレー", "1"),
+                recipe("バターチキンカレー", "2"),
+            ])
+        });
+        recipe_repository.expect_delete_recipe().never();
+
+        let mut line_client = MockLineClient::new();
+        line_client
+            .expect_reply_messages()
+            .withf(|_, messages| {
+                matches!(
+                    messages.as_slice(),
+                    [LineMessage::Text(text), LineMessage::FlexCarousel { bubbles, .. }]
+                        if text == AMBIGUOUS_RECIPE_MESSAGE && bubbles.len() == 2
+                )
+            })
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        let request = DeleteRecipeRequest {
+            target: RecipeRef::Name("カレー".to_string()),
+            reply_to: ReplyTo::new("reply_token"),
+        };
+
+        let result = recipe_service(MockHtmlClient::new(), recipe_repository, line_client)
+            .delete_recipe(request)
+            .await;
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_recipe_card() {
+        let recipe = Recipe {
+            image_url: Some(url::Url::parse("http://example.com/image.jpg").unwrap()),
+            description: Some("甘辛いたれの定番おかず".to_string()),
+            cook_time: Some(std::time::Duration::from_secs(20 * 60)),
+            page_url: Some(url::Url::parse("https://www.notion.so/page").unwrap()),
+            ..recipe("鶏の照り焼き", "recipe/1")
+        };
+
+        let card = recipe_card(&recipe);
+        assert_eq!(card.image_url, None);
+        assert_eq!(card.description.as_deref(), Some("甘辛いたれの定番おかず"));
+        assert_eq!(card.details, vec!["⏱ 20分".to_string()]);
+        assert!(matches!(
+            card.actions.as_slice(),
+            [LineAction::Uri { label: open, .. }, LineAction::Uri { label: notion, uri }]
+                if open == OPEN_RECIPE_LABEL && notion == OPEN_NOTION_LABEL && uri == "https://www.notion.so/page"
+        ));
+    }
 }
@@ -7,9 +7,271 @@ pub struct AppConfig {
     pub debug: bool,
     pub line_channel_access_token: String,
     pub line_channel_secret: String,
+    /// Only needed with the `notion` repository backend.
+    #[serde(default)]
     pub notion_integration_token: String,
+    #[serde(default)]
     pub notion_database_id: String,
+    #[serde(default)]
+    pub notion_properties: NotionPropertyMapping,
+    /// Creates properties missing from the Notion database at startup instead of failing.
+    #[serde(default)]
+    pub notion_bootstrap_schema: bool,
     pub port: u16,
+    #[serde(default)]
+    pub jobs: JobConfig,
+    #[serde(default)]
+    pub dedup: DedupConfig,
+    #[serde(default)]
+    pub repository: RepositoryConfig,
+    #[serde(default)]
+    pub sync: SyncConfig,
+    #[serde(default)]
+    pub fetch: FetchConfig,
+    /// Bearer token for the `/admin` endpoints, which are disabled when unset.
+    #[serde(default)]
+    pub admin_token: Option<String>,
+}
+
+/// Where recipes are stored.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct RepositoryConfig {
+    pub backend: RepositoryBackend,
+    /// SQLite file used by the `sqlite` backend.
+    pub database_path: String,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RepositoryBackend {
+    /// The Notion database set by `notion_database_id`.
+    Notion,
+    Sqlite,
+    /// Recipes are lost when the process exits.
+    Memory,
+}
+
+impl Default for RepositoryConfig {
+    fn default() -> Self {
+        Self {
+            backend: RepositoryBackend::Notion,
+            database_path: "recipena.sqlite3".to_string(),
+        }
+    }
+}
+
+/// Settings of the local mirror of the Notion database.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct SyncConfig {
+    /// Answers reads from the mirror. Only used with the `notion` repository backend.
+    pub enabled: bool,
+    /// SQLite file holding the mirror and the writes not pushed to Notion yet. It must differ
+    /// from `repository.database_path`, as full syncs drop recipes missing from Notion.
+    pub database_path: String,
+    /// Time between two syncs. Local writes are pushed right away as well.
+    pub interval_secs: u64,
+    /// Time between two full syncs, which also notice recipes deleted in Notion.
+    pub full_sync_interval_secs: u64,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            database_path: "recipena-mirror.sqlite3".to_string(),
+            interval_secs: 5 * 60,
+            full_sync_interval_secs: 24 * 60 * 60,
+        }
+    }
+}
+
+/// Settings of the background workers processing webhook events.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct JobConfig {
+    pub backend: JobBackend,
+    /// SQLite file used by the `sqlite` backend.
+    pub database_path: String,
+    /// Number of jobs processed at the same time.
+    pub concurrency: usize,
+    /// Jobs running longer than this are cancelled.
+    pub timeout_secs: u64,
+    /// Maximum number of waiting jobs. Webhook requests fail while the queue is full.
+    pub queue_capacity: usize,
+    /// Time given to queued and running jobs to finish on shutdown.
+    pub shutdown_timeout_secs: u64,
+    /// Number of attempts before a job is moved to the dead letters.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled on every further failure.
+    pub retry_base_secs: u64,
+    pub retry_max_secs: u64,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobBackend {
+    /// Jobs are lost when the process exits.
+    Memory,
+    Sqlite,
+}
+
+impl Default for JobConfig {
+    fn default() -> Self {
+        Self {
+            backend: JobBackend::Sqlite,
+            database_path: "recipena.sqlite3".to_string(),
+            concurrency: 4,
+            timeout_secs: 60,
+            queue_capacity: 1000,
+            shutdown_timeout_secs: 30,
+            max_attempts: 5,
+            retry_base_secs: 5,
+            retry_max_secs: 600,
+        }
+    }
+}
+
+/// Settings of the store skipping webhook events LINE delivered more than once.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct DedupConfig {
+    pub backend: DedupBackend,
+    /// SQLite file used by the `sqlite` backend.
+    pub database_path: String,
+    /// How long an event ID is remembered. LINE stops redelivering well within a day.
+    pub ttl_secs: u64,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DedupBackend {
+    /// Event IDs are forgotten when the process exits.
+    Memory,
+    Sqlite,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        Self {
+            backend: DedupBackend::Memory,
+            database_path: "recipena.sqlite3".to_string(),
+            ttl_secs: 24 * 60 * 60,
+        }
+    }
+}
+
+/// Limits applied when downloading the recipe pages sent by users.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct FetchConfig {
+    pub user_agent: String,
+    pub connect_timeout_secs: u64,
+    /// Time allowed for the whole request, including redirects and the body.
+    pub timeout_secs: u64,
+    pub max_redirects: usize,
+    /// Pages larger than this are rejected while they are downloaded.
+    pub max_body_bytes: usize,
+    /// Allows private, loopback and link-local addresses. Only meant for local testing.
+    pub allow_private_addresses: bool,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        Self {
+            user_agent: format!("recipena/{}", env!("CARGO_PKG_VERSION")),
+            connect_timeout_secs: 5,
+            timeout_secs: 15,
+            max_redirects: 5,
+            max_body_bytes: 5 * 1024 * 1024,
+            allow_private_addresses: false,
+        }
+    }
+}
+
+/// Maps recipe fields to the properties of the Notion database.
+///
+/// Optional fields are only written when they are mapped.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct NotionPropertyMapping {
+    pub title: NotionProperty,
+    pub url: NotionProperty,
+    pub tags: Option<NotionProperty>,
+    pub servings: Option<NotionProperty>,
+    pub cook_time: Option<NotionProperty>,
+    pub image: Option<NotionProperty>,
+    pub description: Option<NotionProperty>,
+    pub source: Option<NotionProperty>,
+    pub rating: Option<NotionProperty>,
+    pub status: Option<NotionProperty>,
+}
+
+impl Default for NotionPropertyMapping {
+    fn default() -> Self {
+        Self {
+            title: NotionProperty::new("Name", NotionPropertyType::Title),
+            url: NotionProperty::new("リンク", NotionPropertyType::Url),
+            tags: None,
+            servings: None,
+            cook_time: None,
+            image: None,
+            description: None,
+            source: None,
+            rating: None,
+            status: None,
+        }
+    }
+}
+
+impl NotionPropertyMapping {
+    /// Returns every mapped property along with the recipe field it stores.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &NotionProperty)> {
+        [
+            ("title", Some(&self.title)),
+            ("url", Some(&self.url)),
+            ("tags", self.tags.as_ref()),
+            ("servings", self.servings.as_ref()),
+            ("cook_time", self.cook_time.as_ref()),
+            ("image", self.image.as_ref()),
+            ("description", self.description.as_ref()),
+            ("source", self.source.as_ref()),
+            ("rating", self.rating.as_ref()),
+            ("status", self.status.as_ref()),
+        ]
+        .into_iter()
+        .filter_map(|(field, property)| property.map(|p| (field, p)))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct NotionProperty {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub property_type: NotionPropertyType,
+}
+
+impl NotionProperty {
+    pub fn new(name: &str, property_type: NotionPropertyType) -> Self {
+        Self {
+            name: name.to_string(),
+            property_type,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum NotionPropertyType {
+    Title,
+    RichText,
+    Url,
+    Number,
+    Select,
+    MultiSelect,
+    Status,
+    Files,
 }
 
 const CONFIG_FILE_NAME: &str = ".recipena";
@@ -0,0 +1,142 @@
+/// Query parameters added by ads and share buttons that don't change the page content.
+const TRACKING_PARAMS: [&str; 12] = [
+    "fbclid", "gclid", "dclid", "yclid", "msclkid", "twclid", "igshid", "mc_cid", "mc_eid", "_ga",
+    "_gl", "ref_src",
+];
+
+/// Characters that cannot appear in a URL written in text, besides whitespace.
+const URL_DELIMITERS: [char; 6] = ['<', '>', '"', '\'', '`', '|'];
+/// Punctuation ending the sentence a URL is written in rather than the URL itself.
+const TRAILING_PUNCTUATION: [char; 7] = ['.', ',', '!', '?', ':', ';', ']'];
+
+fn is_tracking_param(key: &str) -> bool {
+    key.starts_with("utm_") || TRACKING_PARAMS.contains(&key)
+}
+
+/// Normalizes a recipe URL so the same page shared from different places compares equal.
+///
+/// Tracking query parameters and the fragment are removed.
+pub fn canonicalize_url(url: &url::Url) -> url::Url {
+    let mut url = url.clone();
+    url.set_fragment(None);
+
+    let query = url
+        .query_pairs()
+        .filter(|(key, _)| !is_tracking_param(key))
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect::<Vec<_>>();
+    if query.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(query);
+    }
+
+    url
+}
+
+/// Finds the http(s) URLs written in a message, such as text shared from another app with a
+/// page title followed by its link. A link repeated in the message is returned once.
+///
+/// Japanese text often follows a link without a space, so a URL ends at the first non-ASCII
+/// character.
+pub fn extract_urls(text: &str) -> Vec<url::Url> {
+    let mut urls: Vec<url::Url> = Vec::new();
+    let mut rest = text;
+    while let Some(start) = find_scheme(rest) {
+        let candidate = &rest[start..];
+        let end = candidate
+            .find(|c: char| c.is_whitespace() || !c.is_ascii() || URL_DELIMITERS.contains(&c))
+            .unwrap_or(candidate.len());
+        rest = &candidate[end..];
+
+        let Ok(url) = url::Url::parse(trim_url_end(&candidate[..end])) else {
+            continue;
+        };
+        let canonical = canonicalize_url(&url);
+        if url.host_str().is_some() && !urls.iter().any(|u| canonicalize_url(u) == canonical) {
+            urls.push(url);
+        }
+    }
+    urls
+}
+
+fn find_scheme(text: &str) -> Option<usize> {
+    // ASCII lowercasing keeps byte offsets.
+    let text = text.to_ascii_lowercase();
+    ["https://", "http://"]
+        .iter()
+        .filter_map(|scheme| text.find(scheme))
+        .min()
+}
+
+/// Drops punctuation after a URL, keeping closing parentheses that belong to it as in
+/// `https://en.wikipedia.org/wiki/Curry_(dish)`.
+fn trim_url_end(url: &str) -> &str {
+    let mut url = url;
+    loop {
+        let trimmed = url.trim_end_matches(TRAILING_PUNCTUATION);
+        let trimmed = match trimmed.strip_suffix(')') {
+            Some(rest) if trimmed.matches('(').count() < trimmed.matches(')').count() => rest,
+            _ => trimmed,
+        };
+        if trimmed.len() == url.len() {
+            return url;
+        }
+        url = trimmed;
+    }
+}
+
+/// Resolves the canonical URL declared by a page against the URL it was fetched from.
+///
+/// Declarations pointing to another site, or to the top page from a deeper page, are ignored
+/// because some sites use them incorrectly.
+pub fn resolve_canonical_url(page_url: &url::Url, declared: Option<&str>) -> url::Url {
+    let canonical = declared
+        .and_then(|declared| page_url.join(declared).ok())
+        .filter(|canonical| matches!(canonical.scheme(), "http" | "https"))
+        .filter(|canonical| {
+            let host = |url: &url::Url| {
+                url.host_str()
+                    .map(|host| host.trim_start_matches("www.").to_string())
+            };
+            host(canonical) == host(page_url)
+        })
+        .filter(|canonical| canonical.path() != "/" || page_url.path() == "/");
+
+    canonicalize_url(canonical.as_ref().unwrap_or(page_url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case("https://cookpad.com/recipe/123?utm_source=line&utm_medium=share" => "https://cookpad.com/recipe/123" ; "utm params")]
+    #[test_case("https://example.com/r?id=1&fbclid=abc#steps" => "https://example.com/r?id=1" ; "fbclid and fragment")]
+    #[test_case("https://example.com/r?id=1" => "https://example.com/r?id=1" ; "unchanged")]
+    fn test_canonicalize_url(url: &str) -> String {
+        canonicalize_url(&url::Url::parse(url).unwrap()).to_string()
+    }
+
+    #[test_case(Some("/recipe/123") => "https://cookpad.com/recipe/123" ; "relative")]
+    #[test_case(Some("https://www.cookpad.com/recipe/123?utm_source=x") => "https://www.cookpad.com/recipe/123" ; "www host")]
+    #[test_case(Some("https://other.example.com/recipe/123") => "https://cookpad.com/recipe/123" ; "other site")]
+    #[test_case(Some("https://cookpad.com/") => "https://cookpad.com/recipe/123" ; "top page")]
+    #[test_case(None => "https://cookpad.com/recipe/123" ; "not declared")]
+    fn test_resolve_canonical_url(declared: Option<&str>) -> String {
+        let page_url = url::Url::parse("https://cookpad.com/recipe/123?utm_source=line").unwrap();
+        resolve_canonical_url(&page_url, declared).to_string()
+    }
+
+    #[test_case("https://example.com/recipe/1" => vec!["https://example.com/recipe/1"] ; "url only")]
+    #[test_case("これとこれ作りたい https://a.example.com/1 https://b.example.com/2" => vec!["https://a.example.com/1", "https://b.example.com/2"] ; "several urls")]
+    #[test_case("鶏の照り焼き | クラシル\nhttps://www.kurashiru.com/recipes/abc?utm_source=line" => vec!["https://www.kurashiru.com/recipes/abc?utm_source=line"] ; "shared title and link")]
+    #[test_case("https://a.example.com/1を作る（https://b.example.com/2）" => vec!["https://a.example.com/1", "https://b.example.com/2"] ; "japanese around urls")]
+    #[test_case("見て！https://example.com/recipe/1. と https://example.com/recipe/1?utm_source=x" => vec!["https://example.com/recipe/1"] ; "punctuation and repeats")]
+    #[test_case("(see https://en.wikipedia.org/wiki/Curry_(dish))" => vec!["https://en.wikipedia.org/wiki/Curry_(dish)"] ; "parentheses")]
+    #[test_case("HTTPS://EXAMPLE.COM/a" => vec!["https://example.com/a"] ; "upper case")]
+    #[test_case("https:// と ftp://example.com" => Vec::<String>::new() ; "no valid urls")]
+    fn test_extract_urls(text: &str) -> Vec<String> {
+        extract_urls(text).into_iter().map(String::from).collect()
+    }
+}
@@ -1 +1,4 @@
+pub mod link;
+pub mod pantry;
 pub mod recipe;
+pub mod search;
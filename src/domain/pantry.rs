@@ -0,0 +1,356 @@
+//! Suggests recipes for the ingredients at hand.
+//!
+//! Ingredient names are compared after folding width and kana and mapping synonyms to one
+//! name, so "鶏もも肉" in a recipe counts as the "とり肉" the user has. Seasonings most kitchens
+//! keep are left out, so they are neither required nor reported missing.
+
+use std::{collections::HashSet, sync::LazyLock};
+
+use crate::domain::{recipe::Recipe, search::fold_width_and_kana};
+
+/// Ingredients written in several ways, by the name they are compared under. A name ending
+/// with one of the spellings counts as well, e.g. "国産鶏もも肉". Katakana and hiragana need
+/// not be listed separately.
+const SYNONYMS: [(&str, &[&str]); 28] = [
+    (
+        "鶏肉",
+        &[
+            "とり肉",
+            "とりにく",
+            "鳥肉",
+            "鶏もも肉",
+            "鶏もも",
+            "鶏むね肉",
+            "鶏胸肉",
+            "鶏むね",
+            "鶏ささみ",
+            "ささみ",
+            "手羽元",
+            "手羽先",
+            "若鶏",
+            "チキン",
+        ],
+    ),
+    (
+        "豚肉",
+        &[
+            "ぶた肉",
+            "豚バラ肉",
+            "豚バラ",
+            "豚こま肉",
+            "豚こま",
+            "豚こま切れ肉",
+            "豚小間切れ肉",
+            "豚ロース",
+            "豚肩ロース",
+            "豚もも肉",
+            "豚薄切り肉",
+        ],
+    ),
+    (
+        "牛肉",
+        &[
+            "牛こま肉",
+            "牛こま切れ肉",
+            "牛切り落とし",
+            "牛切り落とし肉",
+            "牛もも肉",
+            "牛バラ肉",
+            "牛薄切り肉",
+        ],
+    ),
+    (
+        "ひき肉",
+        &[
+            "挽肉",
+            "挽き肉",
+            "合いびき肉",
+            "合挽き肉",
+            "合い挽き肉",
+            "豚ひき肉",
+            "鶏ひき肉",
+            "牛ひき肉",
+            "ミンチ",
+        ],
+    ),
+    ("玉ねぎ", &["たまねぎ", "玉葱", "新玉ねぎ", "オニオン"]),
+    (
+        "ねぎ",
+        &[
+            "葱",
+            "長ねぎ",
+            "長葱",
+            "白ねぎ",
+            "青ねぎ",
+            "小ねぎ",
+            "万能ねぎ",
+            "九条ねぎ",
+        ],
+    ),
+    ("にんじん", &["人参"]),
+    (
+        "じゃがいも",
+        &["じゃが芋", "馬鈴薯", "男爵いも", "メークイン"],
+    ),
+    ("卵", &["たまご", "玉子", "鶏卵", "全卵", "溶き卵"]),
+    ("しょうが", &["生姜", "おろし生姜", "しょうがチューブ"]),
+    ("にんにく", &["大蒜", "おろしにんにく", "にんにくチューブ"]),
+    (
+        "トマト",
+        &[
+            "ミニトマト",
+            "プチトマト",
+            "トマト缶",
+            "カットトマト缶",
+            "ホールトマト缶",
+        ],
+    ),
+    ("なす", &["茄子"]),
+    ("大根", &["だいこん"]),
+    ("白菜", &["はくさい"]),
+    ("小松菜", &["こまつな"]),
+    ("ほうれん草", &["ほうれんそう", "菠薐草"]),
+    ("しいたけ", &["椎茸", "干ししいたけ"]),
+    ("豆腐", &["とうふ", "木綿豆腐", "絹ごし豆腐", "絹豆腐"]),
+    // "さけ" is left out, as it is also how 酒 is read.
+    ("鮭", &["しゃけ", "生鮭", "塩鮭", "サーモン"]),
+    ("えび", &["海老", "むきえび", "むき海老"]),
+    ("ご飯", &["ごはん", "御飯", "白ご飯", "温かいご飯"]),
+    ("もやし", &["豆もやし", "緑豆もやし"]),
+    ("にら", &["韮"]),
+    ("とうもろこし", &["コーン", "玉蜀黍"]),
+    ("さといも", &["里芋"]),
+    ("ししとう", &["獅子唐", "ししとうがらし"]),
+    ("納豆", &["なっとう", "ひきわり納豆"]),
+];
+
+/// Seasonings assumed to be at hand. Only exact names count, so "ポン酢" is still required.
+const STAPLES: [&str; 23] = [
+    "塩",
+    "砂糖",
+    "さとう",
+    "しょうゆ",
+    "醤油",
+    "こしょう",
+    "胡椒",
+    "塩こしょう",
+    "塩胡椒",
+    "酒",
+    "料理酒",
+    "みりん",
+    "味醂",
+    "酢",
+    "みそ",
+    "味噌",
+    "水",
+    "油",
+    "サラダ油",
+    "ごま油",
+    "オリーブオイル",
+    "片栗粉",
+    "小麦粉",
+];
+
+/// Folded spellings mapped to the folded name they are compared under, longest first so that
+/// "たまねぎ" wins over "ねぎ".
+static FOLDED_SYNONYMS: LazyLock<Vec<(String, String)>> = LazyLock::new(|| {
+    let mut synonyms = SYNONYMS
+        .iter()
+        .flat_map(|(name, spellings)| {
+            let name = fold(name);
+            std::iter::once(name.clone())
+                .chain(spellings.iter().map(|spelling| fold(spelling)))
+                .map(move |spelling| (spelling, name.clone()))
+        })
+        .collect::<Vec<_>>();
+    synonyms.sort_by_key(|(spelling, _)| std::cmp::Reverse(spelling.chars().count()));
+    synonyms
+});
+
+static FOLDED_STAPLES: LazyLock<HashSet<String>> =
+    LazyLock::new(|| STAPLES.iter().map(|staple| fold(staple)).collect());
+
+/// A recipe that uses some of the ingredients at hand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    pub recipe: Recipe,
+    /// The recipe's ingredients that are at hand, as written in the recipe.
+    pub have: Vec<String>,
+    /// The recipe's ingredients that are still needed, as written in the recipe.
+    pub missing: Vec<String>,
+}
+
+fn fold(text: &str) -> String {
+    fold_width_and_kana(text.trim()).into_iter().collect()
+}
+
+/// The name an ingredient is compared under, e.g. "鶏肉" for "鶏もも肉", folded.
+pub fn normalize_ingredient(name: &str) -> String {
+    let folded = fold(name);
+    FOLDED_SYNONYMS
+        .iter()
+        .find(|(spelling, _)| folded.ends_with(spelling.as_str()))
+        .map(|(_, name)| name.clone())
+        .unwrap_or(folded)
+}
+
+pub fn is_staple(name: &str) -> bool {
+    FOLDED_STAPLES.contains(&fold(name))
+}
+
+/// Whether the name is a staple or an ingredient listed in [`SYNONYMS`], including names ending
+/// with one of its spellings.
+pub fn is_known_ingredient(name: &str) -> bool {
+    let folded = fold(name);
+    FOLDED_STAPLES.contains(&folded)
+        || FOLDED_SYNONYMS
+            .iter()
+            .any(|(spelling, _)| folded.ends_with(spelling.as_str()))
+}
+
+/// Ranks the recipes using at least one of the ingredients at hand by the share of their
+/// ingredients at hand, then by how few are missing, then newest first.
+pub fn suggest(
+    ingredients: &[String],
+    recipes: impl IntoIterator<Item = Recipe>,
+    limit: usize,
+) -> Vec<Suggestion> {
+    let at_hand = ingredients
+        .iter()
+        .map(|name| normalize_ingredient(name))
+        .collect::<HashSet<_>>();
+    let mut suggestions = recipes
+        .into_iter()
+        .filter_map(|recipe| {
+            let mut seen = HashSet::new();
+            let (mut have, mut missing) = (Vec::new(), Vec::new());
+            for ingredient in &recipe.ingredients {
+                if is_staple(&ingredient.name) {
+                    continue;
+                }
+                let key = normalize_ingredient(&ingredient.name);
+                if !seen.insert(key.clone()) {
+                    continue;
+                }
+                if at_hand.contains(&key) {
+                    have.push(ingredient.name.clone());
+                } else {
+                    missing.push(ingredient.name.clone());
+                }
+            }
+            (!have.is_empty()).then_some(Suggestion {
+                recipe,
+                have,
+                missing,
+            })
+        })
+        .collect::<Vec<_>>();
+    suggestions.sort_by(|a, b| {
+        let total = |suggestion: &Suggestion| suggestion.have.len() + suggestion.missing.len();
+        (b.have.len() * total(a))
+            .cmp(&(a.have.len() * total(b)))
+            .then(a.missing.len().cmp(&b.missing.len()))
+            .then((b.recipe.created_at, b.recipe.id).cmp(&(a.recipe.created_at, a.recipe.id)))
+    });
+    suggestions.truncate(limit);
+    suggestions
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+    use test_case::test_case;
+
+    use crate::domain::recipe::Ingredient;
+
+    use super::*;
+
+    fn recipe(name: &str, ingredients: &[&str], seconds: i64) -> Recipe {
+        Recipe {
+            ingredients: ingredients
+                .iter()
+                .map(|line| Ingredient::parse(line))
+                .collect(),
+            created_at: DateTime::from_timestamp(seconds, 0).unwrap(),
+            ..Recipe::new(
+                name.to_string(),
+                url::Url::parse(&format!("https://example.com/recipe/{seconds}")).unwrap(),
+            )
+        }
+    }
+
+    #[test_case("鶏もも肉", "鶏肉"; "cut")]
+    #[test_case("国産鶏むね肉", "とり肉"; "suffix")]
+    #[test_case("ネギ", "長ねぎ"; "katakana")]
+    #[test_case("葱", "ねぎ"; "kanji")]
+    #[test_case("ﾀﾏﾈｷﾞ", "玉ねぎ"; "half-width")]
+    #[test_case("新玉ねぎ", "たまねぎ"; "longest spelling")]
+    #[test_case("キャベツ", "きゃべつ"; "not in dictionary")]
+    fn test_normalize_ingredient(name: &str, same_as: &str) {
+        assert_eq!(normalize_ingredient(name), normalize_ingredient(same_as));
+    }
+
+    #[test]
+    fn test_normalize_ingredient_keeps_distinct_names() {
+        assert_ne!(normalize_ingredient("玉ねぎ"), normalize_ingredient("ねぎ"));
+        assert_ne!(normalize_ingredient("鮭"), normalize_ingredient("酒"));
+        assert!(is_staple("醤油"));
+        assert!(!is_staple("ポン酢"));
+    }
+
+    #[test_case("国産鶏もも肉" => true; "synonym with prefix")]
+    #[test_case("さとう" => true; "staple")]
+    #[test_case("なっとう" => true; "kana spelling")]
+    #[test_case("キャベツ" => false; "not in dictionary")]
+    #[test_case("さ" => false; "part of a name")]
+    fn test_is_known_ingredient(name: &str) -> bool {
+        is_known_ingredient(name)
+    }
+
+    #[test]
+    fn test_suggest() {
+        let recipes = vec![
+            recipe(
+                "肉じゃが",
+                &["牛肉 200g", "じゃがいも 3個", "玉ねぎ 1個"],
+                1,
+            ),
+            recipe(
+                "親子丼",
+                &["鶏もも肉 300g", "玉葱 1/2個", "卵 2個", "しょうゆ 大さじ2"],
+                2,
+            ),
+            recipe("チキンソテー", &["鶏むね肉 1枚", "塩 少々"], 3),
+            recipe("サラダ", &["キャベツ 1/4個"], 4),
+            recipe("照り焼き", &["鶏肉 1枚", "鶏もも肉 1枚", "ねぎ 1本"], 5),
+        ];
+
+        let suggestions = suggest(&["とり肉".to_string(), "タマネギ".to_string()], recipes, 3);
+        let summary = suggestions
+            .iter()
+            .map(|suggestion| {
+                (
+                    suggestion.recipe.name.as_str(),
+                    suggestion.have.clone(),
+                    suggestion.missing.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                ("チキンソテー", vec!["鶏むね肉".to_string()], vec![]),
+                (
+                    "親子丼",
+                    vec!["鶏もも肉".to_string(), "玉葱".to_string()],
+                    vec!["卵".to_string()]
+                ),
+                (
+                    "照り焼き",
+                    vec!["鶏肉".to_string()],
+                    vec!["ねぎ".to_string()]
+                ),
+            ]
+        );
+    }
+}
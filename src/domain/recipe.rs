@@ -1,16 +1,325 @@
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use validator::{Validate, ValidationError};
+
+use crate::prelude::*;
+
+/// Words used in Japanese recipes in place of a measured amount.
+const UNMEASURED_AMOUNTS: [&str; 6] = ["少々", "適量", "適宜", "ひとつまみ", "少量", "お好みで"];
+
+/// Largest number of servings a recipe may have.
+pub const MAX_SERVINGS: u32 = 100;
+
+/// Units written before the quantity, e.g. `大さじ2`.
+const PREFIX_UNITS: [&str; 3] = ["大さじ", "小さじ", "カップ"];
+/// Marks a quantity as approximate, e.g. `約200g`; it stays part of the quantity.
+const APPROXIMATE: &str = "約";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Validate)]
+pub struct Ingredient {
+    #[validate(length(min = 1))]
+    pub name: String,
+    pub quantity: Option<String>,
+    pub unit: Option<String>,
+    pub note: Option<String>,
+}
+
+impl Ingredient {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            quantity: None,
+            unit: None,
+            note: None,
+        }
+    }
+
+    /// Parses a free-form ingredient line such as `鶏もも肉（皮なし） 300g` or `しょうゆ 大さじ2`.
+    pub fn parse(line: &str) -> Self {
+        let line = line.trim();
+        let (name, amount) = match line.rsplit_once(char::is_whitespace) {
+            Some((name, amount)) if is_amount(amount) => (name.trim(), Some(amount)),
+            _ => (line, None),
+        };
+        let (name, note) = split_note(name);
+        let (quantity, unit) = amount.map(split_amount).unwrap_or_default();
+
+        Self {
+            name,
+            quantity,
+            unit,
+            note,
+        }
+    }
+}
+
+impl std::fmt::Display for Ingredient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(note) = &self.note {
+            write!(f, "（{note}）")?;
+        }
+        match (&self.quantity, &self.unit) {
+            (Some(quantity), Some(unit)) if PREFIX_UNITS.contains(&unit.as_str()) => {
+                write!(f, " {unit}{quantity}")
+            }
+            (Some(quantity), unit) => write!(f, " {quantity}{}", unit.as_deref().unwrap_or("")),
+            (None, Some(unit)) => write!(f, " {unit}"),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+fn is_quantity_char(c: char) -> bool {
+    c.is_ascii_digit() || ('０'..='９').contains(&c) || "./／〜~-½¼¾".contains(c)
+}
+
+fn is_amount(s: &str) -> bool {
+    s.chars().any(is_quantity_char) || UNMEASURED_AMOUNTS.iter().any(|a| s.contains(a))
+}
+
+fn split_note(name: &str) -> (String, Option<String>) {
+    for (open, close) in [('（', '）'), ('(', ')')] {
+        if let Some((head, rest)) = name.split_once(open) {
+            let note = rest.trim_end_matches(close).trim();
+            return (
+                head.trim().to_string(),
+                (!note.is_empty()).then(|| note.to_string()),
+            );
+        }
+    }
+    (name.to_string(), None)
+}
+
+fn split_amount(amount: &str) -> (Option<String>, Option<String>) {
+    if let Some(rest) = amount.strip_prefix(APPROXIMATE)
+        && let (Some(quantity), unit) = split_amount(rest.trim())
+    {
+        return (Some(format!("{APPROXIMATE}{quantity}")), unit);
+    }
+
+    if let Some(prefix) = PREFIX_UNITS.iter().find(|p| amount.starts_with(**p)) {
+        let quantity = amount[prefix.len()..].trim();
+        return (
+            (!quantity.is_empty()).then(|| quantity.to_string()),
+            Some(prefix.to_string()),
+        );
+    }
+
+    let split = amount
+        .char_indices()
+        .find(|(_, c)| !is_quantity_char(*c))
+        .map_or(amount.len(), |(i, _)| i);
+    let (quantity, unit) = amount.split_at(split);
+    (
+        (!quantity.is_empty()).then(|| quantity.to_string()),
+        (!unit.is_empty()).then(|| unit.trim().to_string()),
+    )
+}
+
+/// Extracts the number of servings from a yield such as `2人前` or `4 servings`.
+pub fn parse_servings(recipe_yield: &str) -> Option<u32> {
+    let digits = recipe_yield
+        .chars()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(char::is_ascii_digit)
+        .collect::<String>();
+    digits.parse().ok()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Validate)]
 pub struct Recipe {
     pub id: ulid::Ulid,
+    #[validate(length(min = 1))]
     pub name: String,
     pub recipe_url: url::Url,
+    #[validate(nested)]
+    pub ingredients: Vec<Ingredient>,
+    #[validate(custom(function = "validate_non_empty_items"))]
+    pub steps: Vec<String>,
+    #[validate(range(min = 1, max = MAX_SERVINGS))]
+    pub servings: Option<u32>,
+    pub prep_time: Option<Duration>,
+    pub cook_time: Option<Duration>,
+    pub total_time: Option<Duration>,
+    pub image_url: Option<url::Url>,
+    /// A short summary of the dish, usually from the recipe page.
+    pub description: Option<String>,
+    pub source: Option<String>,
+    #[validate(custom(function = "validate_non_empty_items"))]
+    pub tags: Vec<String>,
+    #[validate(range(min = 1, max = 5))]
+    pub rating: Option<u8>,
+    pub status: Option<String>,
+    #[validate(custom(function = "validate_non_empty_items"))]
+    pub notes: Vec<String>,
+    /// Where the recipe is stored, e.g. its Notion page. Set by the repository.
+    pub page_url: Option<url::Url>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Recipe fields some repositories can only store when configured to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecipeField {
+    Tags,
+    Rating,
+    Status,
+}
+
+impl RecipeField {
+    /// The field's key in `notion_properties`.
+    pub fn key(self) -> &'static str {
+        match self {
+            RecipeField::Tags => "tags",
+            RecipeField::Rating => "rating",
+            RecipeField::Status => "status",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            RecipeField::Tags => "タグ",
+            RecipeField::Rating => "評価",
+            RecipeField::Status => "ステータス",
+        }
+    }
+}
+
+/// Optional recipe data known at construction time, on top of the name and URL.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecipeDetails {
+    pub ingredients: Vec<Ingredient>,
+    pub steps: Vec<String>,
+    pub servings: Option<u32>,
+    pub prep_time: Option<Duration>,
+    pub cook_time: Option<Duration>,
+    pub total_time: Option<Duration>,
+    pub image_url: Option<url::Url>,
+    pub description: Option<String>,
+    pub source: Option<String>,
+    pub tags: Vec<String>,
 }
 
 impl Recipe {
     pub fn new(name: String, recipe_url: url::Url) -> Self {
+        let now = Utc::now();
         Self {
             id: ulid::Ulid::new(),
             name,
             recipe_url,
+            ingredients: Vec::new(),
+            steps: Vec::new(),
+            servings: None,
+            prep_time: None,
+            cook_time: None,
+            total_time: None,
+            image_url: None,
+            description: None,
+            source: None,
+            tags: Vec::new(),
+            rating: None,
+            status: None,
+            notes: Vec::new(),
+            page_url: None,
+            created_at: now,
+            updated_at: now,
         }
     }
+
+    pub fn with_details(
+        name: String,
+        recipe_url: url::Url,
+        details: RecipeDetails,
+    ) -> Result<Self> {
+        let recipe = Self {
+            ingredients: details.ingredients,
+            steps: details.steps,
+            servings: details.servings,
+            prep_time: details.prep_time,
+            cook_time: details.cook_time,
+            total_time: details.total_time,
+            image_url: details.image_url,
+            description: details.description,
+            source: details.source,
+            tags: details.tags,
+            ..Self::new(name, recipe_url)
+        };
+        recipe.validate()?;
+        Ok(recipe)
+    }
+
+    /// Adds tags that the recipe does not have yet, keeping the existing order.
+    pub fn add_tags(&mut self, tags: impl IntoIterator<Item = String>) {
+        for tag in tags {
+            if !self.tags.contains(&tag) {
+                self.tags.push(tag);
+            }
+        }
+    }
+}
+
+fn validate_non_empty_items(items: &[String]) -> std::result::Result<(), ValidationError> {
+    if items.iter().any(|item| item.trim().is_empty()) {
+        return Err(ValidationError::new("empty_item"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    fn ingredient(
+        name: &str,
+        quantity: Option<&str>,
+        unit: Option<&str>,
+        note: Option<&str>,
+    ) -> Ingredient {
+        Ingredient {
+            name: name.to_string(),
+            quantity: quantity.map(str::to_string),
+            unit: unit.map(str::to_string),
+            note: note.map(str::to_string),
+        }
+    }
+
+    #[test_case("鶏もも肉 300g" => ingredient("鶏もも肉", Some("300"), Some("g"), None) ; "quantity and unit")]
+    #[test_case("しょうゆ 大さじ2" => ingredient("しょうゆ", Some("2"), Some("大さじ"), None) ; "prefix unit")]
+    #[test_case("玉ねぎ 約200g" => ingredient("玉ねぎ", Some("約200"), Some("g"), None) ; "approximate quantity")]
+    #[test_case("玉ねぎ（中） 1/2個" => ingredient("玉ねぎ", Some("1/2"), Some("個"), Some("中")) ; "note and fraction")]
+    #[test_case("塩 少々" => ingredient("塩", None, Some("少々"), None) ; "unmeasured")]
+    #[test_case("サラダ油" => ingredient("サラダ油", None, None, None) ; "name only")]
+    #[test_case("ブラック ペッパー" => ingredient("ブラック ペッパー", None, None, None) ; "name with space")]
+    fn test_parse_ingredient(line: &str) -> Ingredient {
+        Ingredient::parse(line)
+    }
+
+    #[test_case("2人前" => Some(2) ; "japanese")]
+    #[test_case("4 servings" => Some(4) ; "english")]
+    #[test_case("適量" => None ; "no number")]
+    fn test_parse_servings(recipe_yield: &str) -> Option<u32> {
+        parse_servings(recipe_yield)
+    }
+
+    #[test]
+    fn test_with_details_rejects_invalid_data() {
+        let url = url::Url::parse("https://example.com").unwrap();
+
+        let details = RecipeDetails {
+            servings: Some(0),
+            ..Default::default()
+        };
+        assert!(Recipe::with_details("name".to_string(), url.clone(), details).is_err());
+
+        let details = RecipeDetails {
+            ingredients: vec![Ingredient::new("")],
+            ..Default::default()
+        };
+        assert!(Recipe::with_details("name".to_string(), url.clone(), details).is_err());
+
+        assert!(Recipe::with_details(String::new(), url, RecipeDetails::default()).is_err());
+    }
 }
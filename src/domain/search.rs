@@ -0,0 +1,573 @@
+//! Full-text search over recipes written in Japanese.
+//!
+//! Text is folded before matching: full-width and half-width forms are unified, katakana is
+//! turned into hiragana, letters are lowercased and common cooking words written in kanji are
+//! replaced by their reading. "とりにく", "トリニク" and "鶏肉" therefore all match each other.
+//! The text as written is searched as well, so that "肉" still finds "豚肉". Folded text is
+//! split into overlapping bigrams, which index words without a dictionary.
+
+use std::{ops::Range, sync::LazyLock};
+
+use crate::domain::recipe::Recipe;
+
+/// Readings of common cooking words written in kanji, so that searching in kana finds them.
+/// Words missing here only match as they are written. Single kanji whose reading is part of
+/// other words are left out, e.g. 酒 (さけ, like 鮭) and 肉 (にく, within にんにく).
+const READINGS: [(&str, &str); 65] = [
+    ("鶏肉", "とりにく"),
+    ("鳥肉", "とりにく"),
+    ("豚肉", "ぶたにく"),
+    ("牛肉", "ぎゅうにく"),
+    ("挽肉", "ひきにく"),
+    ("挽き肉", "ひきにく"),
+    ("鶏", "とり"),
+    ("豚", "ぶた"),
+    ("牛乳", "ぎゅうにゅう"),
+    ("牛", "ぎゅう"),
+    ("玉葱", "たまねぎ"),
+    ("玉ねぎ", "たまねぎ"),
+    ("長葱", "ながねぎ"),
+    ("長ねぎ", "ながねぎ"),
+    ("人参", "にんじん"),
+    ("大根", "だいこん"),
+    ("胡瓜", "きゅうり"),
+    ("茄子", "なす"),
+    ("南瓜", "かぼちゃ"),
+    ("牛蒡", "ごぼう"),
+    ("蓮根", "れんこん"),
+    ("里芋", "さといも"),
+    ("薩摩芋", "さつまいも"),
+    ("馬鈴薯", "じゃがいも"),
+    ("白菜", "はくさい"),
+    ("小松菜", "こまつな"),
+    ("菠薐草", "ほうれんそう"),
+    ("法蓮草", "ほうれんそう"),
+    ("椎茸", "しいたけ"),
+    ("生姜", "しょうが"),
+    ("大蒜", "にんにく"),
+    ("卵", "たまご"),
+    ("玉子", "たまご"),
+    ("豆腐", "とうふ"),
+    ("油揚げ", "あぶらあげ"),
+    ("納豆", "なっとう"),
+    ("鮭", "さけ"),
+    ("鯖", "さば"),
+    ("鰤", "ぶり"),
+    ("海老", "えび"),
+    ("烏賊", "いか"),
+    ("蛸", "たこ"),
+    ("味噌", "みそ"),
+    ("醤油", "しょうゆ"),
+    ("砂糖", "さとう"),
+    ("味醂", "みりん"),
+    ("胡麻", "ごま"),
+    ("胡椒", "こしょう"),
+    ("片栗粉", "かたくりこ"),
+    ("小麦粉", "こむぎこ"),
+    ("塩", "しお"),
+    ("御飯", "ごはん"),
+    ("ご飯", "ごはん"),
+    ("鍋", "なべ"),
+    ("汁", "しる"),
+    ("唐揚げ", "からあげ"),
+    ("揚げ", "あげ"),
+    ("炒め", "いため"),
+    ("焼き", "やき"),
+    ("煮物", "にもの"),
+    ("煮込み", "にこみ"),
+    ("蒸し", "むし"),
+    ("和え", "あえ"),
+    ("漬け", "つけ"),
+    ("照り", "てり"),
+];
+
+/// Half-width katakana from U+FF61, in code point order.
+const HALF_WIDTH_KATAKANA: &str = "。「」、・ヲァィゥェォャュョッーアイウエオカキクケコサシスセソタチツテトナニヌネノハヒフヘホマミムメモヤユヨラリルレロワン";
+
+/// How much a match counts, by where it was found.
+const NAME_WEIGHT: u32 = 8;
+const TAG_WEIGHT: u32 = 4;
+const INGREDIENT_WEIGHT: u32 = 3;
+const STEP_WEIGHT: u32 = 1;
+/// Repeated matches in one field stop counting after this many.
+const MAX_COUNTED_MATCHES: usize = 3;
+
+/// Readings keyed by their folded spelling, longest first so that "鶏肉" wins over "鶏".
+static FOLDED_READINGS: LazyLock<Vec<(Vec<char>, Vec<char>)>> = LazyLock::new(|| {
+    let mut readings = READINGS
+        .iter()
+        .map(|(word, reading)| (fold_width_and_kana(word), reading.chars().collect()))
+        .collect::<Vec<_>>();
+    readings.sort_by_key(|(word, _)| std::cmp::Reverse(word.len()));
+    readings
+});
+
+/// Text folded for matching, remembering which bytes of the original each character came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FoldedText {
+    chars: Vec<char>,
+    sources: Vec<Range<usize>>,
+    /// The text folded without replacing readings, when it contains any.
+    spelling: Option<Box<FoldedText>>,
+}
+
+impl FoldedText {
+    pub fn new(text: &str) -> Self {
+        let mut folded = Self {
+            chars: Vec::new(),
+            sources: Vec::new(),
+            spelling: None,
+        };
+        let mut chars = text.char_indices().peekable();
+        while let Some((start, c)) = chars.next() {
+            let mut end = start + c.len_utf8();
+            let mut c = fold_width(c);
+            if let Some(&(mark_start, mark)) = chars.peek()
+                && let Some(voiced) = voice(c, mark)
+            {
+                chars.next();
+                end = mark_start + mark.len_utf8();
+                c = voiced;
+            }
+            for c in katakana_to_hiragana(c).to_lowercase() {
+                folded.chars.push(c);
+                folded.sources.push(start..end);
+            }
+        }
+        let spelling = folded.clone();
+        folded.replace_readings();
+        if folded.chars != spelling.chars {
+            folded.spelling = Some(Box::new(spelling));
+        }
+        folded
+    }
+
+    fn replace_readings(&mut self) {
+        let mut chars = Vec::with_capacity(self.chars.len());
+        let mut sources = Vec::with_capacity(self.sources.len());
+        let mut i = 0;
+        while i < self.chars.len() {
+            let reading = FOLDED_READINGS
+                .iter()
+                .find(|(word, _)| self.chars[i..].starts_with(word));
+            match reading {
+                Some((word, reading)) => {
+                    let source = self.sources[i].start..self.sources[i + word.len() - 1].end;
+                    chars.extend(reading);
+                    sources.extend(std::iter::repeat_n(source, reading.len()));
+                    i += word.len();
+                }
+                None => {
+                    chars.push(self.chars[i]);
+                    sources.push(self.sources[i].clone());
+                    i += 1;
+                }
+            }
+        }
+        self.chars = chars;
+        self.sources = sources;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    /// The index terms: the bigrams of each run of letters and digits, and the last character
+    /// of each run, so that single characters can be found as a prefix. Terms of the text as
+    /// written follow those of the readings.
+    pub fn terms(&self) -> Vec<String> {
+        let mut terms = Vec::new();
+        for run in self.chars.split(|c| !c.is_alphanumeric()) {
+            terms.extend(run.windows(2).map(|pair| pair.iter().collect::<String>()));
+            if let Some(last) = run.last() {
+                terms.push(last.to_string());
+            }
+        }
+        if let Some(spelling) = &self.spelling {
+            for term in spelling.terms() {
+                if !terms.contains(&term) {
+                    terms.push(term);
+                }
+            }
+        }
+        terms
+    }
+
+    /// Byte ranges in the original text where `needle` occurs. A needle without readings is
+    /// also looked for in the text as written, so "肉" finds "豚肉" but "鮭" does not find "酒".
+    fn find(&self, needle: &FoldedText) -> Vec<Range<usize>> {
+        let mut found = self.find_folded(needle);
+        if needle.spelling.is_none()
+            && let Some(spelling) = &self.spelling
+        {
+            for range in spelling.find_folded(needle) {
+                if !found.contains(&range) {
+                    found.push(range);
+                }
+            }
+            found.sort_by_key(|range| range.start);
+        }
+        found
+    }
+
+    fn find_folded(&self, needle: &FoldedText) -> Vec<Range<usize>> {
+        if needle.is_empty() || needle.chars.len() > self.chars.len() {
+            return Vec::new();
+        }
+        self.chars
+            .windows(needle.chars.len())
+            .enumerate()
+            .filter(|(_, window)| *window == needle.chars.as_slice())
+            .map(|(i, _)| self.sources[i].start..self.sources[i + needle.chars.len() - 1].end)
+            .collect()
+    }
+}
+
+/// Turns full-width ASCII and half-width katakana into their usual forms.
+fn fold_width(c: char) -> char {
+    match c {
+        '\u{3000}' => ' ',
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        '\u{FF61}'..='\u{FF9D}' => HALF_WIDTH_KATAKANA
+            .chars()
+            .nth((c as u32 - 0xFF61) as usize)
+            .unwrap_or(c),
+        _ => c,
+    }
+}
+
+/// Combines a katakana with a following half-width (semi-)voiced sound mark, as in "ｶﾞ".
+fn voice(c: char, mark: char) -> Option<char> {
+    let offset = match (mark, c) {
+        ('ﾞ', 'ウ') => return Some('ヴ'),
+        ('ﾞ', 'カ'..='ト' | 'ハ'..='ホ') => 1,
+        ('ﾟ', 'ハ'..='ホ') => 2,
+        _ => return None,
+    };
+    // Voiceable kana are followed by their voiced forms, e.g. カ (U+30AB) and ガ (U+30AC).
+    let voiceable = "カキクケコサシスセソタチツテトハヒフヘホ";
+    voiceable
+        .contains(c)
+        .then(|| char::from_u32(c as u32 + offset))
+        .flatten()
+}
+
+fn katakana_to_hiragana(c: char) -> char {
+    match c {
+        'ァ'..='ヶ' => char::from_u32(c as u32 - 0x60).unwrap_or(c),
+        _ => c,
+    }
+}
+
+/// Folds text like [`FoldedText::new`] does, but keeps kanji as they are written.
+pub(crate) fn fold_width_and_kana(text: &str) -> Vec<char> {
+    let mut folded = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let mut c = fold_width(c);
+        if let Some(&mark) = chars.peek()
+            && let Some(voiced) = voice(c, mark)
+        {
+            chars.next();
+            c = voiced;
+        }
+        folded.extend(katakana_to_hiragana(c).to_lowercase());
+    }
+    folded
+}
+
+/// A search query: words separated by spaces, all of which must match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchQuery {
+    pub text: String,
+    words: Vec<FoldedText>,
+}
+
+impl SearchQuery {
+    pub fn parse(text: &str) -> Self {
+        let words = text
+            .split(|c: char| c.is_whitespace())
+            .map(FoldedText::new)
+            .filter(|word| word.chars.iter().any(|c| c.is_alphanumeric()))
+            .collect();
+        Self {
+            text: text.trim().to_string(),
+            words,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Index terms every match contains. Single characters come with `true`, as they may only
+    /// be found at the start of an index term.
+    pub fn terms(&self) -> Vec<(String, bool)> {
+        let mut terms = Vec::new();
+        for word in &self.words {
+            for run in word.chars.split(|c| !c.is_alphanumeric()) {
+                match run {
+                    [] => {}
+                    [c] => terms.push((c.to_string(), true)),
+                    _ => terms.extend(
+                        run.windows(2)
+                            .map(|pair| (pair.iter().collect::<String>(), false)),
+                    ),
+                }
+            }
+        }
+        terms
+    }
+}
+
+/// A recipe matching a search, with the matched parts for display.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchHit {
+    pub recipe: Recipe,
+    pub score: u32,
+    /// The ingredient or step that matched when the name did not, e.g. "鶏もも肉 300g".
+    pub snippet: Option<String>,
+    /// The matched parts of the name and the snippet, as written there.
+    pub highlights: Vec<String>,
+}
+
+/// Where in a recipe a match was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Name,
+    Tag,
+    Ingredient,
+    Step,
+}
+
+impl Field {
+    fn weight(self) -> u32 {
+        match self {
+            Field::Name => NAME_WEIGHT,
+            Field::Tag => TAG_WEIGHT,
+            Field::Ingredient => INGREDIENT_WEIGHT,
+            Field::Step => STEP_WEIGHT,
+        }
+    }
+}
+
+fn searchable_fields(recipe: &Recipe) -> Vec<(Field, String)> {
+    std::iter::once((Field::Name, recipe.name.clone()))
+        .chain(recipe.tags.iter().map(|tag| (Field::Tag, tag.clone())))
+        .chain(
+            recipe
+                .ingredients
+                .iter()
+                .map(|ingredient| (Field::Ingredient, ingredient.to_string())),
+        )
+        .chain(recipe.steps.iter().map(|step| (Field::Step, step.clone())))
+        .collect()
+}
+
+/// The text of a recipe that is searched: its name, tags, ingredients and steps.
+pub fn searchable_text(recipe: &Recipe) -> Vec<String> {
+    searchable_fields(recipe)
+        .into_iter()
+        .map(|(_, text)| text)
+        .collect()
+}
+
+/// Scores a recipe against the query, or returns `None` when a word matches nowhere.
+pub fn score(query: &SearchQuery, recipe: &Recipe) -> Option<SearchHit> {
+    if query.is_empty() {
+        return None;
+    }
+    let fields = searchable_fields(recipe)
+        .into_iter()
+        .map(|(field, text)| (field, FoldedText::new(&text), text))
+        .collect::<Vec<_>>();
+
+    let mut score = 0;
+    let mut matches = vec![Vec::new(); fields.len()];
+    let mut name_matches_all = true;
+    for word in &query.words {
+        let mut word_score = 0;
+        for ((field, folded, _), field_matches) in fields.iter().zip(&mut matches) {
+            let found = folded.find(word);
+            if *field == Field::Name && found.is_empty() {
+                name_matches_all = false;
+            }
+            word_score += field.weight() * found.len().min(MAX_COUNTED_MATCHES) as u32;
+            field_matches.extend(found);
+        }
+        if word_score == 0 {
+            return None;
+        }
+        score += word_score;
+    }
+    if let [word] = query.words.as_slice()
+        && fields[0].1.chars == word.chars
+    {
+        score += NAME_WEIGHT;
+    }
+
+    // Without the name explaining the match, show the first ingredient or step that matched.
+    let snippet = if name_matches_all {
+        None
+    } else {
+        fields
+            .iter()
+            .zip(&matches)
+            .position(|((field, _, _), found)| {
+                matches!(field, Field::Ingredient | Field::Step) && !found.is_empty()
+            })
+    };
+    let mut highlights = Vec::new();
+    for i in std::iter::once(0).chain(snippet) {
+        let text = &fields[i].2;
+        for range in &matches[i] {
+            let highlight = text[range.clone()].to_string();
+            if !highlights.contains(&highlight) {
+                highlights.push(highlight);
+            }
+        }
+    }
+    Some(SearchHit {
+        recipe: recipe.clone(),
+        score,
+        snippet: snippet.map(|i| fields[i].2.clone()),
+        highlights,
+    })
+}
+
+/// Scores the recipes and returns up to `limit` hits, best first, then newest first.
+pub fn rank(
+    query: &SearchQuery,
+    recipes: impl IntoIterator<Item = Recipe>,
+    limit: usize,
+) -> Vec<SearchHit> {
+    let mut hits = recipes
+        .into_iter()
+        .filter_map(|recipe| score(query, &recipe))
+        .collect::<Vec<_>>();
+    hits.sort_by(|a, b| {
+        (b.score, b.recipe.created_at, b.recipe.id).cmp(&(
+            a.score,
+            a.recipe.created_at,
+            a.recipe.id,
+        ))
+    });
+    hits.truncate(limit);
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use crate::domain::recipe::Ingredient;
+
+    use super::*;
+
+    fn folded(text: &str) -> String {
+        FoldedText::new(text).chars.into_iter().collect()
+    }
+
+    fn recipe(name: &str, ingredients: &[&str], steps: &[&str]) -> Recipe {
+        Recipe {
+            ingredients: ingredients
+                .iter()
+                .map(|line| Ingredient::parse(line))
+                .collect(),
+            steps: steps.iter().map(ToString::to_string).collect(),
+            ..Recipe::new(
+                name.to_string(),
+                url::Url::parse("https://example.com/recipe/1").unwrap(),
+            )
+        }
+    }
+
+    #[test_case("カレー", "かれー"; "katakana")]
+    #[test_case("ｶﾚｰ", "かれー"; "half-width katakana")]
+    #[test_case("ｶﾞﾊﾟｵ", "がぱお"; "half-width voiced marks")]
+    #[test_case("ＢＢＱ　ﾁｷﾝ", "bbq ちきん"; "full-width ascii")]
+    #[test_case("鶏肉の照り焼き", "とりにくのてりやき"; "readings")]
+    #[test_case("鶏もも肉", "とりもも肉"; "shorter readings")]
+    #[test_case("豚じゃが", "ぶたじゃが"; "mixed")]
+    fn test_fold(text: &str, expected: &str) {
+        assert_eq!(folded(text), expected);
+    }
+
+    #[test]
+    fn test_terms() {
+        let terms = FoldedText::new("豚汁 Soup").terms();
+        assert_eq!(
+            terms,
+            [
+                "ぶた", "たし", "しる", "る", "so", "ou", "up", "p", "豚汁", "汁"
+            ]
+        );
+        let query = SearchQuery::parse("ｽｰﾌﾟ 卵 え");
+        assert_eq!(
+            query.terms(),
+            [
+                ("すー".to_string(), false),
+                ("ーぷ".to_string(), false),
+                ("たま".to_string(), false),
+                ("まご".to_string(), false),
+                ("え".to_string(), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_score_matches_readings() {
+        let teriyaki = recipe("鶏肉の照り焼き", &["鶏もも肉 300g"], &["皮目から焼く"]);
+
+        let hit = score(&SearchQuery::parse("とりにく"), &teriyaki).unwrap();
+        assert_eq!(hit.highlights, ["鶏肉"]);
+        assert_eq!(hit.snippet, None);
+        assert!(score(&SearchQuery::parse("テリヤキ"), &teriyaki).is_some());
+        assert!(score(&SearchQuery::parse("とりにく ぶたにく"), &teriyaki).is_none());
+        assert!(score(&SearchQuery::parse("  "), &teriyaki).is_none());
+    }
+
+    #[test]
+    fn test_score_keeps_words_sharing_a_reading_apart() {
+        let sakamushi = recipe("あさりの酒蒸し", &["酒 大さじ2"], &[]);
+        let garlic = recipe("にんにくの芽炒め", &["にんにくの芽 1束"], &[]);
+        let ginger_pork = recipe("豚肉の生姜焼き", &["豚肉 200g"], &[]);
+
+        assert!(score(&SearchQuery::parse("鮭"), &sakamushi).is_none());
+        assert!(score(&SearchQuery::parse("酒"), &sakamushi).is_some());
+        assert!(score(&SearchQuery::parse("肉"), &garlic).is_none());
+        let hit = score(&SearchQuery::parse("肉"), &ginger_pork).unwrap();
+        assert_eq!(hit.highlights, ["肉"]);
+    }
+
+    #[test]
+    fn test_score_shows_matched_ingredient() {
+        let curry = recipe(
+            "チキンカレー",
+            &["玉ねぎ 1個", "鶏もも肉 300g"],
+            &["玉ねぎを炒める"],
+        );
+
+        let hit = score(&SearchQuery::parse("タマネギ"), &curry).unwrap();
+        assert_eq!(hit.snippet.as_deref(), Some("玉ねぎ 1個"));
+        assert_eq!(hit.highlights, ["玉ねぎ"]);
+        assert_eq!(hit.score, INGREDIENT_WEIGHT + STEP_WEIGHT);
+    }
+
+    #[test]
+    fn test_rank_prefers_names() {
+        let recipes = [
+            recipe("豚汁", &["大根 5cm"], &[]),
+            recipe("ふろふき大根", &["大根 1/2本"], &[]),
+            recipe("だいこんサラダ", &[], &[]),
+        ];
+
+        let hits = rank(&SearchQuery::parse("大根"), recipes, 2);
+        let names = hits
+            .iter()
+            .map(|hit| hit.recipe.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["ふろふき大根", "だいこんサラダ"]);
+        assert_eq!(hits[1].highlights, ["だいこん"]);
+    }
+}
@@ -1,4 +1,9 @@
-use crate::libs::line::error::LineClientError;
+use http::StatusCode;
+
+use crate::{
+    libs::{fetch::FetchError, line::error::LineClientError},
+    prelude::W,
+};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -18,6 +23,16 @@ pub enum Error {
     LineError(#[from] LineClientError),
     #[error("notion error: {0}")]
     NotionError(#[from] notion_client::NotionClientError),
+    #[error("notion schema error: {0}")]
+    NotionSchemaError(String),
+    #[error("job queue error: {0}")]
+    JobQueueError(String),
+    #[error("sqlite error: {0}")]
+    SqliteError(#[from] rusqlite::Error),
+    #[error("failed to fetch recipe: {0}")]
+    RecipeFetchError(#[source] Box<Error>),
+    #[error("fetch error: {0}")]
+    FetchError(#[from] FetchError),
     #[error("reqwest error: {0}")]
     ReqwestError(#[from] reqwest::Error),
     #[error("tl error: {0}")]
@@ -25,3 +40,157 @@ pub enum Error {
     #[error("anyhow error: {0}")]
     AnyhowError(#[from] anyhow::Error),
 }
+
+impl Error {
+    /// Short name of the kind of error, for logs.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Generic(_) => "generic",
+            Error::IoError(_) => "io",
+            Error::HmacError(_) => "hmac",
+            Error::ConfigError(_) => "config",
+            Error::ValidatorError(_) => "validator",
+            Error::UrlError(_) => "url",
+            Error::LineError(_) => "line",
+            Error::NotionError(_) => "notion",
+            Error::NotionSchemaError(_) => "notion_schema",
+            Error::JobQueueError(_) => "job_queue",
+            Error::SqliteError(_) => "sqlite",
+            Error::RecipeFetchError(_) => "recipe_fetch",
+            Error::FetchError(_) => "fetch",
+            Error::ReqwestError(_) => "reqwest",
+            Error::TlError(_) => "tl",
+            Error::AnyhowError(_) => "anyhow",
+        }
+    }
+}
+
+/// What went wrong from the user's point of view, deciding the reply and the HTTP status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// The message or the link sent by the user is malformed.
+    InvalidInput,
+    /// The recipe page could not be downloaded.
+    UnreachableSite,
+    /// The page was downloaded but no recipe could be read from it.
+    UnsupportedSite,
+    /// The Notion integration lacks access to the database or its properties do not match.
+    NotionConfiguration,
+    /// An upstream service asked us to slow down.
+    RateLimited,
+    /// A bug or an outage; the only category operators need to be alerted about.
+    Internal,
+}
+
+impl ErrorCategory {
+    /// Reply sent to the LINE user.
+    pub fn message(&self) -> &'static str {
+        match self {
+            ErrorCategory::InvalidInput => {
+                "入力が正しくありません。「ヘルプ」で使い方を確認してください"
+            }
+            ErrorCategory::UnreachableSite => {
+                "ページを開けませんでした。URLが正しいか確かめてください"
+            }
+            ErrorCategory::UnsupportedSite => "このページからレシピを取得できませんでした",
+            ErrorCategory::NotionConfiguration => {
+                "Notionに保存できませんでした。データベースの共有設定とプロパティを確認してください"
+            }
+            ErrorCategory::RateLimited => {
+                "混み合っています。しばらくしてからもう一度お試しください"
+            }
+            ErrorCategory::Internal => {
+                "エラーが発生しました。しばらくしてからもう一度お試しください"
+            }
+        }
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            ErrorCategory::InvalidInput => StatusCode::BAD_REQUEST,
+            ErrorCategory::UnreachableSite => StatusCode::BAD_GATEWAY,
+            ErrorCategory::UnsupportedSite => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCategory::NotionConfiguration => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCategory::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCategory::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Whether trying again later may succeed; other failures are given up on at once.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ErrorCategory::UnreachableSite | ErrorCategory::RateLimited | ErrorCategory::Internal
+        )
+    }
+}
+
+impl Error {
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Error::ValidatorError(_) | Error::UrlError(_) => ErrorCategory::InvalidInput,
+            Error::RecipeFetchError(e) => match e.as_ref() {
+                Error::ValidatorError(_) | Error::UrlError(_) => ErrorCategory::InvalidInput,
+                Error::ReqwestError(e) if is_rate_limited(e.status().map(|s| s.as_u16())) => {
+                    ErrorCategory::RateLimited
+                }
+                Error::ReqwestError(_) => ErrorCategory::UnreachableSite,
+                Error::FetchError(e) => match e {
+                    FetchError::UnsupportedScheme(_) | FetchError::BlockedAddress(_) => {
+                        ErrorCategory::InvalidInput
+                    }
+                    FetchError::Status(status) if is_rate_limited(Some(*status)) => {
+                        ErrorCategory::RateLimited
+                    }
+                    FetchError::TooManyRedirects(_) | FetchError::Status(_) => {
+                        ErrorCategory::UnreachableSite
+                    }
+                    FetchError::UnsupportedContentType(_) | FetchError::BodyTooLarge(_) => {
+                        ErrorCategory::UnsupportedSite
+                    }
+                },
+                _ => ErrorCategory::UnsupportedSite,
+            },
+            Error::NotionSchemaError(_) => ErrorCategory::NotionConfiguration,
+            Error::NotionError(notion_client::NotionClientError::InvalidStatusCode { error }) => {
+                match error.status {
+                    401 | 403 | 404 => ErrorCategory::NotionConfiguration,
+                    status if is_rate_limited(Some(status)) => ErrorCategory::RateLimited,
+                    _ => ErrorCategory::Internal,
+                }
+            }
+            Error::LineError(W(line_bot_sdk_rust::line_messaging_api::apis::Error::Api(e)))
+                if is_rate_limited(Some(e.code.as_u16())) =>
+            {
+                ErrorCategory::RateLimited
+            }
+            _ => ErrorCategory::Internal,
+        }
+    }
+}
+
+fn is_rate_limited(status: Option<u16>) -> bool {
+    status == Some(StatusCode::TOO_MANY_REQUESTS.as_u16())
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    fn validation_error() -> Error {
+        Error::ValidatorError(validator::ValidationErrors::new())
+    }
+
+    #[test_case(validation_error() => ErrorCategory::InvalidInput; "validation")]
+    #[test_case(Error::UrlError(url::ParseError::EmptyHost) => ErrorCategory::InvalidInput; "url")]
+    #[test_case(Error::RecipeFetchError(Box::new(Error::Generic("no title".to_string()))) => ErrorCategory::UnsupportedSite; "no recipe")]
+    #[test_case(Error::RecipeFetchError(Box::new(FetchError::BlockedAddress("127.0.0.1".to_string()).into())) => ErrorCategory::InvalidInput; "blocked address")]
+    #[test_case(Error::RecipeFetchError(Box::new(FetchError::Status(429).into())) => ErrorCategory::RateLimited; "rate limited site")]
+    #[test_case(Error::NotionSchemaError("missing property".to_string()) => ErrorCategory::NotionConfiguration; "notion schema")]
+    #[test_case(Error::JobQueueError("queue is full".to_string()) => ErrorCategory::Internal; "queue")]
+    fn test_category(error: Error) -> ErrorCategory {
+        error.category()
+    }
+}
@@ -0,0 +1,22 @@
+use crate::prelude::*;
+use async_trait::async_trait;
+use line_bot_sdk_rust::line_webhook::models::Event;
+
+/// Remembers processed `webhookEventId`s so redelivered events are handled at most once.
+#[cfg_attr(test, automock)]
+#[async_trait]
+pub trait EventDeduplicator {
+    /// Records the event ID and returns `false` when it was already recorded.
+    async fn claim(&self, event_id: &str) -> Result<bool>;
+    /// Forgets the event ID, e.g. when the event could not be queued and LINE will redeliver it.
+    async fn release(&self, event_id: &str) -> Result<()>;
+}
+
+/// Returns the `webhookEventId` and `isRedelivery` flag of the events the bot handles.
+pub fn delivery(e: &Event) -> Option<(&str, bool)> {
+    match e {
+        Event::MessageEvent(e) => Some((&e.webhook_event_id, e.delivery_context.is_redelivery)),
+        Event::PostbackEvent(e) => Some((&e.webhook_event_id, e.delivery_context.is_redelivery)),
+        _ => None,
+    }
+}
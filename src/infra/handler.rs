@@ -1,12 +1,89 @@
 use std::sync::Arc;
 
 use crate::{
-    app::{echo::EchoRequest, recipe::InsertRecipeRequest},
+    app::{command::CommandRequest, postback::PostbackRequest},
+    error::ErrorCategory,
+    infra::{
+        dedup::delivery,
+        job::{Job, JobHandler},
+        line::{LineMessage, ReplyTo, respond},
+    },
     libs::axum::server::AppState,
     prelude::*,
 };
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use line_bot_sdk_rust::line_webhook;
-use validator::Validate;
+
+/// Runs queued webhook events through `handle_event`.
+pub struct EventHandler {
+    state: Arc<AppState>,
+}
+
+impl EventHandler {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self { state }
+    }
+}
+
+#[async_trait]
+impl JobHandler for EventHandler {
+    async fn handle(&self, job: &Job) -> Result<()> {
+        let result = handle_event(self.state.clone(), job.event.clone()).await;
+        if let Err(e) = &result {
+            let event_id = delivery(&job.event).map(|(event_id, _)| event_id);
+            let source = event_reply_to(&job.event).and_then(|reply_to| reply_to.source_id);
+            let category = e.category();
+            // Only internal errors are logged as errors, so alerts are not raised for bad links.
+            if category == ErrorCategory::Internal {
+                tracing::error!(
+                    event_id,
+                    source,
+                    error_class = e.kind(),
+                    attempts = job.attempts,
+                    %e,
+                    "failed to handle event"
+                );
+            } else {
+                tracing::warn!(
+                    event_id,
+                    source,
+                    error_class = e.kind(),
+                    ?category,
+                    attempts = job.attempts,
+                    %e,
+                    "failed to handle event"
+                );
+            }
+        }
+        result
+    }
+
+    /// Tells the user the event could not be handled, once it will not be retried anymore.
+    async fn on_dead_letter(&self, job: &Job, error: &Error) {
+        let Some(reply_to) = event_reply_to(&job.event) else {
+            return;
+        };
+        if let Err(e) = respond(
+            self.state.line_client.as_ref(),
+            &reply_to,
+            vec![LineMessage::Text(error.category().message().to_string())],
+        )
+        .await
+        {
+            tracing::error!(%e, "failed to reply with the error message");
+        }
+    }
+}
+
+/// Whether the event is one `handle_event` acts on, so other events are not queued.
+pub fn is_supported_event(e: &line_webhook::models::Event) -> bool {
+    matches!(
+        e,
+        line_webhook::models::Event::MessageEvent(_)
+            | line_webhook::models::Event::PostbackEvent(_)
+    )
+}
 
 pub async fn handle_event(state: Arc<AppState>, e: line_webhook::models::Event) -> Result<()> {
     match e {
@@ -14,23 +91,33 @@ pub async fn handle_event(state: Arc<AppState>, e: line_webhook::models::Event)
             let (reply_token, message) = extract_message(&message_event)
                 .ok_or(anyhow::anyhow!("failed to extract message"))?;
 
-            let recipe_request = InsertRecipeRequest {
-                recipe_url: message.clone(),
-                reply_token: reply_token.clone(),
+            let command_request = CommandRequest {
+                text: message,
+                reply_to: reply_to(
+                    reply_token,
+                    message_event.source.as_deref(),
+                    message_event.timestamp,
+                ),
             };
+            state.command_service.handle(command_request).await?;
 
-            match recipe_request.validate() {
-                Ok(_) => {
-                    state.recipe_service.insert_recipe(recipe_request).await?;
-                }
-                Err(_) => {
-                    let echo_request = EchoRequest {
-                        reply_token,
-                        message: message.clone(),
-                    };
-                    state.echo_service.echo(echo_request).await?;
-                }
-            }
+            Ok(())
+        }
+        line_webhook::models::Event::PostbackEvent(postback_event) => {
+            let reply_token = postback_event
+                .reply_token
+                .clone()
+                .ok_or(anyhow::anyhow!("failed to extract reply token"))?;
+
+            let postback_request = PostbackRequest {
+                data: postback_event.postback.data.clone(),
+                reply_to: reply_to(
+                    reply_token,
+                    postback_event.source.as_deref(),
+                    postback_event.timestamp,
+                ),
+            };
+            state.postback_service.handle(postback_request).await?;
 
             Ok(())
         }
@@ -48,3 +135,37 @@ fn extract_message(message_event: &line_webhook::models::MessageEvent) -> Option
     }?;
     Some((reply_token, message))
 }
+
+fn reply_to(
+    reply_token: String,
+    source: Option<&line_webhook::models::Source>,
+    timestamp: i64,
+) -> ReplyTo {
+    let source_id = source.and_then(|source| match source {
+        line_webhook::models::Source::UserSource(user) => user.user_id.clone(),
+        line_webhook::models::Source::GroupSource(group) => Some(group.group_id.clone()),
+        line_webhook::models::Source::RoomSource(room) => Some(room.room_id.clone()),
+    });
+    ReplyTo {
+        reply_token,
+        source_id,
+        received_at: DateTime::from_timestamp_millis(timestamp).unwrap_or_else(Utc::now),
+    }
+}
+
+/// Where to answer an event, for the events the bot replies to.
+fn event_reply_to(e: &line_webhook::models::Event) -> Option<ReplyTo> {
+    match e {
+        line_webhook::models::Event::MessageEvent(e) => Some(reply_to(
+            e.reply_token.clone()?,
+            e.source.as_deref(),
+            e.timestamp,
+        )),
+        line_webhook::models::Event::PostbackEvent(e) => Some(reply_to(
+            e.reply_token.clone()?,
+            e.source.as_deref(),
+            e.timestamp,
+        )),
+        _ => None,
+    }
+}
@@ -1,9 +1,77 @@
+use std::time::Duration;
+
 use async_trait::async_trait;
 
 use crate::prelude::*;
 
+/// Recipe data scraped from a web page.
+///
+/// Everything except `name` is optional because most pages only publish a subset of the
+/// schema.org `Recipe` properties.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScrapedRecipe {
+    pub name: String,
+    pub ingredients: Vec<String>,
+    pub instructions: Vec<String>,
+    pub recipe_yield: Option<String>,
+    pub prep_time: Option<Duration>,
+    pub cook_time: Option<Duration>,
+    pub total_time: Option<Duration>,
+    pub image: Option<String>,
+    /// A short summary of the dish, as written by the page.
+    pub description: Option<String>,
+    pub author: Option<String>,
+    pub keywords: Vec<String>,
+    /// URL from `<link rel="canonical">` or `og:url`, as declared by the page.
+    pub canonical_url: Option<String>,
+    /// How the recipe was read from the page.
+    pub strategy: ExtractionStrategy,
+}
+
+/// The ways a recipe can be read from a page, from the most to the least reliable.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExtractionStrategy {
+    /// Rules written for a specific site, named by the site.
+    Site(&'static str),
+    /// The title and description of a video, named by the platform.
+    Video(&'static str),
+    /// A schema.org `Recipe` in a `<script type="application/ld+json">` block.
+    JsonLd,
+    /// A schema.org `Recipe` marked up with `itemscope` and `itemprop` attributes.
+    Microdata,
+    /// The `og:` meta tags, which only describe the page.
+    OpenGraph,
+    /// Only the `<title>` of the page.
+    #[default]
+    Title,
+}
+
+impl ScrapedRecipe {
+    pub fn from_name(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+}
+
 #[cfg_attr(test, mockall::automock)]
 #[async_trait]
 pub trait HtmlClient {
-    async fn get_title(&self, url: &str) -> Result<String>;
+    async fn get_recipe(&self, url: &str) -> Result<ScrapedRecipe>;
+}
+
+/// Reads a recipe from a parsed page.
+///
+/// Extractors are tried in order until one returns a recipe; site-specific ones only for the
+/// hosts they declare.
+pub trait RecipeExtractor {
+    fn strategy(&self) -> ExtractionStrategy;
+
+    /// Whether the extractor applies to pages on the host. Generic extractors apply to all.
+    fn matches(&self, _host: &str) -> bool {
+        true
+    }
+
+    fn extract(&self, dom: &tl::VDom<'_>) -> Option<ScrapedRecipe>;
 }
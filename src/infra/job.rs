@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use crate::{config::JobConfig, prelude::*};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use line_bot_sdk_rust::line_webhook::models::Event;
+use serde::Serialize;
+
+/// A webhook event waiting to be processed in the background.
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub id: ulid::Ulid,
+    pub event: Event,
+    /// Number of times the job already failed.
+    pub attempts: u32,
+    pub enqueued_at: DateTime<Utc>,
+}
+
+impl Job {
+    pub fn new(event: Event) -> Self {
+        Self {
+            id: ulid::Ulid::new(),
+            event,
+            attempts: 0,
+            enqueued_at: Utc::now(),
+        }
+    }
+}
+
+/// A job that used up its attempts and was moved to the dead-letter list.
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedJob {
+    pub id: ulid::Ulid,
+    pub event: Event,
+    pub attempts: u32,
+    pub last_error: String,
+    pub enqueued_at: DateTime<Utc>,
+    pub failed_at: DateTime<Utc>,
+}
+
+/// When failed jobs are retried: exponential backoff up to a number of attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_config(config: &JobConfig) -> Self {
+        Self {
+            max_attempts: config.max_attempts,
+            base_delay: Duration::from_secs(config.retry_base_secs),
+            max_delay: Duration::from_secs(config.retry_max_secs),
+        }
+    }
+
+    /// Returns the delay before retrying a job that failed `attempts` times, or `None` when
+    /// it should be dead-lettered.
+    pub fn next_delay(&self, attempts: u32) -> Option<Duration> {
+        (attempts < self.max_attempts).then(|| {
+            let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+            self.base_delay.saturating_mul(factor).min(self.max_delay)
+        })
+    }
+}
+
+#[cfg_attr(test, automock)]
+#[async_trait]
+pub trait JobQueue {
+    /// Adds a job, failing instead of waiting when the queue is full or closed.
+    async fn enqueue(&self, job: Job) -> Result<()>;
+    /// Waits for the next job. Returns `None` once the queue is closed.
+    async fn dequeue(&self) -> Result<Option<Job>>;
+    /// Marks a dequeued job as done.
+    async fn complete(&self, job: &Job) -> Result<()>;
+    /// Records a failed attempt, scheduling a retry or moving the job to the dead letters. A job
+    /// that should not be retried is dead-lettered at once. Returns `true` when the job was
+    /// dead-lettered.
+    async fn fail(&self, job: &Job, error: &str, retry: bool) -> Result<bool>;
+    /// Lists dead-lettered jobs, most recent failure first.
+    async fn failed_jobs(&self) -> Result<Vec<FailedJob>>;
+    /// Moves a dead-lettered job back to the queue. Returns `false` if there is no such job.
+    async fn requeue(&self, id: ulid::Ulid) -> Result<bool>;
+    /// Stops handing out jobs.
+    async fn close(&self);
+}
+
+#[async_trait]
+pub trait JobHandler {
+    async fn handle(&self, job: &Job) -> Result<()>;
+    /// Called once a job failed for the last time and was moved to the dead letters.
+    async fn on_dead_letter(&self, _job: &Job, _error: &Error) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case(1 => Some(Duration::from_secs(5)); "first failure")]
+    #[test_case(3 => Some(Duration::from_secs(20)); "third failure")]
+    #[test_case(4 => Some(Duration::from_secs(30)); "capped")]
+    #[test_case(5 => None; "gave up")]
+    fn test_next_delay(attempts: u32) -> Option<Duration> {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_secs(5),
+            max_delay: Duration::from_secs(30),
+        };
+        policy.next_delay(attempts)
+    }
+}
@@ -1,13 +1,167 @@
 use crate::prelude::*;
 use async_trait::async_trait;
+use chrono::{DateTime, TimeDelta, Utc};
+use validator::Validate;
+
+/// How long a reply token can be used after the event. LINE allows about a minute, so this
+/// leaves a margin for the request itself.
+const REPLY_TOKEN_LIFETIME: TimeDelta = TimeDelta::seconds(50);
 
 #[derive(Debug, Clone)]
 pub enum LineMessage {
     Text(String),
+    Image {
+        original_content_url: String,
+        preview_image_url: String,
+    },
+    Sticker {
+        package_id: String,
+        sticker_id: String,
+    },
+    /// A template message with up to four buttons.
+    Buttons {
+        alt_text: String,
+        title: Option<String>,
+        text: String,
+        thumbnail_image_url: Option<String>,
+        actions: Vec<LineAction>,
+    },
+    FlexBubble {
+        alt_text: String,
+        bubble: FlexBubble,
+    },
+    FlexCarousel {
+        alt_text: String,
+        bubbles: Vec<FlexBubble>,
+    },
+    /// A message with quick-reply buttons shown above the keyboard.
+    QuickReply {
+        message: Box<LineMessage>,
+        actions: Vec<LineAction>,
+    },
+}
+
+/// A card-style flex bubble: an optional hero image, a bold title, an optional description,
+/// detail lines and a button per action.
+#[derive(Debug, Clone)]
+pub struct FlexBubble {
+    pub image_url: Option<String>,
+    pub title: String,
+    pub description: Option<String>,
+    pub details: Vec<String>,
+    pub actions: Vec<LineAction>,
+    /// Parts of the title and details to emphasize, e.g. the words a search matched.
+    pub highlights: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum LineAction {
+    Uri {
+        label: String,
+        uri: String,
+    },
+    /// Sends `data` back to the webhook as a postback event.
+    Postback {
+        label: String,
+        data: String,
+        /// Text shown in the chat as if the user sent it.
+        display_text: Option<String>,
+        /// Text put in the input field, opening the keyboard.
+        fill_in_text: Option<String>,
+    },
+}
+
+/// Where the response to a LINE event is sent.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Validate)]
+pub struct ReplyTo {
+    #[validate(length(min = 1))]
+    pub reply_token: String,
+    /// User, group or room the event came from, pushed to once the reply token expired.
+    pub source_id: Option<String>,
+    pub received_at: DateTime<Utc>,
+}
+
+impl ReplyTo {
+    pub fn new(reply_token: impl Into<String>) -> Self {
+        Self {
+            reply_token: reply_token.into(),
+            source_id: None,
+            received_at: Utc::now(),
+        }
+    }
+
+    /// Returns the push destination when the reply token can no longer be used.
+    fn push_target(&self, now: DateTime<Utc>) -> Option<&str> {
+        (now - self.received_at > REPLY_TOKEN_LIFETIME)
+            .then_some(self.source_id.as_deref())
+            .flatten()
+    }
 }
 
 #[cfg_attr(test, automock)]
 #[async_trait]
 pub trait LineClient {
     async fn reply_messages(&self, token: &str, message: Vec<LineMessage>) -> Result<()>;
+    async fn push_messages(&self, to: &str, message: Vec<LineMessage>) -> Result<()>;
+}
+
+/// Sends messages in response to an event, replying while the reply token is valid and
+/// pushing them afterwards.
+pub async fn respond(
+    line_client: &(dyn LineClient + Send + Sync),
+    reply_to: &ReplyTo,
+    messages: Vec<LineMessage>,
+) -> Result<()> {
+    match reply_to.push_target(Utc::now()) {
+        Some(to) => line_client.push_messages(to, messages).await,
+        None => {
+            line_client
+                .reply_messages(&reply_to.reply_token, messages)
+                .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_respond_pushes_after_reply_window() {
+        let mut line_client = MockLineClient::new();
+        line_client.expect_reply_messages().never();
+        line_client
+            .expect_push_messages()
+            .with(eq("U123"), always())
+            .times(1)
+            .returning(|_, _| Ok(()));
+
+        let reply_to = ReplyTo {
+            reply_token: "reply_token".to_string(),
+            source_id: Some("U123".to_string()),
+            received_at: Utc::now() - TimeDelta::minutes(5),
+        };
+
+        let result = respond(&line_client, &reply_to, vec![]).await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_respond_replies_within_reply_window() {
+        let mut line_client = MockLineClient::new();
+        line_client
+            .expect_reply_messages()
+            .with(eq("reply_token"), always())
+            .times(1)
+            .returning(|_, _| Ok(()));
+        line_client.expect_push_messages().never();
+
+        let reply_to = ReplyTo {
+            source_id: Some("U123".to_string()),
+            ..ReplyTo::new("reply_token")
+        };
+
+        let result = respond(&line_client, &reply_to, vec![]).await;
+        assert!(result.is_ok());
+    }
 }
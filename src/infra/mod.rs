@@ -1,5 +1,8 @@
+pub mod dedup;
 pub(crate) mod handler;
 pub mod html;
+pub mod job;
 pub mod line;
 pub mod repository;
 pub mod server;
+pub mod sync;
@@ -1,8 +1,108 @@
-use crate::{domain::recipe::Recipe, prelude::*};
+use std::collections::HashSet;
+
+use crate::{
+    domain::{
+        recipe::{Recipe, RecipeField},
+        search::{SearchHit, SearchQuery, rank},
+    },
+    prelude::*,
+};
 use async_trait::async_trait;
 
+/// Number of recent recipes the default [`RecipeRepository::search_text`] ranks besides the
+/// matches of [`RecipeRepository::search`].
+const TEXT_SEARCH_POOL: usize = 100;
+
 #[cfg_attr(test, automock)]
 #[async_trait]
 pub trait RecipeRepository {
-    async fn insert_recipe(&self, recipe: Recipe) -> Result<()>;
+    /// Stores a recipe and returns it as stored, e.g. with its page URL filled in.
+    async fn insert_recipe(&self, recipe: Recipe) -> Result<Recipe>;
+    async fn get_recipe(&self, id: ulid::Ulid) -> Result<Option<Recipe>>;
+    /// Looks up a recipe by its canonical URL.
+    async fn find_by_url(&self, recipe_url: &url::Url) -> Result<Option<Recipe>>;
+    /// Finds recipes whose name or tags contain the query.
+    async fn search(&self, query: &str) -> Result<Vec<Recipe>>;
+    /// Finds recipes whose name, tags, ingredients or steps contain every word of the query,
+    /// best matches first, comparing text as folded by [`crate::domain::search`].
+    ///
+    /// Backends without a full-text index rank the results of [`Self::search`] together with
+    /// their most recent recipes, so older recipes are only found by their name or tags as
+    /// written.
+    async fn search_text(&self, query: &SearchQuery, limit: usize) -> Result<Vec<SearchHit>> {
+        let mut recipes = self.search(&query.text).await?;
+        let found = recipes
+            .iter()
+            .map(|recipe| recipe.id)
+            .collect::<HashSet<_>>();
+        recipes.extend(
+            self.list_recipes(TEXT_SEARCH_POOL)
+                .await?
+                .into_iter()
+                .filter(|recipe| !found.contains(&recipe.id)),
+        );
+        Ok(rank(query, recipes, limit))
+    }
+    /// Returns up to `limit` recipes, most recently saved first.
+    async fn list_recipes(&self, limit: usize) -> Result<Vec<Recipe>>;
+    /// Whether the field is kept by [`Self::update_recipe`]. Backends storing every field keep
+    /// the default.
+    fn stores(&self, _field: RecipeField) -> bool {
+        true
+    }
+    /// Overwrites the stored fields of an existing recipe.
+    async fn update_recipe(&self, recipe: Recipe) -> Result<Recipe>;
+    /// Appends a free-form note to a recipe.
+    async fn add_note(&self, id: ulid::Ulid, note: &str) -> Result<()>;
+    async fn delete_recipe(&self, id: ulid::Ulid) -> Result<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::libs::repository::{
+        conformance::recipe_repository_conformance, memory::MemoryRecipeRepository,
+    };
+
+    use super::*;
+
+    /// Implements only the required methods, like the Notion repository, so the default
+    /// full-text search is checked.
+    struct WithoutTextIndex(MemoryRecipeRepository);
+
+    #[async_trait]
+    impl RecipeRepository for WithoutTextIndex {
+        async fn insert_recipe(&self, recipe: Recipe) -> Result<Recipe> {
+            self.0.insert_recipe(recipe).await
+        }
+
+        async fn get_recipe(&self, id: ulid::Ulid) -> Result<Option<Recipe>> {
+            self.0.get_recipe(id).await
+        }
+
+        async fn find_by_url(&self, recipe_url: &url::Url) -> Result<Option<Recipe>> {
+            self.0.find_by_url(recipe_url).await
+        }
+
+        async fn search(&self, query: &str) -> Result<Vec<Recipe>> {
+            self.0.search(query).await
+        }
+
+        async fn list_recipes(&self, limit: usize) -> Result<Vec<Recipe>> {
+            self.0.list_recipes(limit).await
+        }
+
+        async fn update_recipe(&self, recipe: Recipe) -> Result<Recipe> {
+            self.0.update_recipe(recipe).await
+        }
+
+        async fn add_note(&self, id: ulid::Ulid, note: &str) -> Result<()> {
+            self.0.add_note(id, note).await
+        }
+
+        async fn delete_recipe(&self, id: ulid::Ulid) -> Result<()> {
+            self.0.delete_recipe(id).await
+        }
+    }
+
+    recipe_repository_conformance!(WithoutTextIndex(MemoryRecipeRepository::default()));
 }
@@ -0,0 +1,13 @@
+use chrono::{DateTime, Utc};
+
+use crate::{domain::recipe::Recipe, prelude::*};
+use async_trait::async_trait;
+
+/// Reads the recipes changed in the store that the local mirror follows.
+#[cfg_attr(test, automock)]
+#[async_trait]
+pub trait RecipeChangeFeed {
+    /// Returns the recipes created or edited at or after `since`, least recently edited first,
+    /// or every recipe without `since`.
+    async fn recipes_edited_since(&self, since: Option<DateTime<Utc>>) -> Result<Vec<Recipe>>;
+}
@@ -0,0 +1,90 @@
+use std::sync::Arc;
+
+use axum::{
+    Json, Router,
+    extract::{Path, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use hmac::digest::CtOutput;
+use http::{StatusCode, header::AUTHORIZATION};
+
+use crate::{infra::job::FailedJob, libs::axum::server::AppState, prelude::*};
+
+/// Routes for operating the job queue, guarded by the configured admin token.
+pub(crate) fn router(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/admin/jobs/failed", get(list_failed_jobs))
+        .route("/admin/jobs/failed/{id}/requeue", post(requeue_failed_job))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state,
+            verify_admin_token,
+        ))
+}
+
+async fn list_failed_jobs(
+    State(state): State<Arc<AppState>>,
+) -> std::result::Result<Json<Vec<FailedJob>>, Error> {
+    Ok(Json(state.job_queue.failed_jobs().await?))
+}
+
+async fn requeue_failed_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> std::result::Result<Response, Error> {
+    let Ok(id) = ulid::Ulid::from_string(&id) else {
+        return Ok((StatusCode::BAD_REQUEST, "invalid job id").into_response());
+    };
+    if state.job_queue.requeue(id).await? {
+        tracing::info!(%id, "requeued failed job");
+        Ok(StatusCode::NO_CONTENT.into_response())
+    } else {
+        Ok(StatusCode::NOT_FOUND.into_response())
+    }
+}
+
+/// Rejects requests without the admin token. The endpoints do not exist when no token is set.
+async fn verify_admin_token(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    request: Request,
+    next: Next,
+) -> std::result::Result<Response, StatusCode> {
+    let Some(admin_token) = state.config.admin_token.as_deref() else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let bearer = headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    match bearer {
+        Some(token) if token_matches(token, admin_token) => Ok(next.run(request).await),
+        _ => Err(StatusCode::UNAUTHORIZED),
+    }
+}
+
+/// Compares in constant time so the token cannot be guessed from response times.
+fn token_matches(token: &str, admin_token: &str) -> bool {
+    let digest = |value: &str| -> CtOutput<sha2::Sha256> {
+        use sha2::Digest;
+        CtOutput::new(sha2::Sha256::digest(value.as_bytes()))
+    };
+    digest(token) == digest(admin_token)
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case("secret", "secret" => true; "same token")]
+    #[test_case("secret ", "secret" => false; "trailing space")]
+    #[test_case("", "secret" => false; "empty token")]
+    #[test_case("Secret", "secret" => false; "different case")]
+    fn test_token_matches(token: &str, admin_token: &str) -> bool {
+        token_matches(token, admin_token)
+    }
+}
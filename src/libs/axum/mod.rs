@@ -1,3 +1,4 @@
+pub(crate) mod admin;
 mod line;
 pub(crate) mod recipe;
 pub(crate) mod server;
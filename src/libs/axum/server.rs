@@ -7,81 +7,251 @@ use axum::{
     extract::State,
     response::{IntoResponse, Response},
 };
-use http::StatusCode;
-use line_bot_sdk_rust::line_webhook::models::CallbackRequest;
-use tokio::{
-    net::TcpListener,
-    task::{self, JoinHandle},
-};
+use line_bot_sdk_rust::line_webhook::models::{CallbackRequest, Event};
+use tokio::net::TcpListener;
 use tower_http::trace::TraceLayer;
 
 use crate::{
-    app::{echo::EchoService, recipe::RecipeService},
-    config::AppConfig,
-    infra::handler::handle_event,
+    app::{
+        command::CommandService,
+        postback::{PostbackCodec, PostbackService},
+        recipe::RecipeService,
+    },
+    config::{AppConfig, DedupBackend, JobBackend, RepositoryBackend},
+    error::ErrorCategory,
+    infra::{
+        dedup::{EventDeduplicator, delivery},
+        handler::{EventHandler, is_supported_event},
+        html::HtmlClient,
+        job::{Job, JobQueue, RetryPolicy},
+        line::LineClient,
+        repository::recipe::RecipeRepository,
+    },
     libs::{
+        dedup::{memory::MemoryEventDeduplicator, sqlite::SqliteEventDeduplicator},
+        fetch::Fetcher,
+        job::{memory::MemoryJobQueue, sqlite::SqliteJobQueue, worker::WorkerPool},
         line::client::LineClientImpl,
         notion::{client::NotionClient, recipe::RecipeRepositoryImpl},
+        repository::{memory::MemoryRecipeRepository, sqlite::SqliteRecipeRepository},
         reqwest::ReqwestClient,
+        sync::{store::SyncStore, syncer::RecipeSyncer},
     },
     prelude::*,
 };
 
-use super::line::middleware::verify_line_signature;
+use super::{admin, line::middleware::verify_line_signature};
 
 pub struct HttpServer {
     app_state: Arc<AppState>,
+    /// Keeps the local mirror of Notion up to date when `sync.enabled` is set.
+    recipe_syncer: Option<Arc<RecipeSyncer>>,
 }
 
 #[derive(Clone)]
 pub struct AppState {
     pub config: AppConfig,
-    pub echo_service: EchoService,
+    pub command_service: CommandService,
+    pub postback_service: PostbackService,
+    pub line_client: Arc<dyn LineClient + Send + Sync>,
+    pub job_queue: Arc<dyn JobQueue + Send + Sync>,
+    pub event_deduplicator: Arc<dyn EventDeduplicator + Send + Sync>,
     pub recipe_service: RecipeService,
 }
 
+impl AppState {
+    pub fn new(
+        config: AppConfig,
+        recipe_repository: Arc<dyn RecipeRepository + Send + Sync>,
+        line_client: Arc<dyn LineClient + Send + Sync>,
+        html_client: Arc<dyn HtmlClient + Send + Sync>,
+        job_queue: Arc<dyn JobQueue + Send + Sync>,
+        event_deduplicator: Arc<dyn EventDeduplicator + Send + Sync>,
+    ) -> Self {
+        let postback_codec = PostbackCodec::new(config.line_channel_secret.clone());
+        let recipe_service = RecipeService::new(
+            recipe_repository,
+            line_client.clone(),
+            html_client,
+            postback_codec.clone(),
+        );
+        Self {
+            config,
+            command_service: CommandService::new(recipe_service.clone(), line_client.clone()),
+            postback_service: PostbackService::new(
+                recipe_service.clone(),
+                postback_codec,
+                line_client.clone(),
+            ),
+            line_client,
+            job_queue,
+            event_deduplicator,
+            recipe_service,
+        }
+    }
+}
+
 impl HttpServer {
-    pub fn new(config: AppConfig) -> Self {
+    pub async fn new(config: AppConfig) -> Result<Self> {
+        // A full sync would drop the recipes of the `sqlite` backend missing from Notion.
+        if config.sync.enabled && config.sync.database_path == config.repository.database_path {
+            return Err(Error::Generic(
+                "`sync.database_path` must differ from `repository.database_path`".to_string(),
+            ));
+        }
         let line_client = LineClientImpl::new(config.line_channel_access_token.clone());
-        let notion_client =
-            NotionClient::from_api_key(config.notion_integration_token.clone()).unwrap();
-        let recipe_repository =
-            RecipeRepositoryImpl::new(Arc::new(notion_client), config.notion_database_id.clone());
+        let mut recipe_syncer = None;
+        let recipe_repository: Arc<dyn RecipeRepository + Send + Sync> =
+            match config.repository.backend {
+                RepositoryBackend::Notion => {
+                    let notion_client =
+                        NotionClient::from_api_key(config.notion_integration_token.clone())?;
+                    let recipe_repository = RecipeRepositoryImpl::new(
+                        Arc::new(notion_client),
+                        config.notion_database_id.clone(),
+                        config.notion_properties.clone(),
+                    );
+                    recipe_repository
+                        .verify_schema(config.notion_bootstrap_schema)
+                        .await?;
+                    let recipe_repository = Arc::new(recipe_repository);
+                    if config.sync.enabled {
+                        let syncer = Arc::new(RecipeSyncer::new(
+                            recipe_repository.clone(),
+                            recipe_repository,
+                            Arc::new(SqliteRecipeRepository::open(&config.sync.database_path)?),
+                            Arc::new(SyncStore::open(&config.sync.database_path)?),
+                            &config.sync,
+                        ));
+                        let synced_repository = syncer.repository();
+                        recipe_syncer = Some(syncer);
+                        Arc::new(synced_repository)
+                    } else {
+                        recipe_repository
+                    }
+                }
+                RepositoryBackend::Sqlite => Arc::new(SqliteRecipeRepository::open(
+                    &config.repository.database_path,
+                )?),
+                RepositoryBackend::Memory => Arc::new(MemoryRecipeRepository::default()),
+            };
+
+        let retry_policy = RetryPolicy::from_config(&config.jobs);
+        let job_queue: Arc<dyn JobQueue + Send + Sync> = match config.jobs.backend {
+            JobBackend::Memory => Arc::new(MemoryJobQueue::new(
+                config.jobs.queue_capacity,
+                retry_policy,
+            )),
+            JobBackend::Sqlite => Arc::new(SqliteJobQueue::open(
+                &config.jobs.database_path,
+                config.jobs.queue_capacity,
+                retry_policy,
+            )?),
+        };
 
-        let app_state = Arc::new(AppState {
+        let dedup_ttl = std::time::Duration::from_secs(config.dedup.ttl_secs);
+        let event_deduplicator: Arc<dyn EventDeduplicator + Send + Sync> =
+            match config.dedup.backend {
+                DedupBackend::Memory => Arc::new(MemoryEventDeduplicator::new(dedup_ttl)),
+                DedupBackend::Sqlite => Arc::new(SqliteEventDeduplicator::open(
+                    &config.dedup.database_path,
+                    dedup_ttl,
+                )?),
+            };
+
+        let html_client = ReqwestClient::new(Fetcher::from_config(&config.fetch)?);
+
+        let app_state = Arc::new(AppState::new(
             config,
-            echo_service: EchoService::new(Arc::new(line_client.clone())),
-            recipe_service: RecipeService::new(
-                Arc::new(recipe_repository),
-                Arc::new(line_client.clone()),
-                Arc::new(ReqwestClient::default()),
-            ),
-        });
-        Self { app_state }
+            recipe_repository,
+            Arc::new(line_client),
+            Arc::new(html_client),
+            job_queue,
+            event_deduplicator,
+        ));
+        Ok(Self {
+            app_state,
+            recipe_syncer,
+        })
+    }
+
+    /// Queues an event unless it was received before.
+    async fn enqueue_event(state: &AppState, e: &Event) -> Result<()> {
+        let Some((event_id, is_redelivery)) = delivery(e) else {
+            return state.job_queue.enqueue(Job::new(e.clone())).await;
+        };
+        if !state.event_deduplicator.claim(event_id).await? {
+            tracing::info!(
+                event_id,
+                is_redelivery,
+                "skipped an event that was already received"
+            );
+            return Ok(());
+        }
+        if let Err(error) = state.job_queue.enqueue(Job::new(e.clone())).await {
+            // LINE redelivers the event after a failed response, so it must not be skipped.
+            state.event_deduplicator.release(event_id).await?;
+            return Err(error);
+        }
+        Ok(())
     }
 
+    /// Queues the events and responds right away; workers process them afterwards.
     async fn post_callback(
         State(state): State<Arc<AppState>>,
         request: axum::Json<CallbackRequest>,
     ) -> std::result::Result<Response, Error> {
-        let mut handles = Vec::new();
-        for e in request.events.iter() {
-            let e = e.clone();
-
-            let state = state.clone();
-            let handle: JoinHandle<Result<()>> =
-                task::spawn(async move { handle_event(state, e).await });
-            handles.push(handle);
+        // Each event is queued on its own so one failure does not fail the others. LINE
+        // redelivers the whole batch, and the events queued already are then skipped.
+        let mut failure = None;
+        for e in request.events.iter().filter(|e| is_supported_event(e)) {
+            if let Err(error) = Self::enqueue_event(&state, e).await {
+                tracing::error!(
+                    event_id = delivery(e).map(|(event_id, _)| event_id),
+                    error_class = error.kind(),
+                    %error,
+                    "failed to queue event"
+                );
+                failure.get_or_insert(error);
+            }
         }
-
-        for handle in handles {
-            handle.await.with_context(|| "failed to handle event")??;
+        if let Some(error) = failure {
+            return Err(error);
         }
 
         Ok(().into_response())
     }
 }
 
+/// Resolves on Ctrl+C or, on Unix, SIGTERM as sent by Cloud Run.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!(%e, "failed to listen for ctrl+c");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!(%e, "failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
 #[async_trait]
 impl crate::infra::server::Server for HttpServer {
     async fn run(&self) -> Result<()> {
@@ -102,16 +272,222 @@ impl crate::infra::server::Server for HttpServer {
             ))
             .layer(TraceLayer::new_for_http())
             .route("/", axum::routing::post(Self::post_callback))
+            .merge(admin::router(self.app_state.clone()))
             .with_state(self.app_state.clone());
 
-        axum::serve(listener, app).await?;
+        let workers = tokio::spawn(
+            WorkerPool::new(
+                self.app_state.job_queue.clone(),
+                Arc::new(EventHandler::new(self.app_state.clone())),
+                &self.app_state.config.jobs,
+            )
+            .run(),
+        );
+
+        let sync_task = self
+            .recipe_syncer
+            .clone()
+            .map(|syncer| tokio::spawn(syncer.run()));
+
+        axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown_signal())
+            .await?;
+
+        // Writes not pushed yet stay in the sync store and are pushed after the restart.
+        if let Some(sync_task) = sync_task {
+            sync_task.abort();
+        }
+        tracing::info!("shutting down, finishing queued jobs");
+        self.app_state.job_queue.close().await;
+        let shutdown_timeout =
+            std::time::Duration::from_secs(self.app_state.config.jobs.shutdown_timeout_secs);
+        if tokio::time::timeout(shutdown_timeout, workers)
+            .await
+            .is_err()
+        {
+            tracing::warn!("jobs did not finish before the shutdown timeout");
+        }
         Ok(())
     }
 }
 
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
-        tracing::error!(%self, "failed to handle request");
-        (StatusCode::INTERNAL_SERVER_ERROR, "something went wrong").into_response()
+        let category = self.category();
+        if category == ErrorCategory::Internal {
+            tracing::error!(%self, error_class = self.kind(), "failed to handle request");
+        } else {
+            tracing::warn!(%self, ?category, "failed to handle request");
+        }
+        (category.status_code(), category.message()).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use http::StatusCode;
+    use line_bot_sdk_rust::line_webhook::models::Event;
+    use serde_json::json;
+
+    use crate::{
+        infra::{html::ScrapedRecipe, line::LineMessage},
+        libs::{
+            fake::{FakeHtmlClient, FakeLineClient, app_config},
+            job::memory::tests::retry_policy,
+        },
+    };
+
+    use super::*;
+
+    const RECIPE_URL: &str = "https://example.com/recipe/1";
+
+    fn message_event(reply_token: &str, text: &str) -> Event {
+        serde_json::from_value(json!({
+            "type": "message",
+            "mode": "active",
+            "timestamp": chrono::Utc::now().timestamp_millis(),
+            "source": { "type": "user", "userId": "U4af4980629" },
+            "webhookEventId": "01FZ74A0TDDPYRVKNK77XKC3ZR",
+            "deliveryContext": { "isRedelivery": false },
+            "replyToken": reply_token,
+            "message": { "id": "444573844083572737", "type": "text", "quoteToken": "q3Plxr4AgKd", "text": text },
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_refuses_mirror_sharing_the_repository_file() {
+        let mut config = app_config();
+        config.sync.enabled = true;
+        config.sync.database_path = config.repository.database_path.clone();
+
+        assert!(HttpServer::new(config).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_saves_recipe_after_retrying_failed_fetch() {
+        let recipe_repository = Arc::new(MemoryRecipeRepository::default());
+        let line_client = Arc::new(FakeLineClient::default());
+        let html_client = FakeHtmlClient::default()
+            .with_page(RECIPE_URL, ScrapedRecipe::from_name("肉じゃが"))
+            .failing(1);
+        let job_queue = Arc::new(SqliteJobQueue::open_in_memory(10, retry_policy(3)).unwrap());
+        let state = Arc::new(AppState::new(
+            app_config(),
+            recipe_repository.clone(),
+            line_client.clone(),
+            Arc::new(html_client),
+            job_queue.clone(),
+            Arc::new(MemoryEventDeduplicator::new(Duration::from_secs(60))),
+        ));
+        let workers = tokio::spawn(
+            WorkerPool::new(
+                job_queue.clone(),
+                Arc::new(EventHandler::new(state.clone())),
+                &state.config.jobs,
+            )
+            .run(),
+        );
+
+        job_queue
+            .enqueue(Job::new(message_event("reply_token", RECIPE_URL)))
+            .await
+            .unwrap();
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while line_client.replies.lock().unwrap().is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the job was not retried");
+        job_queue.close().await;
+        workers.await.unwrap();
+
+        assert!(job_queue.failed_jobs().await.unwrap().is_empty());
+        let recipes = recipe_repository.list_recipes(10).await.unwrap();
+        assert_eq!(recipes.len(), 1);
+        assert_eq!(recipes[0].name, "肉じゃが");
+    }
+
+    #[tokio::test]
+    async fn test_replies_when_event_fails_without_affecting_others() {
+        let recipe_repository = Arc::new(MemoryRecipeRepository::default());
+        let line_client = Arc::new(FakeLineClient::default());
+        let html_client =
+            FakeHtmlClient::default().with_page(RECIPE_URL, ScrapedRecipe::from_name("肉じゃが"));
+        let job_queue = Arc::new(MemoryJobQueue::new(10, retry_policy(1)));
+        let state = Arc::new(AppState::new(
+            app_config(),
+            recipe_repository.clone(),
+            line_client.clone(),
+            Arc::new(html_client),
+            job_queue.clone(),
+            Arc::new(MemoryEventDeduplicator::new(Duration::from_secs(60))),
+        ));
+
+        for (reply_token, url) in [
+            ("missing", "https://example.com/missing"),
+            ("found", RECIPE_URL),
+        ] {
+            job_queue
+                .enqueue(Job::new(message_event(reply_token, url)))
+                .await
+                .unwrap();
+        }
+        job_queue.close().await;
+        WorkerPool::new(
+            job_queue.clone(),
+            Arc::new(EventHandler::new(state.clone())),
+            &state.config.jobs,
+        )
+        .run()
+        .await;
+
+        assert_eq!(job_queue.failed_jobs().await.unwrap().len(), 1);
+        assert_eq!(recipe_repository.list_recipes(10).await.unwrap().len(), 1);
+        let replies = line_client.replies.lock().unwrap();
+        let (_, messages) = replies
+            .iter()
+            .find(|(reply_token, _)| reply_token == "missing")
+            .unwrap();
+        assert!(
+            matches!(&messages[..], [LineMessage::Text(text)] if text == "このページからレシピを取得できませんでした")
+        );
+        assert!(
+            replies
+                .iter()
+                .any(|(reply_token, _)| reply_token == "found")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_post_callback_skips_redelivered_events() {
+        let job_queue = Arc::new(MemoryJobQueue::new(10, retry_policy(1)));
+        let state = Arc::new(AppState::new(
+            app_config(),
+            Arc::new(MemoryRecipeRepository::default()),
+            Arc::new(FakeLineClient::default()),
+            Arc::new(FakeHtmlClient::default()),
+            job_queue.clone(),
+            Arc::new(MemoryEventDeduplicator::new(Duration::from_secs(60))),
+        ));
+        let mut request: serde_json::Value =
+            serde_json::from_str(include_str!("../../../tests/sample-req.json")).unwrap();
+        let delivery = serde_json::from_value::<CallbackRequest>(request.clone()).unwrap();
+        request["events"][0]["deliveryContext"]["isRedelivery"] = json!(true);
+        let redelivery = serde_json::from_value::<CallbackRequest>(request).unwrap();
+
+        for request in [delivery, redelivery] {
+            let response = HttpServer::post_callback(State(state.clone()), axum::Json(request))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        job_queue.close().await;
+        assert!(job_queue.dequeue().await.unwrap().is_some());
+        assert!(job_queue.dequeue().await.unwrap().is_none());
     }
 }
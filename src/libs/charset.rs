@@ -0,0 +1,131 @@
+use encoding_rs::{EUC_JP, Encoding, SHIFT_JIS, UTF_8};
+
+/// How far into the body `<meta>` declarations are looked for, as browsers do.
+const META_PRESCAN_BYTES: usize = 1024;
+
+/// Decodes an HTML page, taking the charset from the `Content-Type` header, a BOM, a
+/// `<meta>` declaration or, when none of them is present, guessing between the encodings
+/// used by Japanese sites.
+pub fn decode_html(body: &[u8], content_type: Option<&str>) -> String {
+    let encoding = content_type
+        .and_then(charset_param)
+        .and_then(|label| Encoding::for_label(label.as_bytes()))
+        .or_else(|| Encoding::for_bom(body).map(|(encoding, _)| encoding))
+        .or_else(|| meta_charset(body))
+        .unwrap_or_else(|| guess_encoding(body));
+    // `decode` also strips a BOM, which takes precedence over a mismatching declaration.
+    let (text, encoding, had_errors) = encoding.decode(body);
+    if had_errors {
+        tracing::debug!(
+            encoding = encoding.name(),
+            "page contains malformed characters"
+        );
+    }
+    text.into_owned()
+}
+
+/// Returns the `charset` parameter of a `Content-Type` value.
+fn charset_param(content_type: &str) -> Option<&str> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches(['"', '\'']))
+    })
+}
+
+/// Finds `<meta charset>` or `<meta http-equiv="Content-Type" content="...; charset=...">`
+/// at the start of the page.
+fn meta_charset(body: &[u8]) -> Option<&'static Encoding> {
+    let head = &body[..body.len().min(META_PRESCAN_BYTES)];
+    // Declarations are ASCII, so the bytes of any ASCII-compatible encoding can be searched.
+    let head = String::from_utf8_lossy(head).to_ascii_lowercase();
+    head.match_indices("<meta")
+        .filter_map(|(start, _)| {
+            let tag = &head[start..];
+            let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+            let value = &tag[tag.find("charset")? + "charset".len()..];
+            let value = value.trim_start().strip_prefix('=')?.trim_start();
+            let value = value.trim_start_matches(['"', '\'']);
+            let end = value
+                .find(|c: char| matches!(c, '"' | '\'' | ';' | '/' | '>') || c.is_whitespace())
+                .unwrap_or(value.len());
+            Encoding::for_label(value[..end].as_bytes())
+        })
+        .next()
+        // A page read as ASCII cannot be UTF-16, whatever it declares.
+        .map(|encoding| encoding.output_encoding())
+}
+
+/// Picks the first encoding the body is valid in. EUC-JP is tried before Shift_JIS because
+/// EUC-JP text is usually also valid, though garbled, Shift_JIS.
+fn guess_encoding(body: &[u8]) -> &'static Encoding {
+    [UTF_8, EUC_JP, SHIFT_JIS]
+        .into_iter()
+        .find(|encoding| {
+            encoding
+                .decode_without_bom_handling_and_without_replacement(body)
+                .is_some()
+        })
+        .unwrap_or(UTF_8)
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    const SHIFT_JIS_PAGE: &[u8] = include_bytes!("../../tests/fixtures/shift_jis.html");
+    const EUC_JP_PAGE: &[u8] = include_bytes!("../../tests/fixtures/euc_jp.html");
+    const UTF8_PAGE: &[u8] = include_bytes!("../../tests/fixtures/utf8.html");
+
+    fn title(html: &str) -> &str {
+        let start = html.find("<title>").unwrap() + "<title>".len();
+        &html[start..html.find("</title>").unwrap()]
+    }
+
+    #[test_case(SHIFT_JIS_PAGE, None => "肉じゃがの作り方 - おばあちゃんの台所"; "shift_jis meta http-equiv")]
+    #[test_case(EUC_JP_PAGE, None => "筑前煮のレシピ｜家庭料理の本棚"; "euc-jp meta charset")]
+    #[test_case(UTF8_PAGE, None => "鶏の照り焼き レシピ・作り方"; "utf-8 without declaration")]
+    #[test_case(SHIFT_JIS_PAGE, Some("text/html; charset=Shift_JIS") => "肉じゃがの作り方 - おばあちゃんの台所"; "shift_jis header")]
+    #[test_case(EUC_JP_PAGE, Some("text/html") => "筑前煮のレシピ｜家庭料理の本棚"; "header without charset")]
+    #[test_case(UTF8_PAGE, Some("text/html; charset=\"utf-8\"") => "鶏の照り焼き レシピ・作り方"; "quoted header charset")]
+    fn test_decode_html_fixtures(body: &[u8], content_type: Option<&str>) -> String {
+        title(&decode_html(body, content_type)).to_string()
+    }
+
+    #[test]
+    fn test_decode_html_header_overrides_meta() {
+        let body = "<meta charset=\"shift_jis\"><title>肉じゃが</title>".as_bytes();
+        assert_eq!(
+            title(&decode_html(body, Some("text/html; charset=utf-8"))),
+            "肉じゃが"
+        );
+    }
+
+    #[test]
+    fn test_decode_html_bom() {
+        let body = [
+            b"\xEF\xBB\xBF".as_slice(),
+            "<title>肉じゃが</title>".as_bytes(),
+        ]
+        .concat();
+        assert_eq!(title(&decode_html(&body, None)), "肉じゃが");
+    }
+
+    #[test]
+    fn test_decode_html_guesses_undeclared_encodings() {
+        let (shift_jis, _, _) = SHIFT_JIS.encode("<title>肉じゃがの作り方</title>");
+        assert_eq!(title(&decode_html(&shift_jis, None)), "肉じゃがの作り方");
+        let (euc_jp, _, _) = EUC_JP.encode("<title>筑前煮のレシピ</title>");
+        assert_eq!(title(&decode_html(&euc_jp, None)), "筑前煮のレシピ");
+    }
+
+    #[test_case("text/html; charset=Shift_JIS" => Some("Shift_JIS"); "plain")]
+    #[test_case("text/html;charset='euc-jp'" => Some("euc-jp"); "quoted")]
+    #[test_case("text/html" => None; "missing")]
+    fn test_charset_param(content_type: &str) -> Option<&str> {
+        charset_param(content_type)
+    }
+}
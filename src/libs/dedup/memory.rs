@@ -0,0 +1,107 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use tokio::time::Instant;
+
+use crate::{infra::dedup::EventDeduplicator, prelude::*};
+
+/// Keeps event IDs in memory for `ttl`. IDs are forgotten when the process exits.
+pub struct MemoryEventDeduplicator {
+    ttl: Duration,
+    seen: Mutex<Seen>,
+}
+
+#[derive(Default)]
+struct Seen {
+    claimed_at: HashMap<String, Instant>,
+    /// Claims in the order they were made, so expired ones are dropped from the front.
+    order: VecDeque<(Instant, String)>,
+}
+
+impl Seen {
+    fn expire(&mut self, now: Instant, ttl: Duration) {
+        while let Some((claimed_at, _)) = self.order.front() {
+            if now.duration_since(*claimed_at) < ttl {
+                break;
+            }
+            let (claimed_at, event_id) = self.order.pop_front().expect("front was just checked");
+            // The ID may have been released and claimed again since.
+            if self.claimed_at.get(&event_id) == Some(&claimed_at) {
+                self.claimed_at.remove(&event_id);
+            }
+        }
+    }
+}
+
+impl MemoryEventDeduplicator {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            seen: Mutex::new(Seen::default()),
+        }
+    }
+}
+
+#[async_trait]
+impl EventDeduplicator for MemoryEventDeduplicator {
+    async fn claim(&self, event_id: &str) -> Result<bool> {
+        let now = Instant::now();
+        let mut seen = self.seen.lock().expect("deduplicator lock is poisoned");
+        seen.expire(now, self.ttl);
+        if seen.claimed_at.contains_key(event_id) {
+            return Ok(false);
+        }
+        seen.claimed_at.insert(event_id.to_string(), now);
+        seen.order.push_back((now, event_id.to_string()));
+        Ok(true)
+    }
+
+    async fn release(&self, event_id: &str) -> Result<()> {
+        self.seen
+            .lock()
+            .expect("deduplicator lock is poisoned")
+            .claimed_at
+            .remove(event_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EVENT_ID: &str = "01FZ74A0TDDPYRVKNK77XKC3ZR";
+
+    #[tokio::test(start_paused = true)]
+    async fn test_claim_until_ttl_expires() {
+        let deduplicator = MemoryEventDeduplicator::new(Duration::from_secs(60));
+        assert!(deduplicator.claim(EVENT_ID).await.unwrap());
+        assert!(!deduplicator.claim(EVENT_ID).await.unwrap());
+        assert!(
+            deduplicator
+                .claim("01FZ74A0TDDPYRVKNK77XKC3ZS")
+                .await
+                .unwrap()
+        );
+
+        tokio::time::advance(Duration::from_secs(61)).await;
+        assert!(deduplicator.claim(EVENT_ID).await.unwrap());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_release_allows_claiming_again() {
+        let deduplicator = MemoryEventDeduplicator::new(Duration::from_secs(60));
+        assert!(deduplicator.claim(EVENT_ID).await.unwrap());
+        deduplicator.release(EVENT_ID).await.unwrap();
+
+        tokio::time::advance(Duration::from_secs(30)).await;
+        assert!(deduplicator.claim(EVENT_ID).await.unwrap());
+        // The expired first claim must not drop the second one.
+        tokio::time::advance(Duration::from_secs(40)).await;
+        assert!(!deduplicator.claim(EVENT_ID).await.unwrap());
+    }
+}
@@ -0,0 +1,115 @@
+use std::{path::Path, time::Duration};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use rusqlite::{Connection, params};
+
+use crate::{infra::dedup::EventDeduplicator, libs::sqlite::SqliteConnection, prelude::*};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS processed_events (
+    event_id TEXT PRIMARY KEY,
+    claimed_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS processed_events_claimed_at ON processed_events (claimed_at);
+";
+
+/// Keeps event IDs in a SQLite file for `ttl`, so redeliveries after a restart are skipped too.
+pub struct SqliteEventDeduplicator {
+    connection: SqliteConnection,
+    ttl: Duration,
+}
+
+impl SqliteEventDeduplicator {
+    pub fn open(path: impl AsRef<Path>, ttl: Duration) -> Result<Self> {
+        Self::from_connection(Connection::open(path)?, ttl)
+    }
+
+    pub fn open_in_memory(ttl: Duration) -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?, ttl)
+    }
+
+    fn from_connection(connection: Connection, ttl: Duration) -> Result<Self> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self {
+            connection: SqliteConnection::new(connection),
+            ttl,
+        })
+    }
+}
+
+#[async_trait]
+impl EventDeduplicator for SqliteEventDeduplicator {
+    async fn claim(&self, event_id: &str) -> Result<bool> {
+        let event_id = event_id.to_string();
+        let now = Utc::now().timestamp_millis();
+        let expires_before = now - i64::try_from(self.ttl.as_millis()).unwrap_or(i64::MAX);
+        self.connection
+            .call(move |connection| {
+                let transaction = connection.transaction()?;
+                transaction.execute(
+                    "DELETE FROM processed_events WHERE claimed_at <= ?1",
+                    params![expires_before],
+                )?;
+                let inserted = transaction.execute(
+                    "INSERT OR IGNORE INTO processed_events (event_id, claimed_at) VALUES (?1, ?2)",
+                    params![event_id, now],
+                )?;
+                transaction.commit()?;
+                Ok(inserted == 1)
+            })
+            .await
+    }
+
+    async fn release(&self, event_id: &str) -> Result<()> {
+        let event_id = event_id.to_string();
+        self.connection
+            .call(move |connection| {
+                connection.execute(
+                    "DELETE FROM processed_events WHERE event_id = ?1",
+                    params![event_id],
+                )?;
+                Ok(())
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EVENT_ID: &str = "01FZ74A0TDDPYRVKNK77XKC3ZR";
+
+    #[tokio::test]
+    async fn test_claim_and_release() {
+        let deduplicator =
+            SqliteEventDeduplicator::open_in_memory(Duration::from_secs(60)).unwrap();
+        assert!(deduplicator.claim(EVENT_ID).await.unwrap());
+        assert!(!deduplicator.claim(EVENT_ID).await.unwrap());
+
+        deduplicator.release(EVENT_ID).await.unwrap();
+        assert!(deduplicator.claim(EVENT_ID).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_claim_after_ttl_expires() {
+        let deduplicator = SqliteEventDeduplicator::open_in_memory(Duration::ZERO).unwrap();
+        assert!(deduplicator.claim(EVENT_ID).await.unwrap());
+        assert!(deduplicator.claim(EVENT_ID).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_claims_survive_reopening() {
+        let path =
+            std::env::temp_dir().join(format!("recipena-dedup-{}.sqlite3", ulid::Ulid::new()));
+        let ttl = Duration::from_secs(60);
+        {
+            let deduplicator = SqliteEventDeduplicator::open(&path, ttl).unwrap();
+            assert!(deduplicator.claim(EVENT_ID).await.unwrap());
+        }
+        let deduplicator = SqliteEventDeduplicator::open(&path, ttl).unwrap();
+        assert!(!deduplicator.claim(EVENT_ID).await.unwrap());
+        std::fs::remove_file(path).unwrap();
+    }
+}
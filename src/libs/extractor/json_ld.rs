@@ -0,0 +1,274 @@
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::infra::html::{ExtractionStrategy, RecipeExtractor, ScrapedRecipe};
+
+use super::{normalize_text, select_all};
+
+const JSON_LD_SELECTOR: &str = r#"script[type="application/ld+json"]"#;
+
+/// Reads the schema.org `Recipe` published in the JSON-LD blocks of a page.
+pub struct JsonLdExtractor;
+
+impl RecipeExtractor for JsonLdExtractor {
+    fn strategy(&self) -> ExtractionStrategy {
+        ExtractionStrategy::JsonLd
+    }
+
+    /// Looks for a schema.org `Recipe` in every JSON-LD block of the page.
+    fn extract(&self, dom: &tl::VDom<'_>) -> Option<ScrapedRecipe> {
+        select_all(dom, JSON_LD_SELECTOR)
+            .into_iter()
+            .filter_map(|node| {
+                let json = node.inner_text(dom.parser());
+                serde_json::from_str::<Value>(json.trim())
+                    .inspect_err(|e| tracing::debug!(%e, "skipping malformed JSON-LD block"))
+                    .ok()
+            })
+            .find_map(|value| find_recipe(&value).and_then(parse_recipe))
+    }
+}
+
+/// Walks a JSON-LD document (including `@graph` arrays and nested entities) and returns the
+/// first node typed as `Recipe`.
+fn find_recipe(value: &Value) -> Option<&Value> {
+    match value {
+        Value::Array(values) => values.iter().find_map(find_recipe),
+        Value::Object(object) => {
+            if is_type(value, "Recipe") {
+                return Some(value);
+            }
+            object.values().find_map(find_recipe)
+        }
+        _ => None,
+    }
+}
+
+fn is_type(value: &Value, ty: &str) -> bool {
+    match value.get("@type") {
+        Some(Value::String(s)) => s == ty,
+        Some(Value::Array(types)) => types.iter().any(|t| t.as_str() == Some(ty)),
+        _ => false,
+    }
+}
+
+fn parse_recipe(value: &Value) -> Option<ScrapedRecipe> {
+    let name = value.get("name").and_then(first_text)?;
+
+    Some(ScrapedRecipe {
+        name,
+        ingredients: value
+            .get("recipeIngredient")
+            .or_else(|| value.get("ingredients"))
+            .map(texts)
+            .unwrap_or_default(),
+        instructions: value
+            .get("recipeInstructions")
+            .map(instructions)
+            .unwrap_or_default(),
+        recipe_yield: value.get("recipeYield").and_then(first_text),
+        prep_time: value.get("prepTime").and_then(duration),
+        cook_time: value.get("cookTime").and_then(duration),
+        total_time: value.get("totalTime").and_then(duration),
+        image: value.get("image").and_then(image),
+        description: value.get("description").and_then(first_text),
+        author: value.get("author").and_then(author),
+        keywords: value.get("keywords").map(keywords).unwrap_or_default(),
+        ..Default::default()
+    })
+}
+
+fn first_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => normalize_text(s),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Array(values) => values.iter().find_map(first_text),
+        _ => None,
+    }
+}
+
+fn texts(value: &Value) -> Vec<String> {
+    match value {
+        Value::Array(values) => values.iter().filter_map(first_text).collect(),
+        value => first_text(value).into_iter().collect(),
+    }
+}
+
+/// Flattens `recipeInstructions`, which may be plain text, a list of strings, `HowToStep`s or
+/// `HowToSection`s containing steps.
+fn instructions(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(s) => s.lines().filter_map(normalize_text).collect(),
+        Value::Array(values) => values.iter().flat_map(instructions).collect(),
+        Value::Object(_) => {
+            if let Some(items) = value.get("itemListElement") {
+                return instructions(items);
+            }
+            value
+                .get("text")
+                .or_else(|| value.get("name"))
+                .and_then(first_text)
+                .into_iter()
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn image(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => normalize_text(s),
+        Value::Array(values) => values.iter().find_map(image),
+        Value::Object(_) => value.get("url").and_then(first_text),
+        _ => None,
+    }
+}
+
+fn author(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => normalize_text(s),
+        Value::Array(values) => values.iter().find_map(author),
+        Value::Object(_) => value.get("name").and_then(first_text),
+        _ => None,
+    }
+}
+
+fn keywords(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(s) => s
+            .split([',', '、', '，'])
+            .filter_map(normalize_text)
+            .collect(),
+        Value::Array(values) => values.iter().flat_map(keywords).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn duration(value: &Value) -> Option<Duration> {
+    value.as_str().and_then(parse_iso8601_duration)
+}
+
+/// Parses the subset of ISO 8601 durations used by recipe sites, e.g. `PT1H30M` or `P0DT20M`.
+pub(crate) fn parse_iso8601_duration(s: &str) -> Option<Duration> {
+    let rest = s.trim().strip_prefix('P')?;
+    let mut seconds = 0u64;
+    let mut number = String::new();
+    let mut in_time = false;
+
+    for c in rest.chars() {
+        match c {
+            'T' => in_time = true,
+            '0'..='9' | '.' => number.push(c),
+            unit => {
+                let n = number.parse::<f64>().ok()?;
+                number.clear();
+                let multiplier = match (unit, in_time) {
+                    ('W', false) => 7 * 24 * 60 * 60,
+                    ('D', false) => 24 * 60 * 60,
+                    ('H', true) => 60 * 60,
+                    ('M', true) => 60,
+                    ('S', true) => 1,
+                    _ => return None,
+                };
+                seconds += (n * multiplier as f64) as u64;
+            }
+        }
+    }
+
+    number.is_empty().then_some(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test]
+    fn test_extract_json_ld_graph() {
+        let body = r#"
+        <html>
+            <head>
+                <title>鶏の照り焼き レシピ・作り方 | クラシル</title>
+                <script type="application/ld+json">{"@context":"https://schema.org","@type":"Organization","name":"クラシル"}</script>
+                <script type="application/ld+json">
+                {
+                    "@context": "https://schema.org",
+                    "@graph": [
+                        {"@type": "WebPage", "name": "page"},
+                        {
+                            "@type": ["Recipe"],
+                            "name": "鶏の照り焼き",
+                            "image": [{"@type": "ImageObject", "url": "https://example.com/teriyaki.jpg"}],
+                            "description": "甘辛いたれが絡んだ定番のおかずです。",
+                            "author": {"@type": "Person", "name": "クラシル"},
+                            "recipeYield": ["2人前"],
+                            "prepTime": "PT10M",
+                            "cookTime": "PT20M",
+                            "totalTime": "PT30M",
+                            "keywords": "鶏肉, 照り焼き、お弁当",
+                            "recipeIngredient": ["鶏もも肉 300g", "しょうゆ 大さじ2"],
+                            "recipeInstructions": [
+                                {
+                                    "@type": "HowToSection",
+                                    "name": "下準備",
+                                    "itemListElement": [{"@type": "HowToStep", "text": "鶏肉を切る。"}]
+                                },
+                                {"@type": "HowToStep", "text": "フライパンで焼く。"}
+                            ]
+                        }
+                    ]
+                }
+                </script>
+            </head>
+        </html>
+        "#;
+        let dom = tl::parse(body, tl::ParserOptions::default()).unwrap();
+        let recipe = JsonLdExtractor.extract(&dom).unwrap();
+
+        assert_eq!(
+            recipe,
+            ScrapedRecipe {
+                name: "鶏の照り焼き".to_string(),
+                ingredients: vec!["鶏もも肉 300g".to_string(), "しょうゆ 大さじ2".to_string()],
+                instructions: vec!["鶏肉を切る。".to_string(), "フライパンで焼く。".to_string()],
+                recipe_yield: Some("2人前".to_string()),
+                prep_time: Some(Duration::from_secs(10 * 60)),
+                cook_time: Some(Duration::from_secs(20 * 60)),
+                total_time: Some(Duration::from_secs(30 * 60)),
+                image: Some("https://example.com/teriyaki.jpg".to_string()),
+                description: Some("甘辛いたれが絡んだ定番のおかずです。".to_string()),
+                author: Some("クラシル".to_string()),
+                keywords: vec![
+                    "鶏肉".to_string(),
+                    "照り焼き".to_string(),
+                    "お弁当".to_string()
+                ],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_extract_json_ld_without_recipe() {
+        let body = r#"
+        <html>
+            <head>
+                <script type="application/ld+json">{"@type":"WebSite","name":"example"}</script>
+            </head>
+        </html>
+        "#;
+        let dom = tl::parse(body, tl::ParserOptions::default()).unwrap();
+        assert_eq!(JsonLdExtractor.extract(&dom), None);
+    }
+
+    #[test_case("PT20M" => Some(Duration::from_secs(20 * 60)) ; "minutes")]
+    #[test_case("PT1H30M" => Some(Duration::from_secs(90 * 60)) ; "hours and minutes")]
+    #[test_case("P0DT0H45M" => Some(Duration::from_secs(45 * 60)) ; "days prefix")]
+    #[test_case("P1D" => Some(Duration::from_secs(24 * 60 * 60)) ; "days")]
+    #[test_case("20分" => None ; "not iso8601")]
+    #[test_case("PT20" => None ; "missing unit")]
+    fn test_parse_iso8601_duration(s: &str) -> Option<Duration> {
+        parse_iso8601_duration(s)
+    }
+}
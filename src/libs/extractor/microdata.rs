@@ -0,0 +1,123 @@
+use crate::infra::html::{ExtractionStrategy, RecipeExtractor, ScrapedRecipe};
+
+use super::{
+    attribute, json_ld::parse_iso8601_duration, normalize_text, select_all, select_within, text,
+};
+
+/// Reads a schema.org `Recipe` marked up with `itemscope` and `itemprop` attributes.
+pub struct MicrodataExtractor;
+
+impl RecipeExtractor for MicrodataExtractor {
+    fn strategy(&self) -> ExtractionStrategy {
+        ExtractionStrategy::Microdata
+    }
+
+    fn extract(&self, dom: &tl::VDom<'_>) -> Option<ScrapedRecipe> {
+        let scope = select_all(dom, "[itemscope]").into_iter().find(|node| {
+            attribute(node, "itemtype").is_some_and(|itemtype| {
+                itemtype
+                    .split_whitespace()
+                    .any(|t| t.trim_end_matches('/').ends_with("schema.org/Recipe"))
+            })
+        })?;
+        let values = |property: &str| -> Vec<String> {
+            select_within(dom, scope, &format!(r#"[itemprop="{property}"]"#))
+                .into_iter()
+                .filter_map(|node| value(dom, node))
+                .collect()
+        };
+        let first = |property: &str| values(property).into_iter().next();
+        let duration = |property: &str| first(property).and_then(|d| parse_iso8601_duration(&d));
+
+        let mut ingredients = values("recipeIngredient");
+        if ingredients.is_empty() {
+            ingredients = values("ingredients");
+        }
+        Some(ScrapedRecipe {
+            // Nested items such as the author have names too, but they come after the recipe's.
+            name: first("name")?,
+            ingredients,
+            instructions: values("recipeInstructions"),
+            recipe_yield: first("recipeYield"),
+            prep_time: duration("prepTime"),
+            cook_time: duration("cookTime"),
+            total_time: duration("totalTime"),
+            image: first("image"),
+            description: first("description"),
+            author: first("author"),
+            keywords: values("keywords")
+                .iter()
+                .flat_map(|keywords| keywords.split([',', '、', '，']))
+                .filter_map(normalize_text)
+                .collect(),
+            ..Default::default()
+        })
+    }
+}
+
+/// The value of a property: machine-readable attributes first, then the text.
+fn value(dom: &tl::VDom<'_>, node: &tl::Node<'_>) -> Option<String> {
+    ["content", "datetime", "src", "href"]
+        .into_iter()
+        .find_map(|name| attribute(node, name))
+        .or_else(|| text(dom, node))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_extract_microdata() {
+        let body = r#"
+        <html>
+            <body>
+                <div itemscope itemtype="https://schema.org/Recipe">
+                    <h1 itemprop="name">かぼちゃの煮物</h1>
+                    <img itemprop="image" src="https://example.com/kabocha.jpg">
+                    <span itemprop="author" itemscope itemtype="https://schema.org/Person">
+                        <span itemprop="name">料理研究家</span>
+                    </span>
+                    <meta itemprop="cookTime" content="PT25M">
+                    <span itemprop="recipeYield">4人分</span>
+                    <ul>
+                        <li itemprop="recipeIngredient">かぼちゃ 1/4個</li>
+                        <li itemprop="recipeIngredient">しょうゆ 大さじ1</li>
+                    </ul>
+                    <ol>
+                        <li itemprop="recipeInstructions">かぼちゃを切る。</li>
+                        <li itemprop="recipeInstructions">煮る。</li>
+                    </ol>
+                </div>
+            </body>
+        </html>
+        "#;
+        let dom = tl::parse(body, tl::ParserOptions::default()).unwrap();
+
+        let recipe = MicrodataExtractor.extract(&dom).unwrap();
+        assert_eq!(recipe.name, "かぼちゃの煮物");
+        assert_eq!(
+            recipe.ingredients,
+            vec!["かぼちゃ 1/4個".to_string(), "しょうゆ 大さじ1".to_string()]
+        );
+        assert_eq!(
+            recipe.instructions,
+            vec!["かぼちゃを切る。".to_string(), "煮る。".to_string()]
+        );
+        assert_eq!(recipe.cook_time, Some(Duration::from_secs(25 * 60)));
+        assert_eq!(recipe.recipe_yield.as_deref(), Some("4人分"));
+        assert_eq!(
+            recipe.image.as_deref(),
+            Some("https://example.com/kabocha.jpg")
+        );
+    }
+
+    #[test]
+    fn test_extract_microdata_without_recipe() {
+        let body = r#"<div itemscope itemtype="https://schema.org/Article"><h1 itemprop="name">記事</h1></div>"#;
+        let dom = tl::parse(body, tl::ParserOptions::default()).unwrap();
+        assert_eq!(MicrodataExtractor.extract(&dom), None);
+    }
+}
@@ -0,0 +1,268 @@
+pub mod json_ld;
+pub mod microdata;
+pub mod open_graph;
+pub mod site;
+pub mod title;
+pub mod video;
+
+use crate::infra::html::{RecipeExtractor, ScrapedRecipe};
+
+use self::video::VideoPlatform;
+
+/// Extractors in the order they are tried for a page.
+pub struct ExtractorRegistry {
+    extractors: Vec<Box<dyn RecipeExtractor + Send + Sync>>,
+}
+
+impl Default for ExtractorRegistry {
+    fn default() -> Self {
+        Self::with_video_platforms(&video::platforms())
+    }
+}
+
+impl ExtractorRegistry {
+    pub fn new(extractors: Vec<Box<dyn RecipeExtractor + Send + Sync>>) -> Self {
+        Self { extractors }
+    }
+
+    /// Video platforms and site-specific extractors first, then JSON-LD, microdata, OpenGraph
+    /// and `<title>`.
+    pub fn with_video_platforms(platforms: &[VideoPlatform]) -> Self {
+        let mut extractors = video::extractors(platforms);
+        extractors.extend(site::extractors());
+        extractors.push(Box::new(json_ld::JsonLdExtractor));
+        extractors.push(Box::new(microdata::MicrodataExtractor));
+        extractors.push(Box::new(open_graph::OpenGraphExtractor));
+        extractors.push(Box::new(title::TitleExtractor));
+        Self::new(extractors)
+    }
+
+    /// Returns the recipe from the first extractor that applies to the host and finds one,
+    /// with the image and description it lacks taken from the page's OpenGraph tags.
+    pub fn extract(&self, url: &url::Url, dom: &tl::VDom<'_>) -> Option<ScrapedRecipe> {
+        let host = url.host_str().unwrap_or_default();
+        let recipe = self
+            .extractors
+            .iter()
+            .filter(|extractor| extractor.matches(host))
+            .find_map(|extractor| {
+                let recipe = extractor.extract(dom)?;
+                Some(ScrapedRecipe {
+                    strategy: extractor.strategy(),
+                    ..recipe
+                })
+            })?;
+        let meta = open_graph::PageMeta::read(dom);
+        Some(ScrapedRecipe {
+            image: recipe.image.or(meta.image),
+            description: recipe.description.or(meta.description),
+            ..recipe
+        })
+    }
+}
+
+/// Whether the host is one of the domains or a subdomain of one.
+pub(crate) fn host_matches(host: &str, domains: &[impl AsRef<str>]) -> bool {
+    domains.iter().any(|domain| {
+        let domain = domain.as_ref();
+        host == domain
+            || host
+                .strip_suffix(domain)
+                .is_some_and(|sub| sub.ends_with('.'))
+    })
+}
+
+pub(crate) fn normalize_text(text: &str) -> Option<String> {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+/// Like [`normalize_text`], but keeps line breaks, which separate the items of lists written
+/// in descriptions.
+pub(crate) fn normalize_lines(text: &str) -> Option<String> {
+    let lines = text
+        .lines()
+        .map(|line| normalize_text(line).unwrap_or_default())
+        .collect::<Vec<_>>();
+    let text = lines.join("\n");
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// Returns every node matching the selector.
+pub(crate) fn select_all<'a>(dom: &'a tl::VDom<'_>, selector: &str) -> Vec<&'a tl::Node<'a>> {
+    dom.query_selector(selector)
+        .map(|handles| {
+            handles
+                .filter_map(|handle| handle.get(dom.parser()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the nodes matching the selector inside a node.
+pub(crate) fn select_within<'a>(
+    dom: &'a tl::VDom<'_>,
+    node: &'a tl::Node<'a>,
+    selector: &str,
+) -> Vec<&'a tl::Node<'a>> {
+    node.as_tag()
+        .and_then(|tag| tag.query_selector(dom.parser(), selector))
+        .map(|handles| {
+            handles
+                .filter_map(|handle| handle.get(dom.parser()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub(crate) fn text(dom: &tl::VDom<'_>, node: &tl::Node<'_>) -> Option<String> {
+    normalize_text(&node.inner_text(dom.parser()))
+}
+
+pub(crate) fn attribute(node: &tl::Node<'_>, name: &str) -> Option<String> {
+    normalize_text(&raw_attribute(node, name)?)
+}
+
+/// The value of an attribute with its character references decoded.
+fn raw_attribute(node: &tl::Node<'_>, name: &str) -> Option<String> {
+    let value = node.as_tag()?.attributes().get(name)??;
+    Some(decode_character_references(&value.as_utf8_str()))
+}
+
+/// Returns the `content` of `<meta property="...">` or `<meta name="...">`.
+pub(crate) fn meta_content(dom: &tl::VDom<'_>, property: &str) -> Option<String> {
+    meta_nodes(dom, property).find_map(|node| attribute(node, "content"))
+}
+
+/// Like [`meta_content`], keeping line breaks.
+pub(crate) fn meta_content_lines(dom: &tl::VDom<'_>, property: &str) -> Option<String> {
+    meta_nodes(dom, property).find_map(|node| normalize_lines(&raw_attribute(node, "content")?))
+}
+
+fn meta_nodes<'a>(dom: &'a tl::VDom<'_>, property: &str) -> impl Iterator<Item = &'a tl::Node<'a>> {
+    select_all(dom, &format!(r#"meta[property="{property}"]"#))
+        .into_iter()
+        .chain(select_all(dom, &format!(r#"meta[name="{property}"]"#)))
+}
+
+/// Decodes the character references found in attributes: numeric ones and the few named ones
+/// pages use for quotes, ampersands and angle brackets.
+fn decode_character_references(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let reference = rest
+            .find(';')
+            .filter(|end| *end <= 10)
+            .and_then(|end| Some((character_reference(&rest[1..end])?, end)));
+        match reference {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn character_reference(name: &str) -> Option<char> {
+    let code = match name {
+        "quot" => return Some('"'),
+        "apos" => return Some('\''),
+        "amp" => return Some('&'),
+        "lt" => return Some('<'),
+        "gt" => return Some('>'),
+        "nbsp" => return Some('\u{a0}'),
+        _ => name.strip_prefix('#')?,
+    };
+    let code = match code.strip_prefix(['x', 'X']) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => code.parse().ok()?,
+    };
+    char::from_u32(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use crate::infra::html::ExtractionStrategy;
+
+    use super::*;
+
+    const JSON_LD_AND_OPEN_GRAPH: &str = r#"
+    <html>
+        <head>
+            <title>肉じゃが | example</title>
+            <meta property="og:title" content="肉じゃが">
+            <script type="application/ld+json">{"@type":"Recipe","name":"肉じゃが","recipeIngredient":["じゃがいも 3個"]}</script>
+        </head>
+    </html>
+    "#;
+
+    fn extract(url: &str, html: &str) -> Option<ScrapedRecipe> {
+        let dom = tl::parse(html, tl::ParserOptions::default()).unwrap();
+        ExtractorRegistry::default().extract(&url::Url::parse(url).unwrap(), &dom)
+    }
+
+    #[test]
+    fn test_extract_prefers_json_ld() {
+        let recipe = extract("https://example.com/recipe/1", JSON_LD_AND_OPEN_GRAPH).unwrap();
+        assert_eq!(recipe.strategy, ExtractionStrategy::JsonLd);
+        assert_eq!(recipe.ingredients, vec!["じゃがいも 3個".to_string()]);
+    }
+
+    #[test]
+    fn test_extract_fills_in_open_graph_image_and_description() {
+        let html = r#"
+        <html>
+            <head>
+                <meta property="og:image" content="https://example.com/nikujaga.jpg">
+                <meta name="description" content="定番の煮物です。">
+                <script type="application/ld+json">{"@type":"Recipe","name":"肉じゃが"}</script>
+            </head>
+        </html>
+        "#;
+        let recipe = extract("https://example.com/recipe/1", html).unwrap();
+        assert_eq!(recipe.strategy, ExtractionStrategy::JsonLd);
+        assert_eq!(
+            recipe.image.as_deref(),
+            Some("https://example.com/nikujaga.jpg")
+        );
+        assert_eq!(recipe.description.as_deref(), Some("定番の煮物です。"));
+    }
+
+    #[test]
+    fn test_extract_falls_back_to_title() {
+        let recipe = extract(
+            "https://example.com/recipe/1",
+            "<html><head><title>肉じゃが</title></head></html>",
+        )
+        .unwrap();
+        assert_eq!(recipe.strategy, ExtractionStrategy::Title);
+        assert_eq!(recipe.name, "肉じゃが");
+    }
+
+    #[test_case("鶏肉 &amp; 玉ねぎ" => "鶏肉 & 玉ねぎ"; "named")]
+    #[test_case("&quot;肉じゃが&quot;&#10;材料&#x3A;" => "\"肉じゃが\"\n材料:"; "numeric")]
+    #[test_case("塩&こしょう &unknown; &#xZZ;" => "塩&こしょう &unknown; &#xZZ;"; "not references")]
+    fn test_decode_character_references(text: &str) -> String {
+        decode_character_references(text)
+    }
+
+    #[test]
+    fn test_extract_without_anything() {
+        assert_eq!(
+            extract("https://example.com/recipe/1", "<html><body></body></html>"),
+            None
+        );
+    }
+}
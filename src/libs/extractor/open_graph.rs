@@ -0,0 +1,165 @@
+use crate::infra::html::{ExtractionStrategy, RecipeExtractor, ScrapedRecipe};
+
+use super::{meta_content, meta_content_lines};
+
+/// Separators after which the rest of a title is always the site name.
+const PIPE_SEPARATORS: [&str; 2] = ["|", "｜"];
+/// Separators that also appear inside recipe names, as in "ごぼう - にんじんのきんぴら".
+const DASH_SEPARATORS: [&str; 3] = [" - ", " – ", " — "];
+
+/// Reads the name, image and description from the OpenGraph and Twitter card meta tags,
+/// which most pages have.
+pub struct OpenGraphExtractor;
+
+impl RecipeExtractor for OpenGraphExtractor {
+    fn strategy(&self) -> ExtractionStrategy {
+        ExtractionStrategy::OpenGraph
+    }
+
+    fn extract(&self, dom: &tl::VDom<'_>) -> Option<ScrapedRecipe> {
+        let meta = PageMeta::read(dom);
+        Some(ScrapedRecipe {
+            name: clean_title(&meta.title?, meta.site_name.as_deref())?,
+            image: meta.image,
+            description: meta.description,
+            author: meta.site_name,
+            ..Default::default()
+        })
+    }
+}
+
+/// What a page declares about itself for link previews.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct PageMeta {
+    pub title: Option<String>,
+    pub image: Option<String>,
+    pub description: Option<String>,
+    pub site_name: Option<String>,
+}
+
+impl PageMeta {
+    /// Reads `og:*` tags, falling back to `twitter:*` and `<meta name="description">`.
+    /// Line breaks are kept in descriptions, which may list ingredients.
+    pub fn read(dom: &tl::VDom<'_>) -> Self {
+        let first = |properties: &[&str]| {
+            properties
+                .iter()
+                .find_map(|property| meta_content(dom, property))
+        };
+        let description = ["og:description", "twitter:description", "description"]
+            .iter()
+            .find_map(|property| meta_content_lines(dom, property));
+        Self {
+            title: first(&["og:title", "twitter:title"]),
+            image: first(&[
+                "og:image",
+                "og:image:url",
+                "twitter:image",
+                "twitter:image:src",
+            ]),
+            description,
+            site_name: first(&["og:site_name", "application-name"]),
+        }
+    }
+}
+
+/// Removes the site name sites append to their titles, as in "肉じゃが | クラシル" or
+/// "肉じゃが - Cookpad".
+///
+/// The part after a `|` is always dropped. The part after a dash is only dropped when it is
+/// the site name or a Latin-only name following a Japanese title.
+pub(crate) fn clean_title(title: &str, site_name: Option<&str>) -> Option<String> {
+    let title = title.trim();
+    let is_site_name = |suffix: &str| {
+        site_name.is_some_and(|name| {
+            let name = name.trim().to_lowercase();
+            !name.is_empty() && suffix.to_lowercase().contains(&name)
+        })
+    };
+    let pipes = PIPE_SEPARATORS
+        .iter()
+        .filter_map(|separator| title.rsplit_once(separator));
+    let dashes = DASH_SEPARATORS
+        .iter()
+        .filter_map(|separator| title.rsplit_once(separator))
+        .filter(|(head, suffix)| {
+            is_site_name(suffix) || (!head.is_ascii() && suffix.trim().is_ascii())
+        });
+    let cleaned = pipes
+        .chain(dashes)
+        .map(|(head, _)| head.trim())
+        .filter(|head| !head.is_empty())
+        // The separator closest to the end wins, so only the last segment is removed.
+        .max_by_key(|head| head.len())
+        .unwrap_or(title);
+    super::normalize_text(cleaned)
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test]
+    fn test_extract_open_graph() {
+        let body = r#"
+        <html>
+            <head>
+                <title>肉じゃが | example</title>
+                <meta property="og:title" content="肉じゃが | example">
+                <meta property="og:image" content="https://example.com/nikujaga.jpg">
+                <meta property="og:description" content="ほっくりしたじゃがいもの煮物です。">
+                <meta property="og:site_name" content="example">
+            </head>
+        </html>
+        "#;
+        let dom = tl::parse(body, tl::ParserOptions::default()).unwrap();
+
+        let recipe = OpenGraphExtractor.extract(&dom).unwrap();
+        assert_eq!(recipe.name, "肉じゃが");
+        assert_eq!(
+            recipe.image.as_deref(),
+            Some("https://example.com/nikujaga.jpg")
+        );
+        assert_eq!(
+            recipe.description.as_deref(),
+            Some("ほっくりしたじゃがいもの煮物です。")
+        );
+        assert_eq!(recipe.author.as_deref(), Some("example"));
+    }
+
+    #[test]
+    fn test_extract_twitter_card() {
+        let body = r#"
+        <html>
+            <head>
+                <meta name="twitter:title" content="筑前煮 - Cookpad">
+                <meta name="twitter:image" content="https://example.com/chikuzenni.jpg">
+                <meta name="description" content="根菜たっぷりの煮物。">
+            </head>
+        </html>
+        "#;
+        let dom = tl::parse(body, tl::ParserOptions::default()).unwrap();
+
+        let recipe = OpenGraphExtractor.extract(&dom).unwrap();
+        assert_eq!(recipe.name, "筑前煮");
+        assert_eq!(
+            recipe.image.as_deref(),
+            Some("https://example.com/chikuzenni.jpg")
+        );
+        assert_eq!(recipe.description.as_deref(), Some("根菜たっぷりの煮物。"));
+    }
+
+    #[test_case("鶏の照り焼き レシピ・作り方 | クラシル", None => Some("鶏の照り焼き レシピ・作り方".to_string()); "pipe")]
+    #[test_case("筑前煮のレシピ｜家庭料理の本棚", None => Some("筑前煮のレシピ".to_string()); "full-width pipe")]
+    #[test_case("筑前煮 - Cookpad", None => Some("筑前煮".to_string()); "latin site name")]
+    #[test_case("肉じゃがの作り方 - おばあちゃんの台所", Some("おばあちゃんの台所") => Some("肉じゃがの作り方".to_string()); "dash site name")]
+    #[test_case("ごぼう - にんじんのきんぴら", None => Some("ごぼう - にんじんのきんぴら".to_string()); "dash in name")]
+    #[test_case("肉じゃが | 和食 | example", None => Some("肉じゃが | 和食".to_string()); "only last segment")]
+    #[test_case(" | example", None => Some("| example".to_string()); "nothing before separator")]
+    #[test_case("肉じゃが", Some("example") => Some("肉じゃが".to_string()); "plain")]
+    fn test_clean_title(title: &str, site_name: Option<&str>) -> Option<String> {
+        clean_title(title, site_name)
+    }
+}
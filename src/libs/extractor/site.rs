@@ -0,0 +1,361 @@
+use crate::infra::html::{ExtractionStrategy, RecipeExtractor, ScrapedRecipe};
+
+use super::{
+    host_matches, json_ld::JsonLdExtractor, microdata::MicrodataExtractor, select_all,
+    select_within, text,
+};
+
+/// Marks put before ingredients to group them, e.g. `★しょうゆ` for the sauce.
+const INGREDIENT_MARKERS: [char; 10] = ['・', '●', '○', '◎', '★', '☆', '◆', '◇', '※', '*'];
+
+/// Brackets that make a line a group heading such as `【たれ】`, or a one-letter group label
+/// such as `(A)` when followed by more text.
+const BRACKETS: [(char, char); 6] = [
+    ('【', '】'),
+    ('＜', '＞'),
+    ('<', '>'),
+    ('［', '］'),
+    ('(', ')'),
+    ('（', '）'),
+];
+
+/// Where a site puts the parts of a recipe in its markup.
+struct Selectors {
+    name: &'static str,
+    /// One node per ingredient.
+    ingredients: &'static str,
+    /// Name and quantity within an ingredient node, for sites putting them in separate
+    /// elements. The whole node is read otherwise.
+    ingredient_parts: Option<(&'static str, &'static str)>,
+    steps: &'static str,
+    servings: Option<&'static str>,
+}
+
+/// The quirks of a recipe site.
+struct Site {
+    name: &'static str,
+    /// Domains of the site, subdomains included.
+    hosts: &'static [&'static str],
+    /// Read before the structured data, for sites where it is missing or incomplete.
+    selectors: Option<Selectors>,
+    /// Text the site adds around recipe names, such as `【動画】` or `レシピ・作り方`.
+    name_affixes: &'static [&'static str],
+}
+
+static SITES: [Site; 6] = [
+    Site {
+        name: "cookpad",
+        hosts: &["cookpad.com"],
+        selectors: Some(Selectors {
+            name: "h1",
+            ingredients: "#ingredients li",
+            ingredient_parts: Some(("span", "bdi")),
+            steps: "#steps li p",
+            servings: Some("#ingredients .servings"),
+        }),
+        name_affixes: &[],
+    },
+    Site {
+        name: "kurashiru",
+        hosts: &["kurashiru.com"],
+        selectors: None,
+        name_affixes: &["レシピ・作り方"],
+    },
+    Site {
+        name: "delish_kitchen",
+        hosts: &["delishkitchen.tv"],
+        selectors: None,
+        name_affixes: &["【動画】", "のレシピ動画・作り方", "レシピ動画・作り方"],
+    },
+    Site {
+        name: "nadia",
+        hosts: &["oceans-nadia.com"],
+        selectors: None,
+        name_affixes: &[],
+    },
+    Site {
+        name: "orangepage",
+        hosts: &["orangepage.net"],
+        selectors: Some(Selectors {
+            name: "h1",
+            ingredients: "#ingredients li",
+            ingredient_parts: Some((".name", ".amount")),
+            steps: "#procedure li",
+            servings: Some("#ingredients .serving"),
+        }),
+        name_affixes: &["のレシピ"],
+    },
+    Site {
+        name: "lettuce_club",
+        hosts: &["lettuceclub.net"],
+        selectors: None,
+        name_affixes: &["のレシピ・作り方"],
+    },
+];
+
+/// Returns an extractor for every supported site.
+pub fn extractors() -> Vec<Box<dyn RecipeExtractor + Send + Sync>> {
+    SITES
+        .iter()
+        .map(|site| Box::new(SiteExtractor { site }) as Box<dyn RecipeExtractor + Send + Sync>)
+        .collect()
+}
+
+/// Reads a recipe with the rules of one site, then cleans up what the site adds to it.
+pub struct SiteExtractor {
+    site: &'static Site,
+}
+
+impl RecipeExtractor for SiteExtractor {
+    fn strategy(&self) -> ExtractionStrategy {
+        ExtractionStrategy::Site(self.site.name)
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        host_matches(host, self.site.hosts)
+    }
+
+    fn extract(&self, dom: &tl::VDom<'_>) -> Option<ScrapedRecipe> {
+        let recipe = self
+            .site
+            .selectors
+            .as_ref()
+            .and_then(|selectors| selectors.extract(dom))
+            .or_else(|| JsonLdExtractor.extract(dom))
+            .or_else(|| MicrodataExtractor.extract(dom))?;
+
+        Some(ScrapedRecipe {
+            name: clean_name(&recipe.name, self.site.name_affixes),
+            ingredients: recipe
+                .ingredients
+                .iter()
+                .filter_map(|ingredient| clean_ingredient(ingredient))
+                .collect(),
+            instructions: recipe
+                .instructions
+                .iter()
+                .filter_map(|step| clean_step(step))
+                .collect(),
+            ..recipe
+        })
+    }
+}
+
+impl Selectors {
+    fn extract(&self, dom: &tl::VDom<'_>) -> Option<ScrapedRecipe> {
+        let name = select_all(dom, self.name)
+            .into_iter()
+            .find_map(|node| text(dom, node))?;
+        let ingredients = select_all(dom, self.ingredients)
+            .into_iter()
+            .filter_map(|node| match self.ingredient_parts {
+                Some((name, quantity)) => {
+                    let part = |selector| {
+                        select_within(dom, node, selector)
+                            .into_iter()
+                            .find_map(|node| text(dom, node))
+                    };
+                    let name = part(name)?;
+                    Some(match part(quantity) {
+                        Some(quantity) => format!("{name} {quantity}"),
+                        None => name,
+                    })
+                }
+                None => text(dom, node),
+            })
+            .collect::<Vec<_>>();
+        let instructions = select_all(dom, self.steps)
+            .into_iter()
+            .filter_map(|node| text(dom, node))
+            .collect::<Vec<_>>();
+        // A page whose layout changed still has a heading; fall back to the structured data.
+        if ingredients.is_empty() && instructions.is_empty() {
+            return None;
+        }
+        let recipe_yield = self.servings.and_then(|selector| {
+            select_all(dom, selector)
+                .into_iter()
+                .find_map(|node| text(dom, node))
+        });
+
+        Some(ScrapedRecipe {
+            name,
+            ingredients,
+            instructions,
+            recipe_yield,
+            ..Default::default()
+        })
+    }
+}
+
+fn clean_name(name: &str, affixes: &[&str]) -> String {
+    let mut name = name.trim();
+    while let Some(stripped) = affixes.iter().find_map(|affix| {
+        name.strip_prefix(affix)
+            .or_else(|| name.strip_suffix(affix))
+            .map(str::trim)
+    }) {
+        name = stripped;
+    }
+    name.to_string()
+}
+
+/// Drops group headings and the marks and labels putting ingredients into groups.
+pub(crate) fn clean_ingredient(ingredient: &str) -> Option<String> {
+    let ingredient = ingredient.trim();
+    let is_heading = BRACKETS
+        .iter()
+        .any(|(open, close)| ingredient.starts_with(*open) && ingredient.ends_with(*close))
+        || ingredient.starts_with(['■', '▼', '□']);
+    if is_heading {
+        return None;
+    }
+
+    let ingredient = ingredient
+        .trim_start_matches(INGREDIENT_MARKERS)
+        .trim_start();
+    let ingredient = BRACKETS
+        .iter()
+        .find_map(|(open, close)| {
+            let (label, rest) = ingredient.strip_prefix(*open)?.split_once(*close)?;
+            (label.chars().count() == 1).then_some(rest)
+        })
+        .unwrap_or(ingredient)
+        .trim();
+    (!ingredient.is_empty()).then(|| ingredient.to_string())
+}
+
+/// Removes the numbering some sites keep in the text of their steps. Only a number followed by
+/// a period or a parenthesis counts, so "3 分煮る。" and "1.5カップの水" are kept.
+pub(crate) fn clean_step(step: &str) -> Option<String> {
+    let is_digit = |c: char| c.is_ascii_digit() || ('０'..='９').contains(&c);
+    let step = step.trim();
+    let step = match step.chars().next() {
+        Some('①'..='⑳') => step.chars().skip(1).collect::<String>(),
+        _ => {
+            let rest = step.trim_start_matches(is_digit);
+            let mut chars = rest.chars();
+            let numbered = rest.len() < step.len()
+                && matches!(chars.next(), Some('.' | '．' | ')' | '）'))
+                && !chars.next().is_some_and(is_digit);
+            if numbered {
+                rest.chars().skip(1).collect()
+            } else {
+                step.to_string()
+            }
+        }
+    };
+    let step = step.trim();
+    (!step.is_empty()).then(|| step.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use crate::libs::extractor::ExtractorRegistry;
+
+    use super::*;
+
+    struct Expected {
+        name: &'static str,
+        ingredients: &'static [&'static str],
+        first_step: &'static str,
+    }
+
+    #[test_case("https://cookpad.com/jp/recipes/123", include_str!("../../../tests/fixtures/sites/cookpad.html"), "cookpad", Expected {
+        name: "簡単！基本の肉じゃが",
+        ingredients: &["じゃがいも 3個", "牛こま切れ肉 200g", "しょうゆ 大さじ3"],
+        first_step: "じゃがいもは皮をむいて一口大に切る。",
+    }; "cookpad")]
+    #[test_case("https://www.kurashiru.com/recipes/abc", include_str!("../../../tests/fixtures/sites/kurashiru.html"), "kurashiru", Expected {
+        name: "鶏の照り焼き",
+        ingredients: &["鶏もも肉 300g", "しょうゆ 大さじ2", "みりん 大さじ2"],
+        first_step: "鶏もも肉は余分な脂を取り除く。",
+    }; "kurashiru")]
+    #[test_case("https://delishkitchen.tv/recipes/456", include_str!("../../../tests/fixtures/sites/delish_kitchen.html"), "delish_kitchen", Expected {
+        name: "ふわとろ親子丼",
+        ingredients: &["鶏もも肉 150g", "卵 2個", "ごはん 丼2杯分"],
+        first_step: "鶏肉は一口大に切る。",
+    }; "delish kitchen")]
+    #[test_case("https://oceans-nadia.com/user/1/recipe/789", include_str!("../../../tests/fixtures/sites/nadia.html"), "nadia", Expected {
+        name: "豚こまの生姜焼き",
+        ingredients: &["豚こま切れ肉 250g", "しょうゆ 大さじ1と1/2", "しょうがすりおろし 小さじ2"],
+        first_step: "Aを混ぜ合わせる。",
+    }; "nadia")]
+    #[test_case("https://www.orangepage.net/recipes/detail_111", include_str!("../../../tests/fixtures/sites/orangepage.html"), "orangepage", Expected {
+        name: "かぼちゃの煮もの",
+        ingredients: &["かぼちゃ 1/4個", "砂糖 大さじ1", "しょうゆ 大さじ1"],
+        first_step: "かぼちゃは種とわたを取り、一口大に切る。",
+    }; "orange page")]
+    #[test_case("https://www.lettuceclub.net/recipe/dish/222/", include_str!("../../../tests/fixtures/sites/lettuce_club.html"), "lettuce_club", Expected {
+        name: "ほうれん草のごまあえ",
+        ingredients: &["ほうれん草 1わ", "すりごま 大さじ2", "砂糖 小さじ1"],
+        first_step: "ほうれん草はゆでて水にとり、水けを絞る。",
+    }; "lettuce club")]
+    fn test_extract_site_fixture(url: &str, html: &str, site: &str, expected: Expected) {
+        let dom = tl::parse(html, tl::ParserOptions::default()).unwrap();
+        let recipe = ExtractorRegistry::default()
+            .extract(&url::Url::parse(url).unwrap(), &dom)
+            .unwrap();
+
+        assert!(matches!(recipe.strategy, ExtractionStrategy::Site(name) if name == site));
+        assert_eq!(recipe.name, expected.name);
+        assert_eq!(recipe.ingredients, expected.ingredients);
+        assert_eq!(
+            recipe.instructions.first().map(String::as_str),
+            Some(expected.first_step)
+        );
+    }
+
+    #[test]
+    fn test_extract_falls_back_when_selectors_find_nothing() {
+        let html = r#"<html><head><script type="application/ld+json">
+            {"@type": "Recipe", "name": "肉じゃが", "recipeIngredient": ["じゃがいも 3個"],
+             "recipeInstructions": ["煮る。"]}
+            </script></head><body><h1>肉じゃが</h1></body></html>"#;
+        let dom = tl::parse(html, tl::ParserOptions::default()).unwrap();
+        let recipe = ExtractorRegistry::default()
+            .extract(
+                &url::Url::parse("https://cookpad.com/jp/recipes/123").unwrap(),
+                &dom,
+            )
+            .unwrap();
+
+        assert_eq!(recipe.ingredients, ["じゃがいも 3個"]);
+        assert_eq!(recipe.instructions, ["煮る。"]);
+    }
+
+    #[test_case("cookpad.com" => true; "domain")]
+    #[test_case("www.cookpad.com" => true; "subdomain")]
+    #[test_case("notcookpad.com" => false; "other domain")]
+    fn test_matches(host: &str) -> bool {
+        SiteExtractor { site: &SITES[0] }.matches(host)
+    }
+
+    #[test_case("【たれ】" => None; "heading")]
+    #[test_case("■合わせ調味料" => None; "square heading")]
+    #[test_case("★しょうゆ 大さじ1" => Some("しょうゆ 大さじ1".to_string()); "marker")]
+    #[test_case("(A)みりん 大さじ1" => Some("みりん 大さじ1".to_string()); "group label")]
+    #[test_case("（Ａ）酒 大さじ1" => Some("酒 大さじ1".to_string()); "full-width group label")]
+    #[test_case("鶏もも肉（皮なし） 300g" => Some("鶏もも肉（皮なし） 300g".to_string()); "note kept")]
+    fn test_clean_ingredient(ingredient: &str) -> Option<String> {
+        clean_ingredient(ingredient)
+    }
+
+    #[test_case("1. 切る。" => Some("切る。".to_string()); "dot")]
+    #[test_case("②煮る。" => Some("煮る。".to_string()); "circled")]
+    #[test_case("１０）盛りつける。" => Some("盛りつける。".to_string()); "full-width")]
+    #[test_case("200gの肉を焼く。" => Some("200gの肉を焼く。".to_string()); "quantity kept")]
+    #[test_case("3 分煮る。" => Some("3 分煮る。".to_string()); "duration kept")]
+    #[test_case("1.5カップの水を加える。" => Some("1.5カップの水を加える。".to_string()); "decimal kept")]
+    fn test_clean_step(step: &str) -> Option<String> {
+        clean_step(step)
+    }
+
+    #[test_case("【動画】ふわとろ親子丼のレシピ動画・作り方", &["【動画】", "のレシピ動画・作り方"] => "ふわとろ親子丼"; "both ends")]
+    #[test_case("鶏の照り焼き", &["レシピ・作り方"] => "鶏の照り焼き"; "untouched")]
+    fn test_clean_name(name: &str, affixes: &[&str]) -> String {
+        clean_name(name, affixes)
+    }
+}
@@ -0,0 +1,54 @@
+use crate::infra::html::{ExtractionStrategy, RecipeExtractor, ScrapedRecipe};
+
+use super::{meta_content, open_graph::clean_title, select_all, text};
+
+/// Names the recipe after the `<title>` of the page, without the site name, as a last resort.
+pub struct TitleExtractor;
+
+impl RecipeExtractor for TitleExtractor {
+    fn strategy(&self) -> ExtractionStrategy {
+        ExtractionStrategy::Title
+    }
+
+    fn extract(&self, dom: &tl::VDom<'_>) -> Option<ScrapedRecipe> {
+        let title = select_all(dom, "title").into_iter().next()?;
+        let site_name = meta_content(dom, "og:site_name");
+        let name = clean_title(&text(dom, title)?, site_name.as_deref())?;
+        Some(ScrapedRecipe::from_name(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_title() {
+        let body = r#""
+        <html>
+            <head>
+                <title>Google</title>
+            </head>
+        </html>
+        "#;
+        let dom = tl::parse(body, tl::ParserOptions::default()).unwrap();
+        assert_eq!(TitleExtractor.extract(&dom).unwrap().name, "Google");
+    }
+
+    #[test]
+    fn test_extract_title_without_site_name() {
+        let body = r#"
+        <html>
+            <head>
+                <title>肉じゃがの作り方 - おばあちゃんの台所</title>
+                <meta property="og:site_name" content="おばあちゃんの台所">
+            </head>
+        </html>
+        "#;
+        let dom = tl::parse(body, tl::ParserOptions::default()).unwrap();
+        assert_eq!(
+            TitleExtractor.extract(&dom).unwrap().name,
+            "肉じゃがの作り方"
+        );
+    }
+}
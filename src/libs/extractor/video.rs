@@ -0,0 +1,466 @@
+use serde::Deserialize;
+
+use crate::infra::html::{ExtractionStrategy, RecipeExtractor, ScrapedRecipe};
+
+use super::{
+    attribute, host_matches, meta_content, normalize_text,
+    open_graph::PageMeta,
+    select_all, select_within,
+    site::{clean_ingredient, clean_step},
+};
+
+/// Where YouTube keeps the full description in the player data embedded in the page.
+const SHORT_DESCRIPTION_KEY: &str = r#""shortDescription":"#;
+
+/// Marks put around headings in descriptions, as in `【材料】` or `■作り方`.
+const HEADING_MARKS: [char; 16] = [
+    '【', '】', '[', ']', '［', '］', '<', '>', '＜', '＞', '〈', '〉', '■', '□', '◆', '▼',
+];
+
+/// Dots some uploaders put between an ingredient and its quantity, as in `鶏もも肉…300g`.
+const QUANTITY_LEADERS: [&str; 4] = ["・・・", "...", "…", "‥"];
+
+const INGREDIENT_HEADINGS: [&str; 2] = ["材料", "ingredients"];
+const STEP_HEADINGS: [&str; 5] = [
+    "作り方",
+    "手順",
+    "how to make",
+    "instructions",
+    "directions",
+];
+
+/// A site hosting recipe videos, whose pages hold little besides the video, so recipes are
+/// read from the title and description written by the uploader.
+#[derive(Debug, Clone)]
+pub struct VideoPlatform {
+    pub name: &'static str,
+    /// Domains of the platform, subdomains included.
+    pub hosts: Vec<String>,
+    /// oEmbed endpoint taking the video URL as `url`, when the platform serves one without
+    /// an app token.
+    pub oembed_endpoint: Option<String>,
+}
+
+impl VideoPlatform {
+    pub fn new(name: &'static str, hosts: &[&str], oembed_endpoint: Option<&str>) -> Self {
+        Self {
+            name,
+            hosts: hosts.iter().map(ToString::to_string).collect(),
+            oembed_endpoint: oembed_endpoint.map(str::to_string),
+        }
+    }
+
+    pub fn matches(&self, url: &url::Url) -> bool {
+        url.host_str()
+            .is_some_and(|host| host_matches(host, &self.hosts))
+    }
+
+    /// Returns the oEmbed URL describing a video of the platform.
+    pub fn oembed_url(&self, video_url: &url::Url) -> Option<url::Url> {
+        let mut url = url::Url::parse(self.oembed_endpoint.as_deref()?).ok()?;
+        url.query_pairs_mut()
+            .append_pair("url", video_url.as_str())
+            .append_pair("format", "json");
+        Some(url)
+    }
+}
+
+/// The supported video platforms. Instagram only serves oEmbed to registered apps, so its
+/// posts are read from the page alone.
+pub fn platforms() -> Vec<VideoPlatform> {
+    vec![
+        VideoPlatform::new(
+            "youtube",
+            &["youtube.com", "youtu.be"],
+            Some("https://www.youtube.com/oembed"),
+        ),
+        VideoPlatform::new("instagram", &["instagram.com"], None),
+        VideoPlatform::new(
+            "tiktok",
+            &["tiktok.com"],
+            Some("https://www.tiktok.com/oembed"),
+        ),
+    ]
+}
+
+/// Returns an extractor for each video platform.
+pub fn extractors(platforms: &[VideoPlatform]) -> Vec<Box<dyn RecipeExtractor + Send + Sync>> {
+    platforms
+        .iter()
+        .map(|platform| {
+            Box::new(VideoExtractor {
+                platform: platform.clone(),
+            }) as Box<dyn RecipeExtractor + Send + Sync>
+        })
+        .collect()
+}
+
+/// Reads a video page: its title, channel, thumbnail and the ingredients and steps listed in
+/// its description.
+pub struct VideoExtractor {
+    platform: VideoPlatform,
+}
+
+impl RecipeExtractor for VideoExtractor {
+    fn strategy(&self) -> ExtractionStrategy {
+        ExtractionStrategy::Video(self.platform.name)
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        host_matches(host, &self.platform.hosts)
+    }
+
+    fn extract(&self, dom: &tl::VDom<'_>) -> Option<ScrapedRecipe> {
+        let meta = PageMeta::read(dom);
+        let title = meta.title.or_else(|| meta_content(dom, "title"))?;
+        let description = full_description(dom).or(meta.description);
+        video_recipe(
+            unquote_caption(&title),
+            description.as_deref().map(unquote_caption),
+            channel(dom),
+            meta.image,
+        )
+    }
+}
+
+/// The fields of an oEmbed response used for recipes.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct OEmbed {
+    pub title: Option<String>,
+    pub author_name: Option<String>,
+    pub thumbnail_url: Option<String>,
+}
+
+impl OEmbed {
+    /// Completes the recipe read from the video page. The page may be a consent or login page
+    /// instead of the video, so it is only used when it was read as a video, and the oEmbed
+    /// title and channel win over its own.
+    pub fn complete(
+        self,
+        platform: &VideoPlatform,
+        page: Option<ScrapedRecipe>,
+    ) -> Option<ScrapedRecipe> {
+        let strategy = ExtractionStrategy::Video(platform.name);
+        match page.filter(|recipe| recipe.strategy == strategy) {
+            Some(recipe) => Some(ScrapedRecipe {
+                name: self
+                    .title
+                    .as_deref()
+                    .and_then(video_title)
+                    .unwrap_or(recipe.name),
+                author: self.author_name.or(recipe.author),
+                image: self.thumbnail_url.or(recipe.image),
+                ..recipe
+            }),
+            None => {
+                let title = self.title?;
+                // TikTok returns the whole caption as the title.
+                let caption = title.contains('\n').then_some(title.as_str());
+                let recipe = video_recipe(&title, caption, self.author_name, self.thumbnail_url)?;
+                Some(ScrapedRecipe { strategy, ..recipe })
+            }
+        }
+    }
+}
+
+fn video_recipe(
+    title: &str,
+    description: Option<&str>,
+    author: Option<String>,
+    image: Option<String>,
+) -> Option<ScrapedRecipe> {
+    let description = description.map(str::trim).filter(|d| !d.is_empty());
+    let parsed = description.map(parse_description).unwrap_or_default();
+    Some(ScrapedRecipe {
+        name: video_title(title)?,
+        ingredients: parsed.ingredients,
+        instructions: parsed.steps,
+        recipe_yield: parsed.servings,
+        image,
+        description: description.map(str::to_string),
+        author,
+        ..Default::default()
+    })
+}
+
+/// The first line of a video title without its hashtags.
+fn video_title(title: &str) -> Option<String> {
+    title.lines().find_map(|line| {
+        let words = line
+            .split_whitespace()
+            .filter(|word| !word.starts_with(['#', '＃']))
+            .collect::<Vec<_>>();
+        normalize_text(&words.join(" "))
+    })
+}
+
+/// Instagram puts captions in quotes after the account name, as in
+/// `user on Instagram: "caption"`.
+fn unquote_caption(text: &str) -> &str {
+    text.split_once(": \"")
+        .and_then(|(_, caption)| {
+            let caption = caption.trim_end();
+            caption
+                .strip_suffix("\".")
+                .or_else(|| caption.strip_suffix('"'))
+        })
+        .unwrap_or(text)
+}
+
+/// YouTube only puts the first lines of a description in its meta tags; the full text is in
+/// the player data embedded in a script.
+fn full_description(dom: &tl::VDom<'_>) -> Option<String> {
+    select_all(dom, "script").into_iter().find_map(|node| {
+        let script = node.inner_text(dom.parser());
+        let start = script.find(SHORT_DESCRIPTION_KEY)? + SHORT_DESCRIPTION_KEY.len();
+        serde_json::Deserializer::from_str(&script[start..])
+            .into_iter::<String>()
+            .next()?
+            .ok()
+    })
+}
+
+/// The channel name from the `author` microdata of the page.
+fn channel(dom: &tl::VDom<'_>) -> Option<String> {
+    select_all(dom, r#"[itemprop="author"]"#)
+        .into_iter()
+        .flat_map(|node| select_within(dom, node, r#"[itemprop="name"]"#))
+        .find_map(|node| attribute(node, "content"))
+        .or_else(|| meta_content(dom, "author"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Ingredients,
+    Steps,
+}
+
+/// What a video description says about the recipe.
+#[derive(Debug, Default, PartialEq)]
+struct VideoDescription {
+    servings: Option<String>,
+    ingredients: Vec<String>,
+    steps: Vec<String>,
+}
+
+/// Reads the ingredients and steps listed under headings such as `【材料】(2人分)` and
+/// `■作り方` in a description.
+fn parse_description(text: &str) -> VideoDescription {
+    let mut parsed = VideoDescription::default();
+    let mut section = None;
+    for line in text.lines().map(str::trim) {
+        if let Some((heading, rest)) = heading(line) {
+            if heading == Section::Ingredients && parsed.servings.is_none() {
+                parsed.servings = servings(rest);
+            }
+            section = Some(heading);
+            continue;
+        }
+        // Hashtags and links follow the recipe.
+        if line.starts_with(['#', '＃']) || line.contains("://") {
+            section = None;
+            continue;
+        }
+        match section {
+            // Ingredients are listed on consecutive lines, so a blank line ends them.
+            Some(Section::Ingredients) if line.is_empty() && !parsed.ingredients.is_empty() => {
+                section = None;
+            }
+            Some(Section::Ingredients) => {
+                let mut line = line.to_string();
+                for leader in QUANTITY_LEADERS {
+                    line = line.replace(leader, " ");
+                }
+                parsed
+                    .ingredients
+                    .extend(normalize_text(&line).as_deref().and_then(clean_ingredient));
+            }
+            // Other headings, such as `【ポイント】`, end the steps.
+            Some(Section::Steps) if line.starts_with(HEADING_MARKS) => section = None,
+            Some(Section::Steps) => parsed.steps.extend(clean_step(line)),
+            None => {}
+        }
+    }
+    parsed
+}
+
+/// Recognizes an ingredient or step heading, returning the text after the keyword.
+fn heading(line: &str) -> Option<(Section, &str)> {
+    let line = line.trim_start_matches(|c: char| HEADING_MARKS.contains(&c) || c.is_whitespace());
+    let sections = INGREDIENT_HEADINGS
+        .iter()
+        .map(|keyword| (Section::Ingredients, keyword))
+        .chain(
+            STEP_HEADINGS
+                .iter()
+                .map(|keyword| (Section::Steps, keyword)),
+        );
+    sections.find_map(|(section, keyword)| {
+        let rest = line
+            .get(..keyword.len())
+            .filter(|prefix| prefix.eq_ignore_ascii_case(keyword))
+            .map(|_| &line[keyword.len()..])?;
+        // "材料を切る" is a step, not a heading.
+        (!rest.starts_with(char::is_alphanumeric)).then_some((section, rest))
+    })
+}
+
+/// Servings written after an ingredient heading, as in `(2人分)`.
+fn servings(rest: &str) -> Option<String> {
+    let rest = rest.trim_matches(|c: char| {
+        HEADING_MARKS.contains(&c)
+            || matches!(c, '(' | ')' | '（' | '）' | ':' | '：')
+            || c.is_whitespace()
+    });
+    rest.contains(|c: char| c.is_ascii_digit())
+        .then(|| rest.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use crate::libs::extractor::ExtractorRegistry;
+
+    use super::*;
+
+    const YOUTUBE_PAGE: &str = include_str!("../../../tests/fixtures/videos/youtube.html");
+    const INSTAGRAM_PAGE: &str = include_str!("../../../tests/fixtures/videos/instagram.html");
+
+    fn extract(url: &str, html: &str) -> ScrapedRecipe {
+        let dom = tl::parse(html, tl::ParserOptions::default()).unwrap();
+        ExtractorRegistry::default()
+            .extract(&url::Url::parse(url).unwrap(), &dom)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_extract_youtube_page() {
+        let recipe = extract("https://www.youtube.com/watch?v=abc123", YOUTUBE_PAGE);
+        assert_eq!(recipe.strategy, ExtractionStrategy::Video("youtube"));
+        assert_eq!(recipe.name, "【簡単】フライパンで作る鶏の照り焼き");
+        assert_eq!(recipe.author.as_deref(), Some("おうちごはんチャンネル"));
+        assert_eq!(
+            recipe.image.as_deref(),
+            Some("https://i.ytimg.com/vi/abc123/maxresdefault.jpg")
+        );
+        assert_eq!(recipe.recipe_yield.as_deref(), Some("2人分"));
+        assert_eq!(
+            recipe.ingredients,
+            vec![
+                "鶏もも肉 1枚(300g)".to_string(),
+                "しょうゆ 大さじ2".to_string(),
+                "みりん 大さじ2".to_string(),
+                "砂糖 小さじ1".to_string(),
+            ]
+        );
+        assert_eq!(
+            recipe.instructions,
+            vec![
+                "鶏肉の皮目をフォークで刺す。".to_string(),
+                "皮目から中火で焼く。".to_string(),
+                "たれを加えて煮絡める。".to_string(),
+            ]
+        );
+        assert!(
+            recipe
+                .description
+                .unwrap()
+                .starts_with("今日は甘辛い鶏の照り焼きを作ります。")
+        );
+    }
+
+    #[test]
+    fn test_extract_instagram_page() {
+        let recipe = extract("https://www.instagram.com/reel/xyz/", INSTAGRAM_PAGE);
+        assert_eq!(recipe.strategy, ExtractionStrategy::Video("instagram"));
+        assert_eq!(recipe.name, "レンジで簡単！なすの煮びたし");
+        assert_eq!(
+            recipe.ingredients,
+            vec!["なす 2本".to_string(), "めんつゆ 大さじ3".to_string()]
+        );
+        assert_eq!(
+            recipe.instructions,
+            vec!["なすを切る".to_string(), "レンジで4分加熱する".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_oembed_completes_consent_page() {
+        let platform = &platforms()[0];
+        let consent = ScrapedRecipe::from_name("Before you continue to YouTube");
+        let oembed = OEmbed {
+            title: Some("豚バラ大根 #shorts".to_string()),
+            author_name: Some("おうちごはんチャンネル".to_string()),
+            thumbnail_url: Some("https://i.ytimg.com/vi/abc123/hqdefault.jpg".to_string()),
+        };
+
+        let recipe = oembed.complete(platform, Some(consent)).unwrap();
+        assert_eq!(recipe.strategy, ExtractionStrategy::Video("youtube"));
+        assert_eq!(recipe.name, "豚バラ大根");
+        assert_eq!(recipe.author.as_deref(), Some("おうちごはんチャンネル"));
+    }
+
+    #[test_case("https://www.youtube.com/watch?v=abc" => true; "youtube")]
+    #[test_case("https://youtu.be/abc" => true; "short youtube link")]
+    #[test_case("https://www.tiktok.com/@user/video/1" => false; "other platform")]
+    #[test_case("https://notyoutube.com/watch" => false; "lookalike domain")]
+    fn test_platform_matches(url: &str) -> bool {
+        platforms()[0].matches(&url::Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn test_oembed_url() {
+        let url = platforms()[0]
+            .oembed_url(&url::Url::parse("https://youtu.be/abc").unwrap())
+            .unwrap();
+        assert_eq!(
+            url.as_str(),
+            "https://www.youtube.com/oembed?url=https%3A%2F%2Fyoutu.be%2Fabc&format=json"
+        );
+        assert_eq!(
+            platforms()[1]
+                .oembed_url(&url::Url::parse("https://www.instagram.com/p/abc/").unwrap()),
+            None
+        );
+    }
+
+    #[test_case("鶏の照り焼き #料理 #shorts" => Some("鶏の照り焼き".to_string()); "hashtags")]
+    #[test_case("\n#レシピ\n豚汁の作り方\n材料..." => Some("豚汁の作り方".to_string()); "first line with text")]
+    #[test_case("#shorts" => None; "only hashtags")]
+    fn test_video_title(title: &str) -> Option<String> {
+        video_title(title)
+    }
+
+    #[test_case(r#"user on Instagram: "なすの煮びたし""# => "なすの煮びたし"; "title")]
+    #[test_case(r#"12 likes - user on May 1, 2024: "なすの煮びたし"."# => "なすの煮びたし"; "description")]
+    #[test_case("鶏の照り焼き" => "鶏の照り焼き"; "unquoted")]
+    fn test_unquote_caption(text: &str) -> &str {
+        unquote_caption(text)
+    }
+
+    #[test]
+    fn test_parse_description() {
+        let text = "ご覧いただきありがとうございます。\n\n■材料（2〜3人分）\n・大根…1/3本\n・豚バラ肉…150g\n【煮汁】\n★しょうゆ 大さじ2\n\n■作り方\n1. 大根を切る。\n\n2. 豚肉と煮る。\n【ポイント】\n落としぶたをする。\n#豚バラ大根";
+        assert_eq!(
+            parse_description(text),
+            VideoDescription {
+                servings: Some("2〜3人分".to_string()),
+                ingredients: vec![
+                    "大根 1/3本".to_string(),
+                    "豚バラ肉 150g".to_string(),
+                    "しょうゆ 大さじ2".to_string(),
+                ],
+                steps: vec!["大根を切る。".to_string(), "豚肉と煮る。".to_string()],
+            }
+        );
+    }
+
+    #[test_case("材料(2人分)" => Some((Section::Ingredients, "(2人分)")); "ingredients")]
+    #[test_case("【作り方】" => Some((Section::Steps, "】")); "steps")]
+    #[test_case("Ingredients:" => Some((Section::Ingredients, ":")); "english")]
+    #[test_case("材料を切る" => None; "sentence")]
+    fn test_heading(line: &str) -> Option<(Section, &str)> {
+        heading(line)
+    }
+}
@@ -0,0 +1,95 @@
+//! In-memory stand-ins for the external services, for running the whole pipeline in tests.
+
+use std::{collections::HashMap, sync::Mutex};
+
+use async_trait::async_trait;
+
+use crate::{
+    config::{
+        AppConfig, DedupConfig, FetchConfig, JobConfig, NotionPropertyMapping, RepositoryConfig,
+        SyncConfig,
+    },
+    infra::{
+        html::{HtmlClient, ScrapedRecipe},
+        line::{LineClient, LineMessage},
+    },
+    prelude::*,
+};
+
+pub(crate) fn app_config() -> AppConfig {
+    AppConfig {
+        debug: true,
+        line_channel_access_token: "access_token".to_string(),
+        line_channel_secret: "channel_secret".to_string(),
+        notion_integration_token: "integration_token".to_string(),
+        notion_database_id: "database_id".to_string(),
+        notion_properties: NotionPropertyMapping::default(),
+        notion_bootstrap_schema: false,
+        port: 0,
+        jobs: JobConfig::default(),
+        dedup: DedupConfig::default(),
+        repository: RepositoryConfig::default(),
+        sync: SyncConfig::default(),
+        fetch: FetchConfig::default(),
+        admin_token: Some("admin_token".to_string()),
+    }
+}
+
+/// Serves the registered pages, failing the first `failures` requests.
+#[derive(Default)]
+pub(crate) struct FakeHtmlClient {
+    pages: HashMap<String, ScrapedRecipe>,
+    failures: Mutex<usize>,
+}
+
+impl FakeHtmlClient {
+    pub(crate) fn with_page(mut self, url: &str, recipe: ScrapedRecipe) -> Self {
+        self.pages.insert(url.to_string(), recipe);
+        self
+    }
+
+    pub(crate) fn failing(self, failures: usize) -> Self {
+        *self.failures.lock().unwrap() = failures;
+        self
+    }
+}
+
+#[async_trait]
+impl HtmlClient for FakeHtmlClient {
+    async fn get_recipe(&self, url: &str) -> Result<ScrapedRecipe> {
+        {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return Err(Error::Generic(format!("failed to fetch {url}")));
+            }
+        }
+        self.pages
+            .get(url)
+            .cloned()
+            .ok_or_else(|| Error::Generic(format!("no page at {url}")))
+    }
+}
+
+/// Records every message sent, keyed by reply token or push target.
+#[derive(Default)]
+pub(crate) struct FakeLineClient {
+    pub(crate) replies: Mutex<Vec<(String, Vec<LineMessage>)>>,
+    pub(crate) pushes: Mutex<Vec<(String, Vec<LineMessage>)>>,
+}
+
+#[async_trait]
+impl LineClient for FakeLineClient {
+    async fn reply_messages(&self, token: &str, message: Vec<LineMessage>) -> Result<()> {
+        self.replies
+            .lock()
+            .unwrap()
+            .push((token.to_string(), message));
+        Ok(())
+    }
+
+    async fn push_messages(&self, to: &str, message: Vec<LineMessage>) -> Result<()> {
+        self.pushes.lock().unwrap().push((to.to_string(), message));
+        Ok(())
+    }
+}
@@ -0,0 +1,461 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Context;
+use http::header::CONTENT_TYPE;
+use serde::de::DeserializeOwned;
+
+use crate::{config::FetchConfig, prelude::*};
+
+/// Content types accepted as web pages.
+const HTML_CONTENT_TYPES: [&str; 2] = ["text/html", "application/xhtml+xml"];
+
+/// Why a page was refused before or while it was downloaded.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum FetchError {
+    #[error("unsupported scheme: {0}")]
+    UnsupportedScheme(String),
+    #[error("address is not public: {0}")]
+    BlockedAddress(String),
+    #[error("more than {0} redirects")]
+    TooManyRedirects(usize),
+    #[error("unexpected status: {0}")]
+    Status(u16),
+    #[error("unsupported content type: {0}")]
+    UnsupportedContentType(String),
+    #[error("body is larger than {0} bytes")]
+    BodyTooLarge(usize),
+}
+
+/// A downloaded page.
+#[derive(Debug, Clone)]
+pub struct FetchedPage {
+    /// URL the page was served from, after redirects.
+    pub url: url::Url,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+/// Downloads pages from URLs sent by users without letting them reach internal services.
+///
+/// Host names are resolved by [`PublicResolver`], so addresses are checked for the first
+/// request and every redirect alike; IP literals are checked before each request. Proxies from
+/// the environment are ignored then, as a proxy would resolve the host instead.
+pub struct Fetcher {
+    client: reqwest::Client,
+    max_body_bytes: usize,
+    allow_private_addresses: bool,
+}
+
+impl Fetcher {
+    pub fn from_config(config: &FetchConfig) -> Result<Self> {
+        let max_redirects = config.max_redirects;
+        let allow_private_addresses = config.allow_private_addresses;
+        let redirect_policy = reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() > max_redirects {
+                return attempt.error(FetchError::TooManyRedirects(max_redirects));
+            }
+            match check_url(attempt.url(), allow_private_addresses) {
+                Ok(()) => attempt.follow(),
+                Err(e) => attempt.error(e),
+            }
+        });
+
+        let mut builder = reqwest::Client::builder()
+            .user_agent(&config.user_agent)
+            .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .redirect(redirect_policy);
+        if !allow_private_addresses {
+            builder = builder.no_proxy().dns_resolver(Arc::new(PublicResolver));
+        }
+
+        Ok(Self {
+            client: builder.build()?,
+            max_body_bytes: config.max_body_bytes,
+            allow_private_addresses,
+        })
+    }
+
+    /// Downloads an HTML page.
+    pub async fn get(&self, url: &str) -> Result<FetchedPage> {
+        self.download(url, is_html).await
+    }
+
+    /// Downloads and parses a JSON document, such as an oEmbed response.
+    pub async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let document = self.download(url, is_json).await?;
+        let value: T = serde_json::from_slice(&document.body)
+            .with_context(|| format!("invalid JSON from {}", document.url))?;
+        Ok(value)
+    }
+
+    async fn download(&self, url: &str, accepts: fn(&str) -> bool) -> Result<FetchedPage> {
+        let url = url::Url::parse(url)?;
+        check_url(&url, self.allow_private_addresses)?;
+
+        let mut response = self.client.get(url).send().await.map_err(from_reqwest)?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(FetchError::Status(status.as_u16()).into());
+        }
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        if let Some(content_type) = &content_type
+            && !accepts(content_type)
+        {
+            return Err(FetchError::UnsupportedContentType(content_type.clone()).into());
+        }
+        if response
+            .content_length()
+            .is_some_and(|length| length > self.max_body_bytes as u64)
+        {
+            return Err(FetchError::BodyTooLarge(self.max_body_bytes).into());
+        }
+
+        let url = response.url().clone();
+        // Content-Length may be missing or wrong, so the limit is also checked per chunk.
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(from_reqwest)? {
+            if body.len() + chunk.len() > self.max_body_bytes {
+                return Err(FetchError::BodyTooLarge(self.max_body_bytes).into());
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        Ok(FetchedPage {
+            url,
+            content_type,
+            body,
+        })
+    }
+}
+
+/// Resolves host names to their public addresses only, failing when there are none.
+struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(Box::new(FetchError::BlockedAddress(host)) as _);
+            }
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+fn check_url(url: &url::Url, allow_private_addresses: bool) -> std::result::Result<(), FetchError> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(FetchError::UnsupportedScheme(url.scheme().to_string()));
+    }
+    if allow_private_addresses {
+        return Ok(());
+    }
+    let ip = match url.host() {
+        Some(url::Host::Ipv4(ip)) => IpAddr::V4(ip),
+        Some(url::Host::Ipv6(ip)) => IpAddr::V6(ip),
+        // Names are checked when they are resolved.
+        Some(url::Host::Domain(_)) => return Ok(()),
+        None => return Err(FetchError::BlockedAddress(url.to_string())),
+    };
+    if is_public(ip) {
+        Ok(())
+    } else {
+        Err(FetchError::BlockedAddress(ip.to_string()))
+    }
+}
+
+fn is_html(content_type: &str) -> bool {
+    let mime = mime_type(content_type);
+    HTML_CONTENT_TYPES
+        .iter()
+        .any(|html| mime.eq_ignore_ascii_case(html))
+}
+
+/// Accepts `application/json` and its variants such as `application/json+oembed`.
+fn is_json(content_type: &str) -> bool {
+    let mime = mime_type(content_type).to_ascii_lowercase();
+    mime == "text/json" || mime.starts_with("application/json") || mime.ends_with("+json")
+}
+
+fn mime_type(content_type: &str) -> &str {
+    content_type.split(';').next().unwrap_or_default().trim()
+}
+
+/// Whether the address is reachable on the public internet.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => is_public_v6(ip),
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        // Shared address space used by carrier-grade NAT.
+        || (a == 100 && (64..128).contains(&b))
+        // IETF protocol assignments.
+        || (a == 192 && b == 0 && c == 0)
+        // Benchmarking.
+        || (a == 198 && (18..20).contains(&b))
+        // Reserved.
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    if let Some(ip) = ip.to_ipv4_mapped() {
+        return is_public_v4(ip);
+    }
+    let segments = ip.segments();
+    // NAT64 addresses embed an IPv4 address.
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [a, b] = segments[6].to_be_bytes();
+        let [c, d] = segments[7].to_be_bytes();
+        return is_public_v4(Ipv4Addr::new(a, b, c, d));
+    }
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // Unique local addresses.
+        || (segments[0] & 0xfe00) == 0xfc00
+        // Link-local addresses.
+        || (segments[0] & 0xffc0) == 0xfe80
+        // Documentation.
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8))
+}
+
+/// Keeps the refusals raised inside reqwest, such as by the resolver or on redirects,
+/// distinguishable from network errors.
+fn from_reqwest(e: reqwest::Error) -> Error {
+    let mut source = std::error::Error::source(&e);
+    while let Some(error) = source {
+        if let Some(fetch_error) = error.downcast_ref::<FetchError>() {
+            return fetch_error.clone().into();
+        }
+        source = error.source();
+    }
+    e.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Router,
+        extract::Path,
+        http::HeaderMap,
+        response::{IntoResponse, Redirect},
+        routing::get,
+    };
+    use test_case::test_case;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[test_case("8.8.8.8" => true; "public v4")]
+    #[test_case("127.0.0.1" => false; "loopback")]
+    #[test_case("10.0.0.1" => false; "private")]
+    #[test_case("172.16.0.1" => false; "private 172")]
+    #[test_case("192.168.1.1" => false; "private 192")]
+    #[test_case("169.254.169.254" => false; "metadata")]
+    #[test_case("100.64.0.1" => false; "shared")]
+    #[test_case("0.0.0.0" => false; "unspecified")]
+    #[test_case("2001:4860:4860::8888" => true; "public v6")]
+    #[test_case("::1" => false; "loopback v6")]
+    #[test_case("fd00::1" => false; "unique local")]
+    #[test_case("fe80::1" => false; "link-local v6")]
+    #[test_case("::ffff:127.0.0.1" => false; "mapped loopback")]
+    #[test_case("64:ff9b::a9fe:a9fe" => false; "nat64 metadata")]
+    fn test_is_public(ip: &str) -> bool {
+        is_public(ip.parse().unwrap())
+    }
+
+    #[test_case("https://example.com/recipe" => Ok(()); "domain")]
+    #[test_case("ftp://example.com/recipe" => Err(FetchError::UnsupportedScheme("ftp".to_string())); "ftp")]
+    #[test_case("file:///etc/passwd" => Err(FetchError::UnsupportedScheme("file".to_string())); "file")]
+    #[test_case("http://169.254.169.254/latest/meta-data" => Err(FetchError::BlockedAddress("169.254.169.254".to_string())); "metadata")]
+    #[test_case("http://[::1]:8080/" => Err(FetchError::BlockedAddress("::1".to_string())); "loopback v6")]
+    fn test_check_url(url: &str) -> std::result::Result<(), FetchError> {
+        check_url(&url::Url::parse(url).unwrap(), false)
+    }
+
+    #[test_case("text/html; charset=utf-8" => true; "html")]
+    #[test_case("application/xhtml+xml" => true; "xhtml")]
+    #[test_case("TEXT/HTML" => true; "upper case")]
+    #[test_case("application/json" => false; "json")]
+    #[test_case("image/png" => false; "image")]
+    fn test_is_html(content_type: &str) -> bool {
+        is_html(content_type)
+    }
+
+    #[test_case("application/json; charset=utf-8" => true; "json")]
+    #[test_case("application/json+oembed" => true; "oembed")]
+    #[test_case("text/html" => false; "html")]
+    fn test_is_json(content_type: &str) -> bool {
+        is_json(content_type)
+    }
+
+    fn html(body: impl Into<String>) -> impl IntoResponse {
+        ([(CONTENT_TYPE, "text/html; charset=utf-8")], body.into())
+    }
+
+    /// Serves test pages on a local port and returns its base URL.
+    async fn serve() -> String {
+        let app = Router::new()
+            .route("/", get(|| async { html("<title>ok</title>") }))
+            .route(
+                "/user-agent",
+                get(|headers: HeaderMap| async move {
+                    html(headers["user-agent"].to_str().unwrap().to_string())
+                }),
+            )
+            .route(
+                "/json",
+                get(|| async { ([(CONTENT_TYPE, "application/json")], "{}") }),
+            )
+            .route("/large", get(|| async { html("a".repeat(2048)) }))
+            .route(
+                "/redirect/{n}",
+                get(|Path(n): Path<usize>| async move {
+                    match n {
+                        0 => Redirect::temporary("/").into_response(),
+                        n => Redirect::temporary(&format!("/redirect/{}", n - 1)).into_response(),
+                    }
+                }),
+            )
+            .route(
+                "/redirect-ftp",
+                get(|| async { Redirect::temporary("ftp://example.com/") }),
+            )
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    html("late")
+                }),
+            );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{addr}")
+    }
+
+    fn fetcher(allow_private_addresses: bool) -> Fetcher {
+        Fetcher::from_config(&FetchConfig {
+            user_agent: "recipena-test".to_string(),
+            timeout_secs: 1,
+            max_redirects: 2,
+            max_body_bytes: 1024,
+            allow_private_addresses,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn fetch_error(result: Result<FetchedPage>) -> FetchError {
+        match result {
+            Err(Error::FetchError(e)) => e,
+            other => panic!("expected a fetch error, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_refuses_local_server() {
+        let base = serve().await;
+        let localhost = base.replace("127.0.0.1", "localhost");
+        for url in [base, localhost] {
+            assert!(matches!(
+                fetch_error(fetcher(false).get(&url).await),
+                FetchError::BlockedAddress(_)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_page() {
+        let base = serve().await;
+        let page = fetcher(true)
+            .get(&format!("{base}/user-agent"))
+            .await
+            .unwrap();
+        assert_eq!(page.body, b"recipena-test");
+        assert_eq!(
+            page.content_type.as_deref(),
+            Some("text/html; charset=utf-8")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_follows_redirects_up_to_limit() {
+        let base = serve().await;
+        let page = fetcher(true)
+            .get(&format!("{base}/redirect/1"))
+            .await
+            .unwrap();
+        assert_eq!(page.url.path(), "/");
+
+        assert_eq!(
+            fetch_error(fetcher(true).get(&format!("{base}/redirect/5")).await),
+            FetchError::TooManyRedirects(2)
+        );
+        assert!(
+            fetcher(true)
+                .get(&format!("{base}/redirect-ftp"))
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_rejects_non_html_and_large_pages() {
+        let base = serve().await;
+        assert_eq!(
+            fetch_error(fetcher(true).get(&format!("{base}/json")).await),
+            FetchError::UnsupportedContentType("application/json".to_string())
+        );
+        assert_eq!(
+            fetch_error(fetcher(true).get(&format!("{base}/large")).await),
+            FetchError::BodyTooLarge(1024)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_json() {
+        let base = serve().await;
+        let value: serde_json::Value = fetcher(true)
+            .get_json(&format!("{base}/json"))
+            .await
+            .unwrap();
+        assert_eq!(value, serde_json::json!({}));
+        assert!(matches!(
+            fetcher(true).get_json::<serde_json::Value>(&base).await,
+            Err(Error::FetchError(FetchError::UnsupportedContentType(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_times_out() {
+        let base = serve().await;
+        let result = fetcher(true).get(&format!("{base}/slow")).await;
+        assert!(matches!(result, Err(Error::ReqwestError(e)) if e.is_timeout()));
+    }
+}
@@ -0,0 +1,216 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use tokio::sync::{Mutex as AsyncMutex, mpsc};
+
+use crate::{
+    infra::job::{FailedJob, Job, JobQueue, RetryPolicy},
+    prelude::*,
+};
+
+/// A bounded job queue kept in memory. Queued jobs are lost when the process exits.
+pub struct MemoryJobQueue {
+    /// Dropped on close, which ends the queue once the remaining jobs are taken.
+    sender: Mutex<Option<mpsc::Sender<Job>>>,
+    receiver: AsyncMutex<mpsc::Receiver<Job>>,
+    failed: Mutex<Vec<FailedJob>>,
+    retry_policy: RetryPolicy,
+}
+
+impl MemoryJobQueue {
+    pub fn new(capacity: usize, retry_policy: RetryPolicy) -> Self {
+        let (sender, receiver) = mpsc::channel(capacity);
+        Self {
+            sender: Mutex::new(Some(sender)),
+            receiver: AsyncMutex::new(receiver),
+            failed: Mutex::new(Vec::new()),
+            retry_policy,
+        }
+    }
+
+    fn sender(&self) -> Result<mpsc::Sender<Job>> {
+        self.sender
+            .lock()
+            .expect("job queue lock is poisoned")
+            .clone()
+            .ok_or_else(|| Error::JobQueueError("queue is closed".to_string()))
+    }
+}
+
+#[async_trait]
+impl JobQueue for MemoryJobQueue {
+    async fn enqueue(&self, job: Job) -> Result<()> {
+        self.sender()?.try_send(job).map_err(|e| match e {
+            mpsc::error::TrySendError::Full(_) => Error::JobQueueError("queue is full".to_string()),
+            mpsc::error::TrySendError::Closed(_) => {
+                Error::JobQueueError("queue is closed".to_string())
+            }
+        })
+    }
+
+    async fn dequeue(&self) -> Result<Option<Job>> {
+        Ok(self.receiver.lock().await.recv().await)
+    }
+
+    async fn complete(&self, _job: &Job) -> Result<()> {
+        Ok(())
+    }
+
+    async fn fail(&self, job: &Job, error: &str, retry: bool) -> Result<bool> {
+        let attempts = job.attempts + 1;
+        let next_delay = retry.then(|| self.retry_policy.next_delay(attempts));
+        let Some(delay) = next_delay.flatten() else {
+            self.failed
+                .lock()
+                .expect("job queue lock is poisoned")
+                .push(FailedJob {
+                    id: job.id,
+                    event: job.event.clone(),
+                    attempts,
+                    last_error: error.to_string(),
+                    enqueued_at: job.enqueued_at,
+                    failed_at: Utc::now(),
+                });
+            return Ok(true);
+        };
+
+        // A weak sender lets the queue close while the retry is waiting.
+        let sender = self.sender()?.downgrade();
+        let job = Job {
+            attempts,
+            ..job.clone()
+        };
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            let job_id = job.id;
+            let sent = match sender.upgrade() {
+                Some(sender) => sender.send(job).await.is_ok(),
+                None => false,
+            };
+            if !sent {
+                tracing::warn!(%job_id, "job queue closed before the job was retried");
+            }
+        });
+        Ok(false)
+    }
+
+    async fn failed_jobs(&self) -> Result<Vec<FailedJob>> {
+        let mut failed = self
+            .failed
+            .lock()
+            .expect("job queue lock is poisoned")
+            .clone();
+        failed.reverse();
+        Ok(failed)
+    }
+
+    async fn requeue(&self, id: ulid::Ulid) -> Result<bool> {
+        let failed = {
+            let mut failed = self.failed.lock().expect("job queue lock is poisoned");
+            match failed.iter().position(|job| job.id == id) {
+                Some(index) => failed.remove(index),
+                None => return Ok(false),
+            }
+        };
+        self.enqueue(Job {
+            id: failed.id,
+            event: failed.event,
+            attempts: 0,
+            enqueued_at: failed.enqueued_at,
+        })
+        .await?;
+        Ok(true)
+    }
+
+    async fn close(&self) {
+        self.sender
+            .lock()
+            .expect("job queue lock is poisoned")
+            .take();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::time::Duration;
+
+    use line_bot_sdk_rust::line_webhook::models::Event;
+    use serde_json::json;
+
+    use super::*;
+
+    pub(crate) fn job() -> Job {
+        let event: Event = serde_json::from_value(json!({
+            "type": "unfollow",
+            "mode": "active",
+            "timestamp": 1462629479859_i64,
+            "source": { "type": "user", "userId": "U4af4980629" },
+            "webhookEventId": "01FZ74A0TDDPYRVKNK77XKC3ZR",
+            "deliveryContext": { "isRedelivery": false },
+        }))
+        .unwrap();
+        Job::new(event)
+    }
+
+    pub(crate) fn retry_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_fails_when_full() {
+        let queue = MemoryJobQueue::new(1, retry_policy(1));
+        assert!(queue.enqueue(job()).await.is_ok());
+        assert!(queue.enqueue(job()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_close_drains_queued_jobs() {
+        let queue = MemoryJobQueue::new(2, retry_policy(1));
+        let queued = job();
+        queue.enqueue(queued.clone()).await.unwrap();
+        queue.close().await;
+
+        assert!(queue.enqueue(job()).await.is_err());
+        assert_eq!(
+            queue.dequeue().await.unwrap().map(|job| job.id),
+            Some(queued.id)
+        );
+        assert!(queue.dequeue().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_fail_retries_then_dead_letters() {
+        let queue = MemoryJobQueue::new(2, retry_policy(2));
+        queue.enqueue(job()).await.unwrap();
+
+        let first = queue.dequeue().await.unwrap().unwrap();
+        assert!(!queue.fail(&first, "timeout", true).await.unwrap());
+        let retried = queue.dequeue().await.unwrap().unwrap();
+        assert_eq!((retried.id, retried.attempts), (first.id, 1));
+
+        assert!(queue.fail(&retried, "timeout", true).await.unwrap());
+        let failed = queue.failed_jobs().await.unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].attempts, 2);
+
+        assert!(queue.requeue(first.id).await.unwrap());
+        assert!(queue.failed_jobs().await.unwrap().is_empty());
+        assert_eq!(queue.dequeue().await.unwrap().unwrap().attempts, 0);
+    }
+    #[tokio::test]
+    async fn test_fail_without_retry_dead_letters_at_once() {
+        let queue = MemoryJobQueue::new(2, retry_policy(3));
+        queue.enqueue(job()).await.unwrap();
+
+        let first = queue.dequeue().await.unwrap().unwrap();
+        assert!(queue.fail(&first, "invalid url", false).await.unwrap());
+        let failed = queue.failed_jobs().await.unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].attempts, 1);
+    }
+}
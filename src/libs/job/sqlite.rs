@@ -0,0 +1,381 @@
+use std::{
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, Row, params};
+use tokio::sync::Notify;
+
+use crate::{
+    infra::job::{FailedJob, Job, JobQueue, RetryPolicy},
+    libs::sqlite::SqliteConnection,
+    prelude::*,
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS jobs (
+    id TEXT PRIMARY KEY,
+    event TEXT NOT NULL,
+    attempts INTEGER NOT NULL,
+    enqueued_at INTEGER NOT NULL,
+    run_at INTEGER NOT NULL,
+    running INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT
+);
+CREATE INDEX IF NOT EXISTS jobs_run_at ON jobs (running, run_at);
+CREATE TABLE IF NOT EXISTS dead_jobs (
+    id TEXT PRIMARY KEY,
+    event TEXT NOT NULL,
+    attempts INTEGER NOT NULL,
+    last_error TEXT NOT NULL,
+    enqueued_at INTEGER NOT NULL,
+    failed_at INTEGER NOT NULL
+);
+";
+
+/// How often the queue is checked for jobs whose retry delay has passed.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A job queue persisted in a SQLite file, so queued jobs survive restarts.
+///
+/// Timestamps are stored as Unix milliseconds and events as their webhook JSON.
+pub struct SqliteJobQueue {
+    connection: SqliteConnection,
+    notify: Notify,
+    closed: AtomicBool,
+    capacity: usize,
+    retry_policy: RetryPolicy,
+}
+
+impl SqliteJobQueue {
+    pub fn open(
+        path: impl AsRef<Path>,
+        capacity: usize,
+        retry_policy: RetryPolicy,
+    ) -> Result<Self> {
+        Self::from_connection(Connection::open(path)?, capacity, retry_policy)
+    }
+
+    pub fn open_in_memory(capacity: usize, retry_policy: RetryPolicy) -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?, capacity, retry_policy)
+    }
+
+    fn from_connection(
+        connection: Connection,
+        capacity: usize,
+        retry_policy: RetryPolicy,
+    ) -> Result<Self> {
+        connection.execute_batch(SCHEMA)?;
+        // Jobs still marked as running were interrupted by the previous process.
+        let interrupted =
+            connection.execute("UPDATE jobs SET running = 0 WHERE running = 1", [])?;
+        if interrupted > 0 {
+            tracing::info!(interrupted, "requeued jobs interrupted by a restart");
+        }
+        Ok(Self {
+            connection: SqliteConnection::new(connection),
+            notify: Notify::new(),
+            closed: AtomicBool::new(false),
+            capacity,
+            retry_policy,
+        })
+    }
+
+    /// Marks the next due job as running and returns it.
+    async fn claim_next(&self) -> Result<Option<Job>> {
+        self.connection
+            .call(|connection| {
+                let transaction = connection.transaction()?;
+                let job = transaction
+                    .query_row(
+                        "SELECT id, event, attempts, enqueued_at FROM jobs
+                         WHERE running = 0 AND run_at <= ?1 ORDER BY run_at LIMIT 1",
+                        params![Utc::now().timestamp_millis()],
+                        |row| {
+                            Ok((
+                                row.get::<_, String>(0)?,
+                                row.get::<_, String>(1)?,
+                                row.get::<_, u32>(2)?,
+                                row.get::<_, i64>(3)?,
+                            ))
+                        },
+                    )
+                    .optional()?;
+                let Some((id, event, attempts, enqueued_at)) = job else {
+                    return Ok(None);
+                };
+
+                let job = parse_id(&id).and_then(|job_id| {
+                    Ok(Job {
+                        id: job_id,
+                        event: parse_event(&event)?,
+                        attempts,
+                        enqueued_at: timestamp(enqueued_at),
+                    })
+                });
+                match job {
+                    Ok(_) => transaction
+                        .execute("UPDATE jobs SET running = 1 WHERE id = ?1", params![id])?,
+                    Err(ref e) => {
+                        // The row can never be processed, so keeping it would block the queue.
+                        tracing::error!(%e, job_id = %id, "dropping unreadable job");
+                        transaction.execute("DELETE FROM jobs WHERE id = ?1", params![id])?
+                    }
+                };
+                transaction.commit()?;
+                Ok(job.ok())
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl JobQueue for SqliteJobQueue {
+    async fn enqueue(&self, job: Job) -> Result<()> {
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(Error::JobQueueError("queue is closed".to_string()));
+        }
+        let event =
+            serde_json::to_string(&job.event).with_context(|| "failed to serialize event")?;
+        let capacity = self.capacity;
+        self.connection
+            .call(move |connection| {
+                let queued: i64 =
+                    connection.query_row("SELECT COUNT(*) FROM jobs", [], |row| row.get(0))?;
+                if queued as usize >= capacity {
+                    return Err(Error::JobQueueError("queue is full".to_string()));
+                }
+                connection.execute(
+                    "INSERT INTO jobs (id, event, attempts, enqueued_at, run_at)
+                     VALUES (?1, ?2, ?3, ?4, ?4)",
+                    params![
+                        job.id.to_string(),
+                        event,
+                        job.attempts,
+                        job.enqueued_at.timestamp_millis()
+                    ],
+                )?;
+                Ok(())
+            })
+            .await?;
+        self.notify.notify_one();
+        Ok(())
+    }
+
+    async fn dequeue(&self) -> Result<Option<Job>> {
+        loop {
+            if self.closed.load(Ordering::SeqCst) {
+                return Ok(None);
+            }
+            if let Some(job) = self.claim_next().await? {
+                return Ok(Some(job));
+            }
+            tokio::select! {
+                _ = self.notify.notified() => {},
+                _ = tokio::time::sleep(POLL_INTERVAL) => {},
+            }
+        }
+    }
+
+    async fn complete(&self, job: &Job) -> Result<()> {
+        let id = job.id.to_string();
+        self.connection
+            .call(move |connection| {
+                connection.execute("DELETE FROM jobs WHERE id = ?1", params![id])?;
+                Ok(())
+            })
+            .await
+    }
+
+    async fn fail(&self, job: &Job, error: &str, retry: bool) -> Result<bool> {
+        let id = job.id.to_string();
+        let error = error.to_string();
+        let attempts = job.attempts + 1;
+        let next_delay = retry
+            .then(|| self.retry_policy.next_delay(attempts))
+            .flatten();
+        self.connection
+            .call(move |connection| {
+                let now = Utc::now();
+                match next_delay {
+                    Some(delay) => {
+                        let run_at = now.timestamp_millis() + delay.as_millis() as i64;
+                        connection.execute(
+                        "UPDATE jobs SET running = 0, attempts = ?2, run_at = ?3, last_error = ?4
+                         WHERE id = ?1",
+                        params![id, attempts, run_at, error],
+                    )?;
+                    }
+                    None => {
+                        let transaction = connection.transaction()?;
+                        transaction.execute(
+                            "INSERT OR REPLACE INTO dead_jobs
+                             (id, event, attempts, last_error, enqueued_at, failed_at)
+                             SELECT id, event, ?2, ?3, enqueued_at, ?4 FROM jobs WHERE id = ?1",
+                            params![id, attempts, error, now.timestamp_millis()],
+                        )?;
+                        transaction.execute("DELETE FROM jobs WHERE id = ?1", params![id])?;
+                        transaction.commit()?;
+                    }
+                }
+                Ok(())
+            })
+            .await?;
+        if next_delay.is_none() {
+            tracing::warn!(job_id = %job.id, attempts, "job moved to the dead letters");
+        }
+        Ok(next_delay.is_none())
+    }
+
+    async fn failed_jobs(&self) -> Result<Vec<FailedJob>> {
+        self.connection
+            .call(|connection| {
+                let mut statement = connection.prepare(
+                    "SELECT id, event, attempts, last_error, enqueued_at, failed_at FROM dead_jobs
+                     ORDER BY failed_at DESC",
+                )?;
+                let rows = statement.query_map([], failed_job_row)?;
+                let mut failed = Vec::new();
+                for row in rows {
+                    let (id, event, attempts, last_error, enqueued_at, failed_at) = row?;
+                    failed.push(FailedJob {
+                        id: parse_id(&id)?,
+                        event: parse_event(&event)?,
+                        attempts,
+                        last_error,
+                        enqueued_at: timestamp(enqueued_at),
+                        failed_at: timestamp(failed_at),
+                    });
+                }
+                Ok(failed)
+            })
+            .await
+    }
+
+    async fn requeue(&self, id: ulid::Ulid) -> Result<bool> {
+        let id = id.to_string();
+        let requeued = self
+            .connection
+            .call(move |connection| {
+                let transaction = connection.transaction()?;
+                let inserted = transaction.execute(
+                    "INSERT INTO jobs (id, event, attempts, enqueued_at, run_at)
+                     SELECT id, event, 0, enqueued_at, ?2 FROM dead_jobs WHERE id = ?1",
+                    params![id, Utc::now().timestamp_millis()],
+                )?;
+                transaction.execute("DELETE FROM dead_jobs WHERE id = ?1", params![id])?;
+                transaction.commit()?;
+                Ok(inserted > 0)
+            })
+            .await?;
+        if requeued {
+            self.notify.notify_one();
+        }
+        Ok(requeued)
+    }
+
+    async fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+}
+
+type FailedJobRow = (String, String, u32, String, i64, i64);
+
+fn failed_job_row(row: &Row) -> rusqlite::Result<FailedJobRow> {
+    Ok((
+        row.get(0)?,
+        row.get(1)?,
+        row.get(2)?,
+        row.get(3)?,
+        row.get(4)?,
+        row.get(5)?,
+    ))
+}
+
+fn parse_id(id: &str) -> Result<ulid::Ulid> {
+    Ok(ulid::Ulid::from_string(id).with_context(|| format!("invalid job id: {id}"))?)
+}
+
+fn parse_event(event: &str) -> Result<line_bot_sdk_rust::line_webhook::models::Event> {
+    Ok(serde_json::from_str(event).with_context(|| "failed to deserialize event")?)
+}
+
+fn timestamp(millis: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_millis(millis).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::libs::job::memory::tests::{job, retry_policy};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_complete_removes_job() {
+        let queue = SqliteJobQueue::open_in_memory(10, retry_policy(1)).unwrap();
+        let queued = job();
+        queue.enqueue(queued.clone()).await.unwrap();
+
+        let dequeued = queue.dequeue().await.unwrap().unwrap();
+        assert_eq!(dequeued.id, queued.id);
+        queue.complete(&dequeued).await.unwrap();
+
+        queue.close().await;
+        assert!(queue.dequeue().await.unwrap().is_none());
+        assert!(queue.claim_next().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_fails_when_full() {
+        let queue = SqliteJobQueue::open_in_memory(1, retry_policy(1)).unwrap();
+        assert!(queue.enqueue(job()).await.is_ok());
+        assert!(queue.enqueue(job()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fail_retries_then_dead_letters() {
+        let queue = SqliteJobQueue::open_in_memory(10, retry_policy(2)).unwrap();
+        queue.enqueue(job()).await.unwrap();
+
+        let first = queue.dequeue().await.unwrap().unwrap();
+        assert!(!queue.fail(&first, "timeout", true).await.unwrap());
+        let retried = queue.dequeue().await.unwrap().unwrap();
+        assert_eq!((retried.id, retried.attempts), (first.id, 1));
+
+        assert!(queue.fail(&retried, "timeout", true).await.unwrap());
+        assert!(queue.claim_next().await.unwrap().is_none());
+        let failed = queue.failed_jobs().await.unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(
+            (failed[0].attempts, failed[0].last_error.as_str()),
+            (2, "timeout")
+        );
+
+        assert!(queue.requeue(first.id).await.unwrap());
+        assert!(!queue.requeue(first.id).await.unwrap());
+        assert!(queue.failed_jobs().await.unwrap().is_empty());
+        assert_eq!(queue.dequeue().await.unwrap().unwrap().attempts, 0);
+    }
+
+    #[tokio::test]
+    async fn test_open_recovers_interrupted_jobs() {
+        let path = std::env::temp_dir().join(format!("recipena-{}.sqlite3", ulid::Ulid::new()));
+        let queued = job();
+        {
+            let queue = SqliteJobQueue::open(&path, 10, retry_policy(1)).unwrap();
+            queue.enqueue(queued.clone()).await.unwrap();
+            // Dequeued but never completed, as if the process was killed.
+            queue.dequeue().await.unwrap().unwrap();
+        }
+
+        let queue = SqliteJobQueue::open(&path, 10, retry_policy(1)).unwrap();
+        let recovered = queue.dequeue().await.unwrap().map(|job| job.id);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(recovered, Some(queued.id));
+    }
+}
@@ -0,0 +1,243 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use crate::{
+    config::JobConfig,
+    infra::job::{Job, JobHandler, JobQueue},
+    prelude::*,
+};
+
+/// Wait before polling the queue again after it failed.
+const DEQUEUE_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Takes jobs from a queue and runs them with bounded concurrency.
+pub struct WorkerPool {
+    queue: Arc<dyn JobQueue + Send + Sync>,
+    handler: Arc<dyn JobHandler + Send + Sync>,
+    concurrency: usize,
+    job_timeout: Duration,
+}
+
+impl WorkerPool {
+    pub fn new(
+        queue: Arc<dyn JobQueue + Send + Sync>,
+        handler: Arc<dyn JobHandler + Send + Sync>,
+        config: &JobConfig,
+    ) -> Self {
+        Self {
+            queue,
+            handler,
+            concurrency: config.concurrency.max(1),
+            job_timeout: Duration::from_secs(config.timeout_secs),
+        }
+    }
+
+    /// Runs jobs until the queue is closed and empty, then waits for the running ones.
+    pub async fn run(self) {
+        let semaphore = Arc::new(Semaphore::new(self.concurrency));
+        let mut running = JoinSet::new();
+        loop {
+            let permit = semaphore
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("worker semaphore is never closed");
+            let job = match self.queue.dequeue().await {
+                Ok(Some(job)) => job,
+                Ok(None) => break,
+                Err(e) => {
+                    tracing::error!(%e, "failed to dequeue job");
+                    tokio::time::sleep(DEQUEUE_RETRY_INTERVAL).await;
+                    continue;
+                }
+            };
+
+            let queue = self.queue.clone();
+            let handler = self.handler.clone();
+            let job_timeout = self.job_timeout;
+            running.spawn(async move {
+                run_job(queue.as_ref(), handler.as_ref(), job, job_timeout).await;
+                drop(permit);
+            });
+            while let Some(result) = running.try_join_next() {
+                log_panic(result);
+            }
+        }
+
+        tracing::info!(
+            jobs = running.len(),
+            "job queue closed, waiting for running jobs"
+        );
+        while let Some(result) = running.join_next().await {
+            log_panic(result);
+        }
+    }
+}
+
+/// Runs a job and reports the outcome to the queue, which decides whether to retry it. Only
+/// failures that may pass later are retried. The handler is told when the job is
+/// dead-lettered, including after a timeout.
+async fn run_job(
+    queue: &(dyn JobQueue + Send + Sync),
+    handler: &(dyn JobHandler + Send + Sync),
+    job: Job,
+    job_timeout: Duration,
+) {
+    let job_id = job.id;
+    let error = match tokio::time::timeout(job_timeout, handler.handle(&job)).await {
+        Ok(Ok(())) => {
+            if let Err(e) = queue.complete(&job).await {
+                tracing::error!(%e, %job_id, "failed to record the job outcome");
+            }
+            return;
+        }
+        Ok(Err(e)) => {
+            tracing::warn!(%e, %job_id, attempts = job.attempts, "job failed");
+            e
+        }
+        Err(_) => {
+            tracing::error!(%job_id, ?job_timeout, "job timed out");
+            Error::Generic(format!("job timed out after {job_timeout:?}"))
+        }
+    };
+    let retry = error.category().is_retryable();
+    match queue.fail(&job, &error.to_string(), retry).await {
+        Ok(true) => handler.on_dead_letter(&job, &error).await,
+        Ok(false) => {}
+        Err(e) => tracing::error!(%e, %job_id, "failed to record the job outcome"),
+    }
+}
+
+fn log_panic(result: std::result::Result<(), tokio::task::JoinError>) {
+    if let Err(e) = result {
+        tracing::error!(%e, "job panicked");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use async_trait::async_trait;
+
+    use crate::{
+        libs::job::memory::{
+            MemoryJobQueue,
+            tests::{job, retry_policy},
+        },
+        prelude::*,
+    };
+
+    use super::*;
+
+    /// Records how many jobs ran, how many ran at the same time and how many were given up.
+    #[derive(Default)]
+    struct SlowHandler {
+        delay: Duration,
+        running: AtomicUsize,
+        max_running: AtomicUsize,
+        finished: AtomicUsize,
+        dead_lettered: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl JobHandler for SlowHandler {
+        async fn handle(&self, _job: &Job) -> Result<()> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            tokio::time::sleep(self.delay).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            self.finished.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn on_dead_letter(&self, _job: &Job, _error: &Error) {
+            self.dead_lettered.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Fails every job with an invalid link, which is not worth retrying.
+    #[derive(Default)]
+    struct InvalidLinkHandler {
+        attempts: AtomicUsize,
+        dead_lettered: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl JobHandler for InvalidLinkHandler {
+        async fn handle(&self, _job: &Job) -> Result<()> {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            Err(Error::UrlError(url::ParseError::EmptyHost))
+        }
+
+        async fn on_dead_letter(&self, _job: &Job, _error: &Error) {
+            self.dead_lettered.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn config(concurrency: usize, timeout_secs: u64) -> JobConfig {
+        JobConfig {
+            concurrency,
+            timeout_secs,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_run_limits_concurrency_and_drains() {
+        let queue = Arc::new(MemoryJobQueue::new(10, retry_policy(1)));
+        for _ in 0..5 {
+            queue.enqueue(job()).await.unwrap();
+        }
+        queue.close().await;
+        let handler = Arc::new(SlowHandler {
+            delay: Duration::from_millis(100),
+            ..Default::default()
+        });
+
+        WorkerPool::new(queue, handler.clone(), &config(2, 60))
+            .run()
+            .await;
+
+        assert_eq!(handler.finished.load(Ordering::SeqCst), 5);
+        assert_eq!(handler.max_running.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_run_cancels_timed_out_jobs() {
+        let queue = Arc::new(MemoryJobQueue::new(10, retry_policy(1)));
+        queue.enqueue(job()).await.unwrap();
+        queue.close().await;
+        let handler = Arc::new(SlowHandler {
+            delay: Duration::from_secs(120),
+            ..Default::default()
+        });
+
+        WorkerPool::new(queue.clone(), handler.clone(), &config(1, 60))
+            .run()
+            .await;
+
+        assert_eq!(handler.finished.load(Ordering::SeqCst), 0);
+        assert_eq!(queue.failed_jobs().await.unwrap().len(), 1);
+        assert_eq!(handler.dead_lettered.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_run_dead_letters_invalid_input_at_once() {
+        let queue = Arc::new(MemoryJobQueue::new(10, retry_policy(3)));
+        queue.enqueue(job()).await.unwrap();
+        queue.close().await;
+        let handler = Arc::new(InvalidLinkHandler::default());
+
+        WorkerPool::new(queue.clone(), handler.clone(), &config(1, 60))
+            .run()
+            .await;
+
+        assert_eq!(handler.attempts.load(Ordering::SeqCst), 1);
+        assert_eq!(handler.dead_lettered.load(Ordering::SeqCst), 1);
+        let failed = queue.failed_jobs().await.unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].attempts, 1);
+    }
+}
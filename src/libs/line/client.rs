@@ -4,7 +4,10 @@ use crate::{infra::line::LineMessage, prelude::*};
 use async_trait::async_trait;
 use line_bot_sdk_rust::{
     client::LINE,
-    line_messaging_api::{apis::MessagingApiApi, models::ReplyMessageRequest},
+    line_messaging_api::{
+        apis::MessagingApiApi,
+        models::{PushMessageRequest, ReplyMessageRequest},
+    },
 };
 
 use crate::infra::line::LineClient;
@@ -38,4 +41,19 @@ impl LineClient for LineClientImpl {
             .map_err(Into::<crate::libs::line::error::LineClientError>::into)?;
         Ok(())
     }
+
+    async fn push_messages(&self, to: &str, message: Vec<LineMessage>) -> Result<()> {
+        let request = PushMessageRequest {
+            to: to.to_string(),
+            messages: message.into_iter().map(|m| m.into()).collect(),
+            notification_disabled: None,
+            custom_aggregation_units: None,
+        };
+        self.client
+            .messaging_api_client
+            .push_message(request, None)
+            .await
+            .map_err(Into::<crate::libs::line::error::LineClientError>::into)?;
+        Ok(())
+    }
 }
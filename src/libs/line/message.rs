@@ -1,15 +1,400 @@
-use line_bot_sdk_rust::line_messaging_api::models::TextMessage;
+use line_bot_sdk_rust::line_messaging_api::models::{
+    Action, ButtonsTemplate, ImageMessage, Message, PostbackAction, PostbackActionInputOption,
+    StickerMessage, Template, TemplateMessage, TextMessage, UriAction,
+};
+use serde_json::{Value, json};
 
-use crate::infra::line::LineMessage;
+use crate::infra::line::{FlexBubble, LineAction, LineMessage};
 
-impl From<LineMessage> for line_bot_sdk_rust::line_messaging_api::models::Message {
+/// Maximum number of quick-reply buttons on a message.
+const MAX_QUICK_REPLY_ITEMS: usize = 13;
+/// Maximum number of bubbles in a flex carousel.
+const MAX_FLEX_CAROUSEL_BUBBLES: usize = 12;
+const MAX_BUTTONS: usize = 4;
+const MAX_ALT_TEXT_LENGTH: usize = 400;
+const MAX_BUTTONS_TITLE_LENGTH: usize = 40;
+/// Maximum length of the buttons template text when it has a title or an image.
+const MAX_BUTTONS_TEXT_LENGTH: usize = 60;
+const MAX_ACTION_LABEL_LENGTH: usize = 20;
+const DETAIL_TEXT_COLOR: &str = "#666666";
+const HIGHLIGHT_TEXT_COLOR: &str = "#E8590C";
+/// Longer descriptions are cut off with an ellipsis by LINE.
+const DESCRIPTION_MAX_LINES: u32 = 3;
+
+/// Cuts text to the given number of characters, marking the cut with an ellipsis.
+pub(crate) fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut truncated = text.chars().take(max_chars - 1).collect::<String>();
+    truncated.push('…');
+    truncated
+}
+
+impl From<LineMessage> for Message {
     fn from(value: LineMessage) -> Self {
         match value {
-            LineMessage::Text(message) => {
-                line_bot_sdk_rust::line_messaging_api::models::Message::Text(TextMessage::new(
-                    message,
+            LineMessage::Text(message) => Message::Text(TextMessage::new(message)),
+            LineMessage::Image {
+                original_content_url,
+                preview_image_url,
+            } => Message::Image(ImageMessage::new(original_content_url, preview_image_url)),
+            LineMessage::Sticker {
+                package_id,
+                sticker_id,
+            } => Message::Sticker(StickerMessage::new(package_id, sticker_id)),
+            LineMessage::Buttons {
+                alt_text,
+                title,
+                text,
+                thumbnail_image_url,
+                actions,
+            } => {
+                let actions = actions
+                    .into_iter()
+                    .take(MAX_BUTTONS)
+                    .map(Into::into)
+                    .collect();
+                let template = ButtonsTemplate {
+                    title: title.map(|title| truncate(&title, MAX_BUTTONS_TITLE_LENGTH)),
+                    thumbnail_image_url,
+                    ..ButtonsTemplate::new(truncate(&text, MAX_BUTTONS_TEXT_LENGTH), actions)
+                };
+                Message::Template(TemplateMessage::new(
+                    truncate(&alt_text, MAX_ALT_TEXT_LENGTH),
+                    Template::Buttons(template),
                 ))
             }
+            LineMessage::FlexBubble { alt_text, bubble } => {
+                flex_message(alt_text, bubble_json(bubble))
+            }
+            LineMessage::FlexCarousel { alt_text, bubbles } => {
+                let bubbles = bubbles
+                    .into_iter()
+                    .take(MAX_FLEX_CAROUSEL_BUBBLES)
+                    .map(bubble_json)
+                    .collect::<Vec<_>>();
+                flex_message(alt_text, json!({ "type": "carousel", "contents": bubbles }))
+            }
+            LineMessage::QuickReply { message, actions } => {
+                with_quick_reply((*message).into(), actions)
+            }
+        }
+    }
+}
+
+/// Attaches quick-reply buttons to a message.
+///
+/// Every SDK message type has its own `quick_reply` field, so it is set on the wire format.
+/// If that fails, the message is sent without the buttons.
+fn with_quick_reply(message: Message, actions: Vec<LineAction>) -> Message {
+    let items = actions
+        .into_iter()
+        .take(MAX_QUICK_REPLY_ITEMS)
+        .map(|action| {
+            Ok(json!({
+                "type": "action",
+                "action": serde_json::to_value(Action::from(action))?,
+            }))
+        })
+        .collect::<serde_json::Result<Vec<_>>>();
+    let with_items = items.and_then(|items| {
+        let mut value = serde_json::to_value(&message)?;
+        value["quickReply"] = json!({ "items": items });
+        serde_json::from_value(value)
+    });
+    with_items.unwrap_or_else(|e| {
+        tracing::error!(%e, "failed to add quick reply, sending the message without it");
+        message
+    })
+}
+
+/// Builds a flex message from the Messaging API JSON of its container.
+///
+/// Flex containers nest deeply, so they are written in their wire format rather than
+/// assembled from SDK structs. If the SDK rejects the JSON, the alt text is sent instead.
+fn flex_message(alt_text: String, contents: Value) -> Message {
+    let alt_text = truncate(&alt_text, MAX_ALT_TEXT_LENGTH);
+    serde_json::from_value(json!({
+        "type": "flex",
+        "altText": &alt_text,
+        "contents": contents,
+    }))
+    .unwrap_or_else(|e| {
+        tracing::error!(%e, "failed to build flex message, falling back to text");
+        Message::Text(TextMessage::new(alt_text))
+    })
+}
+
+fn bubble_json(bubble: FlexBubble) -> Value {
+    let mut contents = vec![json!({
+        "type": "text",
+        "text": bubble.title,
+        "weight": "bold",
+        "size": "lg",
+        "wrap": true,
+    })];
+    add_highlights(&mut contents[0], &bubble.highlights);
+    if let Some(description) = bubble.description {
+        contents.push(json!({
+            "type": "text",
+            "text": description,
+            "size": "sm",
+            "wrap": true,
+            "maxLines": DESCRIPTION_MAX_LINES,
+        }));
+    }
+    contents.extend(
+        bubble
+            .details
+            .into_iter()
+            .filter(|detail| !detail.is_empty())
+            .map(|detail| {
+                let mut value = json!({
+                    "type": "text",
+                    "text": detail,
+                    "size": "sm",
+                    "color": DETAIL_TEXT_COLOR,
+                    "wrap": true,
+                });
+                add_highlights(&mut value, &bubble.highlights);
+                value
+            }),
+    );
+
+    let mut value = json!({
+        "type": "bubble",
+        "body": { "type": "box", "layout": "vertical", "spacing": "sm", "contents": contents },
+    });
+    if let Some(url) = bubble.image_url {
+        value["hero"] = json!({
+            "type": "image",
+            "url": url,
+            "size": "full",
+            "aspectRatio": "20:13",
+            "aspectMode": "cover",
+        });
+    }
+    if !bubble.actions.is_empty() {
+        let buttons = bubble
+            .actions
+            .into_iter()
+            .enumerate()
+            .map(|(i, action)| {
+                json!({
+                    "type": "button",
+                    // The first action is the main one.
+                    "style": if i == 0 { "primary" } else { "secondary" },
+                    "height": "sm",
+                    "action": action_json(action),
+                })
+            })
+            .collect::<Vec<_>>();
+        value["footer"] = json!({
+            "type": "box",
+            "layout": "vertical",
+            "spacing": "sm",
+            "contents": buttons,
+        });
+    }
+    value
+}
+
+/// Splits a text component into spans so the highlighted parts are shown bold and colored.
+fn add_highlights(text: &mut Value, highlights: &[String]) {
+    let spans = highlight_spans(text["text"].as_str().unwrap_or_default(), highlights);
+    if !spans.iter().any(|&(_, highlighted)| highlighted) {
+        return;
+    }
+    let spans = spans
+        .into_iter()
+        .map(|(part, highlighted)| {
+            if highlighted {
+                json!({
+                    "type": "span",
+                    "text": part,
+                    "weight": "bold",
+                    "color": HIGHLIGHT_TEXT_COLOR,
+                })
+            } else {
+                json!({ "type": "span", "text": part })
+            }
+        })
+        .collect::<Vec<_>>();
+    text["contents"] = json!(spans);
+}
+
+/// Cuts text into parts, marking those equal to a highlight. Where highlights overlap, the one
+/// starting first wins, then the longest.
+fn highlight_spans<'a>(text: &'a str, highlights: &[String]) -> Vec<(&'a str, bool)> {
+    let mut spans = Vec::new();
+    let mut rest = text;
+    while let Some((start, len)) = highlights
+        .iter()
+        .filter(|highlight| !highlight.is_empty())
+        .filter_map(|highlight| rest.find(highlight.as_str()).map(|i| (i, highlight.len())))
+        .min_by_key(|&(i, len)| (i, std::cmp::Reverse(len)))
+    {
+        if start > 0 {
+            spans.push((&rest[..start], false));
         }
+        spans.push((&rest[start..start + len], true));
+        rest = &rest[start + len..];
+    }
+    if !rest.is_empty() {
+        spans.push((rest, false));
+    }
+    spans
+}
+
+fn action_json(action: LineAction) -> Value {
+    match action {
+        LineAction::Uri { label, uri } => json!({
+            "type": "uri",
+            "label": truncate(&label, MAX_ACTION_LABEL_LENGTH),
+            "uri": uri,
+        }),
+        LineAction::Postback {
+            label,
+            data,
+            display_text,
+            fill_in_text,
+        } => {
+            let mut value = json!({
+                "type": "postback",
+                "label": truncate(&label, MAX_ACTION_LABEL_LENGTH),
+                "data": data,
+            });
+            if let Some(display_text) = display_text {
+                value["displayText"] = json!(display_text);
+            }
+            if let Some(fill_in_text) = fill_in_text {
+                value["inputOption"] = json!("openKeyboard");
+                value["fillInText"] = json!(fill_in_text);
+            }
+            value
+        }
+    }
+}
+
+impl From<LineAction> for Action {
+    fn from(value: LineAction) -> Self {
+        match value {
+            LineAction::Uri { label, uri } => Action::Uri(UriAction {
+                label: Some(truncate(&label, MAX_ACTION_LABEL_LENGTH)),
+                uri: Some(uri),
+                ..Default::default()
+            }),
+            LineAction::Postback {
+                label,
+                data,
+                display_text,
+                fill_in_text,
+            } => Action::Postback(PostbackAction {
+                label: Some(truncate(&label, MAX_ACTION_LABEL_LENGTH)),
+                data: Some(data),
+                display_text,
+                input_option: fill_in_text
+                    .is_some()
+                    .then_some(PostbackActionInputOption::OpenKeyboard),
+                fill_in_text,
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case("鶏の照り焼き", 10 => "鶏の照り焼き" ; "short")]
+    #[test_case("とても長いレシピのタイトル", 5 => "とても長…" ; "long")]
+    fn test_truncate(text: &str, max_chars: usize) -> String {
+        truncate(text, max_chars)
+    }
+
+    #[test_case("鶏肉の照り焼き", &["鶏肉", "焼き"], &[("鶏肉", true), ("の照り", false), ("焼き", true)] ; "several")]
+    #[test_case("カレーカレー", &["カレー"], &[("カレー", true), ("カレー", true)] ; "repeated")]
+    #[test_case("鶏もも肉", &["鶏", "鶏もも"], &[("鶏もも", true), ("肉", false)] ; "overlapping")]
+    #[test_case("肉じゃが", &[], &[("肉じゃが", false)] ; "none")]
+    fn test_highlight_spans(text: &str, highlights: &[&str], expected: &[(&str, bool)]) {
+        let highlights = highlights
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(highlight_spans(text, &highlights), expected);
+    }
+
+    #[test]
+    fn test_flex_message_with_quick_reply() {
+        let message = LineMessage::QuickReply {
+            message: Box::new(LineMessage::FlexBubble {
+                alt_text: "鶏の照り焼き".to_string(),
+                bubble: FlexBubble {
+                    image_url: None,
+                    title: "鶏の照り焼き".to_string(),
+                    description: None,
+                    details: Vec::new(),
+                    actions: Vec::new(),
+                    highlights: Vec::new(),
+                },
+            }),
+            actions: vec![
+                LineAction::Uri {
+                    label: "開く".to_string(),
+                    uri: "https://example.com/recipe/1".to_string(),
+                },
+                LineAction::Uri {
+                    label: "Notionで見る".to_string(),
+                    uri: "https://www.notion.so/page".to_string(),
+                },
+            ],
+        };
+
+        let value = serde_json::to_value(Message::from(message)).unwrap();
+        assert_eq!(value["type"], "flex");
+        assert_eq!(value["contents"]["type"], "bubble");
+        assert_eq!(value["quickReply"]["items"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_bubble_json() {
+        let bubble = FlexBubble {
+            image_url: None,
+            title: "鶏の照り焼き".to_string(),
+            description: Some("甘辛いたれの定番おかず".to_string()),
+            details: vec!["⏱ 20分".to_string(), String::new()],
+            actions: vec![
+                LineAction::Uri {
+                    label: "開く".to_string(),
+                    uri: "https://example.com/recipe/1".to_string(),
+                },
+                LineAction::Uri {
+                    label: "Notionで見る".to_string(),
+                    uri: "https://www.notion.so/page".to_string(),
+                },
+            ],
+            highlights: vec!["照り焼き".to_string()],
+        };
+
+        let value = bubble_json(bubble);
+        assert!(value.get("hero").is_none());
+        assert_eq!(value["body"]["contents"].as_array().unwrap().len(), 3);
+        assert_eq!(
+            value["body"]["contents"][0]["contents"][1]["text"],
+            "照り焼き"
+        );
+        assert!(value["body"]["contents"][2].get("contents").is_none());
+        assert_eq!(
+            value["body"]["contents"][1]["maxLines"],
+            DESCRIPTION_MAX_LINES
+        );
+        assert_eq!(value["footer"]["contents"][0]["style"], "primary");
+        assert_eq!(
+            value["footer"]["contents"][1]["action"]["label"],
+            "Notionで見る"
+        );
     }
 }
@@ -1,4 +1,14 @@
 pub mod axum;
+pub mod charset;
+pub mod dedup;
+pub mod extractor;
+#[cfg(test)]
+pub(crate) mod fake;
+pub mod fetch;
+pub mod job;
 pub mod line;
 pub mod notion;
+pub mod repository;
 pub mod reqwest;
+pub mod sqlite;
+pub mod sync;
@@ -0,0 +1,223 @@
+use notion_client::objects::{
+    block::{
+        Block, BlockType, HeadingsValue, ImageValue, NumberedListItemValue, ParagraphValue,
+        TextColor, ToDoValue,
+    },
+    file::{ExternalFile, File},
+    rich_text::{RichText, Text},
+};
+
+use crate::domain::recipe::Recipe;
+
+use super::property::plain_text;
+
+/// Maximum number of blocks Notion accepts in a single `children` array.
+pub(crate) const MAX_BLOCKS_PER_REQUEST: usize = 100;
+
+/// Maximum length of the content of a single rich text object.
+const MAX_RICH_TEXT_LENGTH: usize = 2000;
+
+const INGREDIENTS_HEADING: &str = "材料";
+const STEPS_HEADING: &str = "作り方";
+const NOTE_PREFIX: &str = "📝 ";
+
+/// Builds the page body for a recipe: its image and description, the ingredient checklist and
+/// numbered steps.
+pub(crate) fn recipe_blocks(recipe: &Recipe) -> Vec<Block> {
+    let mut blocks = Vec::new();
+
+    if let Some(image_url) = &recipe.image_url {
+        blocks.push(image_block(image_url.to_string()));
+    }
+
+    if let Some(description) = &recipe.description {
+        blocks.push(paragraph_block(description));
+    }
+
+    if !recipe.ingredients.is_empty() {
+        blocks.push(heading_block(INGREDIENTS_HEADING));
+        blocks.extend(
+            recipe
+                .ingredients
+                .iter()
+                .map(|ingredient| to_do_block(&ingredient.to_string())),
+        );
+    }
+
+    if !recipe.steps.is_empty() {
+        blocks.push(heading_block(STEPS_HEADING));
+        blocks.extend(recipe.steps.iter().map(|step| numbered_list_block(step)));
+    }
+
+    blocks
+}
+
+/// Reads back the description written by `recipe_blocks`: the paragraph that opens the body,
+/// after the image.
+pub(crate) fn body_description(blocks: &[Block]) -> Option<String> {
+    let block = blocks
+        .iter()
+        .find(|block| !matches!(block.block_type, BlockType::Image { .. }))?;
+    let BlockType::Paragraph { paragraph } = &block.block_type else {
+        return None;
+    };
+    let text = plain_text(&paragraph.rich_text);
+    // A recipe without a description, ingredients or steps starts with its first note.
+    (!text.is_empty() && !text.starts_with(NOTE_PREFIX)).then_some(text)
+}
+
+pub(crate) fn note_block(note: &str) -> Block {
+    paragraph_block(&format!("{NOTE_PREFIX}{note}"))
+}
+
+fn paragraph_block(text: &str) -> Block {
+    block(BlockType::Paragraph {
+        paragraph: ParagraphValue {
+            rich_text: rich_text(text),
+            color: Some(TextColor::Default),
+            children: None,
+        },
+    })
+}
+
+pub(crate) fn external_file(url: String) -> File {
+    File::External {
+        external: ExternalFile { url },
+    }
+}
+
+/// Splits text into rich text objects that fit in Notion's per-object length limit.
+pub(crate) fn rich_text(content: &str) -> Vec<RichText> {
+    content
+        .chars()
+        .collect::<Vec<_>>()
+        .chunks(MAX_RICH_TEXT_LENGTH)
+        .map(|chunk| RichText::Text {
+            text: Text {
+                content: chunk.iter().collect(),
+                link: None,
+            },
+            annotations: None,
+            plain_text: None,
+            href: None,
+        })
+        .collect()
+}
+
+fn block(block_type: BlockType) -> Block {
+    Block {
+        block_type,
+        ..Default::default()
+    }
+}
+
+fn heading_block(heading: &str) -> Block {
+    block(BlockType::Heading2 {
+        heading_2: HeadingsValue {
+            rich_text: rich_text(heading),
+            color: None,
+            is_toggleable: None,
+        },
+    })
+}
+
+fn to_do_block(content: &str) -> Block {
+    block(BlockType::ToDo {
+        to_do: ToDoValue {
+            rich_text: rich_text(content),
+            checked: Some(false),
+            color: Some(TextColor::Default),
+            children: None,
+        },
+    })
+}
+
+fn numbered_list_block(content: &str) -> Block {
+    block(BlockType::NumberedListItem {
+        numbered_list_item: NumberedListItemValue {
+            rich_text: rich_text(content),
+            color: TextColor::Default,
+            children: None,
+        },
+    })
+}
+
+fn image_block(url: String) -> Block {
+    block(BlockType::Image {
+        image: ImageValue {
+            file_type: external_file(url),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::recipe::{Ingredient, RecipeDetails};
+
+    #[test]
+    fn test_recipe_blocks() {
+        let details = RecipeDetails {
+            ingredients: vec![
+                Ingredient::parse("鶏もも肉 300g"),
+                Ingredient::parse("塩 少々"),
+            ],
+            steps: vec!["切る".to_string(), "焼く".to_string(), "盛る".to_string()],
+            image_url: Some(url::Url::parse("https://example.com/image.jpg").unwrap()),
+            description: Some("甘辛いたれの定番おかず".to_string()),
+            ..Default::default()
+        };
+        let recipe = Recipe::with_details(
+            "鶏の照り焼き".to_string(),
+            url::Url::parse("https://example.com").unwrap(),
+            details,
+        )
+        .unwrap();
+
+        let blocks = recipe_blocks(&recipe);
+        // image + description + heading + 2 ingredients + heading + 3 steps
+        assert_eq!(blocks.len(), 9);
+        assert!(matches!(blocks[0].block_type, BlockType::Image { .. }));
+        assert!(matches!(blocks[1].block_type, BlockType::Paragraph { .. }));
+        assert!(matches!(blocks[2].block_type, BlockType::Heading2 { .. }));
+        assert!(matches!(blocks[3].block_type, BlockType::ToDo { .. }));
+        assert!(matches!(
+            blocks[8].block_type,
+            BlockType::NumberedListItem { .. }
+        ));
+    }
+
+    #[test]
+    fn test_body_description() {
+        let description = "あ".repeat(MAX_RICH_TEXT_LENGTH + 1);
+        let details = RecipeDetails {
+            ingredients: vec![Ingredient::parse("塩 少々")],
+            image_url: Some(url::Url::parse("https://example.com/image.jpg").unwrap()),
+            description: Some(description.clone()),
+            ..Default::default()
+        };
+        let recipe = Recipe::with_details(
+            "鶏の照り焼き".to_string(),
+            url::Url::parse("https://example.com").unwrap(),
+            details,
+        )
+        .unwrap();
+        assert_eq!(body_description(&recipe_blocks(&recipe)), Some(description));
+
+        let recipe = Recipe::new(
+            "鶏の照り焼き".to_string(),
+            url::Url::parse("https://example.com").unwrap(),
+        );
+        let mut blocks = recipe_blocks(&recipe);
+        assert_eq!(body_description(&blocks), None);
+        blocks.push(note_block("甘め"));
+        assert_eq!(body_description(&blocks), None);
+    }
+
+    #[test]
+    fn test_rich_text_splits_long_content() {
+        let content = "あ".repeat(MAX_RICH_TEXT_LENGTH + 1);
+        assert_eq!(rich_text(&content).len(), 2);
+        assert!(rich_text("").is_empty());
+    }
+}
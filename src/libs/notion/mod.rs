@@ -1,2 +1,4 @@
+pub(crate) mod block;
 pub(crate) mod client;
+pub(crate) mod property;
 pub mod recipe;
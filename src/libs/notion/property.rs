@@ -0,0 +1,348 @@
+use std::collections::BTreeMap;
+
+use notion_client::objects::{
+    database::DatabaseProperty,
+    file::File,
+    page::{FilePropertyValue, PageProperty, SelectPropertyValue},
+    rich_text::RichText,
+};
+use serde_json::json;
+
+use crate::config::{NotionPropertyMapping, NotionPropertyType};
+
+use super::block::{external_file, rich_text};
+
+/// A recipe field value before it is shaped into the configured property type.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum PropertyValue {
+    Text(String),
+    Number(serde_json::Number),
+    List(Vec<String>),
+}
+
+impl PropertyValue {
+    pub(crate) fn into_text(self) -> String {
+        match self {
+            Self::Text(text) => text,
+            Self::Number(number) => number.to_string(),
+            Self::List(list) => list.join(", "),
+        }
+    }
+
+    pub(crate) fn into_list(self) -> Vec<String> {
+        match self {
+            Self::List(list) => list,
+            value => vec![value.into_text()],
+        }
+    }
+
+    pub(crate) fn into_u64(self) -> Option<u64> {
+        match self {
+            Self::Number(number) => number.as_u64(),
+            value => value.into_text().trim().parse().ok(),
+        }
+    }
+}
+
+/// Reads the value of a page property, regardless of its type.
+pub(crate) fn property_value(property: &PageProperty) -> Option<PropertyValue> {
+    let value = match property {
+        PageProperty::Title { title, .. } => PropertyValue::Text(plain_text(title)),
+        PageProperty::RichText { rich_text, .. } => PropertyValue::Text(plain_text(rich_text)),
+        PageProperty::Url { url, .. } => PropertyValue::Text(url.clone()?),
+        PageProperty::Number { number, .. } => PropertyValue::Number(number.clone()?),
+        PageProperty::Select { select, .. } => PropertyValue::Text(select.as_ref()?.name.clone()?),
+        PageProperty::Status { status, .. } => PropertyValue::Text(status.as_ref()?.name.clone()?),
+        PageProperty::MultiSelect { multi_select, .. } => PropertyValue::List(
+            multi_select
+                .iter()
+                .filter_map(|value| value.name.clone())
+                .collect(),
+        ),
+        PageProperty::Files { files, .. } => PropertyValue::Text(file_url(&files.first()?.r#type)),
+        _ => return None,
+    };
+    Some(value)
+}
+
+pub(crate) fn plain_text(rich_text: &[RichText]) -> String {
+    rich_text
+        .iter()
+        .map(|text| match text {
+            RichText::Text { text, .. } => text.content.as_str(),
+            _ => "",
+        })
+        .collect()
+}
+
+pub(crate) fn file_url(file: &File) -> String {
+    match file {
+        File::External { external } => external.url.clone(),
+        File::File { file } => file.url.clone(),
+    }
+}
+
+/// Shapes a value into the page property type configured for it.
+///
+/// Returns `None` when the value cannot be represented, e.g. text in a number property.
+pub(crate) fn page_property(
+    property_type: NotionPropertyType,
+    value: PropertyValue,
+) -> Option<PageProperty> {
+    let property = match property_type {
+        NotionPropertyType::Title => PageProperty::Title {
+            title: rich_text(&value.into_text()),
+            id: None,
+        },
+        NotionPropertyType::RichText => PageProperty::RichText {
+            rich_text: rich_text(&value.into_text()),
+            id: None,
+        },
+        NotionPropertyType::Url => PageProperty::Url {
+            url: Some(value.into_text()),
+            id: None,
+        },
+        NotionPropertyType::Number => match value {
+            PropertyValue::Number(number) => PageProperty::Number {
+                number: Some(number),
+                id: None,
+            },
+            _ => return None,
+        },
+        NotionPropertyType::Select => PageProperty::Select {
+            select: Some(select_value(value.into_text())),
+            id: None,
+        },
+        NotionPropertyType::MultiSelect => PageProperty::MultiSelect {
+            multi_select: value.into_list().into_iter().map(select_value).collect(),
+            id: None,
+        },
+        NotionPropertyType::Status => PageProperty::Status {
+            status: Some(select_value(value.into_text())),
+            id: None,
+        },
+        NotionPropertyType::Files => {
+            let url = value.into_text();
+            PageProperty::Files {
+                files: vec![FilePropertyValue {
+                    name: url.clone(),
+                    r#type: external_file(url),
+                }],
+                id: None,
+            }
+        }
+    };
+    Some(property)
+}
+
+fn select_value(name: String) -> SelectPropertyValue {
+    SelectPropertyValue {
+        name: Some(name),
+        id: None,
+        color: None,
+    }
+}
+
+/// Property types each recipe field can be stored in.
+fn allowed_types(field: &str) -> &'static [NotionPropertyType] {
+    use NotionPropertyType::*;
+    match field {
+        "title" => &[Title],
+        "url" => &[Url, RichText],
+        "tags" => &[MultiSelect, Select, RichText],
+        "servings" | "cook_time" => &[Number, RichText],
+        "image" => &[Files, Url],
+        "description" => &[RichText],
+        "source" => &[Select, RichText, Url],
+        "rating" => &[Number, Select],
+        "status" => &[Status, Select],
+        _ => &[],
+    }
+}
+
+pub(crate) fn property_type(property: &DatabaseProperty) -> Option<NotionPropertyType> {
+    match property {
+        DatabaseProperty::Title { .. } => Some(NotionPropertyType::Title),
+        DatabaseProperty::RichText { .. } => Some(NotionPropertyType::RichText),
+        DatabaseProperty::Url { .. } => Some(NotionPropertyType::Url),
+        DatabaseProperty::Number { .. } => Some(NotionPropertyType::Number),
+        DatabaseProperty::Select { .. } => Some(NotionPropertyType::Select),
+        DatabaseProperty::MultiSelect { .. } => Some(NotionPropertyType::MultiSelect),
+        DatabaseProperty::Status { .. } => Some(NotionPropertyType::Status),
+        DatabaseProperty::Files { .. } => Some(NotionPropertyType::Files),
+        _ => None,
+    }
+}
+
+/// Builds the schema of a new database property.
+///
+/// Title and status properties cannot be created through the API, so `None` is returned.
+pub(crate) fn database_property(
+    property_type: NotionPropertyType,
+) -> Option<serde_json::Result<DatabaseProperty>> {
+    let schema = match property_type {
+        NotionPropertyType::Title | NotionPropertyType::Status => return None,
+        NotionPropertyType::RichText => json!({ "type": "rich_text", "rich_text": {} }),
+        NotionPropertyType::Url => json!({ "type": "url", "url": {} }),
+        NotionPropertyType::Number => json!({ "type": "number", "number": { "format": "number" } }),
+        NotionPropertyType::Select => json!({ "type": "select", "select": { "options": [] } }),
+        NotionPropertyType::MultiSelect => {
+            json!({ "type": "multi_select", "multi_select": { "options": [] } })
+        }
+        NotionPropertyType::Files => json!({ "type": "files", "files": {} }),
+    };
+    Some(serde_json::from_value(schema))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum SchemaMismatch {
+    Missing {
+        field: &'static str,
+        property: String,
+        expected: NotionPropertyType,
+    },
+    WrongType {
+        field: &'static str,
+        property: String,
+        expected: NotionPropertyType,
+        actual: Option<NotionPropertyType>,
+    },
+    UnsupportedType {
+        field: &'static str,
+        property_type: NotionPropertyType,
+    },
+}
+
+impl std::fmt::Display for SchemaMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing {
+                field,
+                property,
+                expected,
+            } => write!(
+                f,
+                "property \"{property}\" for `{field}` does not exist (expected type {expected:?})"
+            ),
+            Self::WrongType {
+                field,
+                property,
+                expected,
+                actual,
+            } => match actual {
+                Some(actual) => write!(
+                    f,
+                    "property \"{property}\" for `{field}` has type {actual:?} but {expected:?} is configured"
+                ),
+                None => write!(
+                    f,
+                    "property \"{property}\" for `{field}` has an unsupported type but {expected:?} is configured"
+                ),
+            },
+            Self::UnsupportedType {
+                field,
+                property_type,
+            } => write!(
+                f,
+                "`{field}` cannot be stored in a {property_type:?} property (supported: {:?})",
+                allowed_types(field)
+            ),
+        }
+    }
+}
+
+/// Compares the configured mapping with the properties of the database.
+pub(crate) fn check_schema(
+    mapping: &NotionPropertyMapping,
+    schema: &BTreeMap<String, Option<NotionPropertyType>>,
+) -> Vec<SchemaMismatch> {
+    mapping
+        .iter()
+        .filter_map(|(field, property)| {
+            if !allowed_types(field).contains(&property.property_type) {
+                return Some(SchemaMismatch::UnsupportedType {
+                    field,
+                    property_type: property.property_type,
+                });
+            }
+            match schema.get(&property.name) {
+                None => Some(SchemaMismatch::Missing {
+                    field,
+                    property: property.name.clone(),
+                    expected: property.property_type,
+                }),
+                Some(actual) if *actual != Some(property.property_type) => {
+                    Some(SchemaMismatch::WrongType {
+                        field,
+                        property: property.name.clone(),
+                        expected: property.property_type,
+                        actual: *actual,
+                    })
+                }
+                Some(_) => None,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NotionProperty;
+
+    #[test]
+    fn test_check_schema() {
+        let mapping = NotionPropertyMapping {
+            tags: Some(NotionProperty::new("タグ", NotionPropertyType::MultiSelect)),
+            servings: Some(NotionProperty::new("人数", NotionPropertyType::Number)),
+            rating: Some(NotionProperty::new("評価", NotionPropertyType::Files)),
+            ..Default::default()
+        };
+        let schema = BTreeMap::from([
+            ("Name".to_string(), Some(NotionPropertyType::Title)),
+            ("リンク".to_string(), Some(NotionPropertyType::Url)),
+            ("人数".to_string(), Some(NotionPropertyType::RichText)),
+        ]);
+
+        assert_eq!(
+            check_schema(&mapping, &schema),
+            vec![
+                SchemaMismatch::Missing {
+                    field: "tags",
+                    property: "タグ".to_string(),
+                    expected: NotionPropertyType::MultiSelect,
+                },
+                SchemaMismatch::WrongType {
+                    field: "servings",
+                    property: "人数".to_string(),
+                    expected: NotionPropertyType::Number,
+                    actual: Some(NotionPropertyType::RichText),
+                },
+                SchemaMismatch::UnsupportedType {
+                    field: "rating",
+                    property_type: NotionPropertyType::Files,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_schema_default_mapping() {
+        let schema = BTreeMap::from([
+            ("Name".to_string(), Some(NotionPropertyType::Title)),
+            ("リンク".to_string(), Some(NotionPropertyType::Url)),
+        ]);
+        assert!(check_schema(&NotionPropertyMapping::default(), &schema).is_empty());
+    }
+
+    #[test]
+    fn test_page_property_rejects_text_for_number() {
+        assert!(
+            page_property(
+                NotionPropertyType::Number,
+                PropertyValue::Text("2人前".to_string())
+            )
+            .is_none()
+        );
+    }
+}
@@ -1,76 +1,542 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
 
 use anyhow::Context;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use notion_client::{
-    endpoints::pages::create::request::CreateAPageRequestBuilder,
+    endpoints::{
+        blocks::append::request::AppendBlockChildrenRequest,
+        databases::{
+            query::request::{
+                DateCondition, Direction, Filter, FilterType, MultiSelectCondition,
+                PropertyCondition, QueryDatabaseRequest, SelectCondition, Sort, TextCondition,
+                Timestamp, TimestampCondition,
+            },
+            update::request::UpdateADatabaseRequest,
+        },
+        pages::{
+            create::request::CreateAPageRequestBuilder,
+            update::request::UpdatePagePropertiesRequest,
+        },
+    },
     objects::{
-        page::PageProperty,
+        block::Block,
+        page::{Page, PageProperty},
         parent::Parent,
-        rich_text::{RichText, Text},
     },
 };
 
-use crate::{domain::recipe::Recipe, infra::repository::recipe::RecipeRepository, prelude::*};
+use crate::{
+    config::{NotionProperty, NotionPropertyMapping, NotionPropertyType},
+    domain::recipe::{Recipe, RecipeField},
+    infra::{repository::recipe::RecipeRepository, sync::RecipeChangeFeed},
+    prelude::*,
+};
+
+use super::{
+    block::{MAX_BLOCKS_PER_REQUEST, body_description, external_file, note_block, recipe_blocks},
+    client::NotionClient,
+    property::{
+        PropertyValue, SchemaMismatch, check_schema, database_property, file_url, page_property,
+        property_type, property_value,
+    },
+};
 
-use super::client::NotionClient;
+/// Number of recipes returned by a search, matching the size of a LINE carousel.
+const SEARCH_PAGE_SIZE: u32 = 10;
+/// Maximum page size of a database query.
+const MAX_PAGE_SIZE: usize = 100;
+/// Number of blocks read from the top of a page to find the description: the image and the
+/// paragraph after it.
+const DESCRIPTION_BLOCKS: u32 = 2;
 
 pub struct RecipeRepositoryImpl {
     notion_client: Arc<NotionClient>,
     db_id: String,
+    properties: NotionPropertyMapping,
 }
 
 impl RecipeRepositoryImpl {
-    pub fn new(notion_client: Arc<NotionClient>, db_id: String) -> Self {
+    pub fn new(
+        notion_client: Arc<NotionClient>,
+        db_id: String,
+        properties: NotionPropertyMapping,
+    ) -> Self {
         Self {
             notion_client,
             db_id,
+            properties,
+        }
+    }
+
+    /// Checks the property mapping against the database schema.
+    ///
+    /// With `bootstrap`, properties missing from the database are created instead of being
+    /// reported.
+    pub async fn verify_schema(&self, bootstrap: bool) -> Result<()> {
+        let database = self
+            .notion_client
+            .0
+            .databases
+            .retrieve_a_database(&self.db_id)
+            .await?;
+        let schema = database
+            .properties
+            .iter()
+            .map(|(name, property)| (name.clone(), property_type(property)))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut mismatches = check_schema(&self.properties, &schema);
+        if bootstrap {
+            mismatches = self.create_missing_properties(mismatches).await?;
+        }
+
+        if mismatches.is_empty() {
+            return Ok(());
+        }
+        let details = mismatches
+            .iter()
+            .map(|m| format!("  - {m}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        Err(Error::NotionSchemaError(format!(
+            "the Notion database {} does not match `notion_properties`:\n{details}",
+            self.db_id
+        )))
+    }
+
+    /// Creates the missing properties and returns the mismatches that could not be fixed.
+    async fn create_missing_properties(
+        &self,
+        mismatches: Vec<SchemaMismatch>,
+    ) -> Result<Vec<SchemaMismatch>> {
+        let mut properties = BTreeMap::new();
+        let mut remaining = Vec::new();
+        for mismatch in mismatches {
+            match &mismatch {
+                SchemaMismatch::Missing {
+                    property, expected, ..
+                } => match database_property(*expected) {
+                    Some(schema) => {
+                        let schema = schema.with_context(|| {
+                            format!("failed to build schema for property {property}")
+                        })?;
+                        properties.insert(property.clone(), schema);
+                    }
+                    None => remaining.push(mismatch),
+                },
+                _ => remaining.push(mismatch),
+            }
+        }
+
+        if properties.is_empty() {
+            return Ok(remaining);
+        }
+
+        tracing::info!(
+            properties = ?properties.keys().collect::<Vec<_>>(),
+            "creating missing Notion properties"
+        );
+        let request = UpdateADatabaseRequest {
+            properties: Some(properties),
+            ..Default::default()
+        };
+        self.notion_client
+            .0
+            .databases
+            .update_a_database(&self.db_id, request)
+            .await?;
+        Ok(remaining)
+    }
+
+    fn page_properties(&self, recipe: &Recipe) -> BTreeMap<String, PageProperty> {
+        let mapping = &self.properties;
+        let values = [
+            (
+                Some(&mapping.title),
+                Some(PropertyValue::Text(recipe.name.clone())),
+            ),
+            (
+                Some(&mapping.url),
+                Some(PropertyValue::Text(recipe.recipe_url.to_string())),
+            ),
+            (
+                mapping.tags.as_ref(),
+                (!recipe.tags.is_empty()).then(|| PropertyValue::List(recipe.tags.clone())),
+            ),
+            (
+                mapping.servings.as_ref(),
+                recipe.servings.map(|s| PropertyValue::Number(s.into())),
+            ),
+            (
+                mapping.cook_time.as_ref(),
+                recipe
+                    .total_time
+                    .or(recipe.cook_time)
+                    .map(|t| PropertyValue::Number((t.as_secs() / 60).into())),
+            ),
+            (
+                mapping.image.as_ref(),
+                recipe
+                    .image_url
+                    .as_ref()
+                    .map(|url| PropertyValue::Text(url.to_string())),
+            ),
+            (
+                mapping.description.as_ref(),
+                recipe.description.clone().map(PropertyValue::Text),
+            ),
+            (
+                mapping.source.as_ref(),
+                recipe.source.clone().map(PropertyValue::Text),
+            ),
+            (
+                mapping.rating.as_ref(),
+                recipe.rating.map(|r| PropertyValue::Number(r.into())),
+            ),
+            (
+                mapping.status.as_ref(),
+                recipe.status.clone().map(PropertyValue::Text),
+            ),
+        ];
+
+        values
+            .into_iter()
+            .filter_map(|(property, value)| {
+                let NotionProperty {
+                    name,
+                    property_type,
+                } = property?;
+                Some((name.clone(), page_property(*property_type, value?)?))
+            })
+            .collect()
+    }
+
+    fn recipe_from_page(&self, page: &Page) -> Option<Recipe> {
+        let mapping = &self.properties;
+        let value = |property: Option<&NotionProperty>| {
+            page.properties
+                .get(&property?.name)
+                .and_then(property_value)
+        };
+
+        let name = value(Some(&mapping.title))?.into_text();
+        let recipe_url = url::Url::parse(&value(Some(&mapping.url))?.into_text()).ok()?;
+        let mut recipe = Recipe::new(name, recipe_url);
+
+        recipe.id = page_ulid(&page.id)?;
+        recipe.tags = value(mapping.tags.as_ref())
+            .map(PropertyValue::into_list)
+            .unwrap_or_default();
+        recipe.servings = value(mapping.servings.as_ref())
+            .and_then(PropertyValue::into_u64)
+            .and_then(|s| s.try_into().ok());
+        recipe.total_time = value(mapping.cook_time.as_ref())
+            .and_then(PropertyValue::into_u64)
+            .map(|minutes| Duration::from_secs(minutes * 60));
+        recipe.image_url = value(mapping.image.as_ref())
+            .map(PropertyValue::into_text)
+            .or_else(|| page.cover.as_ref().map(file_url))
+            .and_then(|url| url::Url::parse(&url).ok());
+        recipe.description = value(mapping.description.as_ref()).map(PropertyValue::into_text);
+        recipe.source = value(mapping.source.as_ref()).map(PropertyValue::into_text);
+        recipe.rating = value(mapping.rating.as_ref())
+            .and_then(PropertyValue::into_u64)
+            .and_then(|r| r.try_into().ok());
+        recipe.status = value(mapping.status.as_ref()).map(PropertyValue::into_text);
+        recipe.page_url = url::Url::parse(&page.url).ok();
+        recipe.created_at = page.created_time;
+        recipe.updated_at = page.last_edited_time;
+
+        Some(recipe)
+    }
+
+    async fn query_recipes(&self, request: QueryDatabaseRequest) -> Result<Vec<Recipe>> {
+        let response = self
+            .notion_client
+            .0
+            .databases
+            .query_a_database(&self.db_id, request)
+            .await?;
+        Ok(response
+            .results
+            .iter()
+            .filter_map(|page| self.recipe_from_page(page))
+            .collect())
+    }
+
+    async fn read_body_descriptions(&self, recipes: Vec<Recipe>) -> Result<Vec<Recipe>> {
+        let mut read = Vec::with_capacity(recipes.len());
+        for recipe in recipes {
+            read.push(self.read_body_description(recipe).await?);
+        }
+        Ok(read)
+    }
+
+    /// Takes the description from the page body when it is not mapped to a property.
+    async fn read_body_description(&self, mut recipe: Recipe) -> Result<Recipe> {
+        if self.properties.description.is_none() {
+            let response = self
+                .notion_client
+                .0
+                .blocks
+                .retrieve_block_children(&page_id(recipe.id), None, Some(DESCRIPTION_BLOCKS))
+                .await?;
+            recipe.description = body_description(&response.results);
+        }
+        Ok(recipe)
+    }
+
+    /// Appends blocks to a page in chunks that fit in Notion's per-request block limit.
+    async fn append_blocks(&self, page_id: &str, blocks: Vec<Block>) -> Result<()> {
+        for chunk in blocks.chunks(MAX_BLOCKS_PER_REQUEST) {
+            let request = AppendBlockChildrenRequest {
+                children: chunk.to_vec(),
+                after: None,
+            };
+            self.notion_client
+                .0
+                .blocks
+                .append_block_children(page_id, request)
+                .await?;
         }
+        Ok(())
     }
 }
 
 #[async_trait]
 impl RecipeRepository for RecipeRepositoryImpl {
-    async fn insert_recipe(&self, recipe: Recipe) -> Result<()> {
-        let mut properties = BTreeMap::new();
-        properties.insert("Name".to_string(), title_property(recipe.name));
-        properties.insert(
-            "リンク".to_string(),
-            link_property(recipe.recipe_url.to_string()),
-        );
+    /// Optional fields are only stored when mapped to a property.
+    fn stores(&self, field: RecipeField) -> bool {
+        let mapping = &self.properties;
+        match field {
+            RecipeField::Tags => mapping.tags.is_some(),
+            RecipeField::Rating => mapping.rating.is_some(),
+            RecipeField::Status => mapping.status.is_some(),
+        }
+    }
+
+    async fn insert_recipe(&self, recipe: Recipe) -> Result<Recipe> {
+        let mut blocks = recipe_blocks(&recipe);
+        let remaining_blocks = blocks.split_off(blocks.len().min(MAX_BLOCKS_PER_REQUEST));
+        let cover = recipe
+            .image_url
+            .as_ref()
+            .map(|url| external_file(url.to_string()));
 
-        let request = CreateAPageRequestBuilder::default()
+        let mut builder = CreateAPageRequestBuilder::default();
+        builder
             .parent(Parent::DatabaseId {
                 database_id: self.db_id.clone(),
             })
-            .properties(properties)
+            .properties(self.page_properties(&recipe))
+            .children(blocks);
+        if let Some(cover) = cover {
+            builder.cover(cover);
+        }
+        let request = builder
             .build()
             .with_context(|| "Failed to build CreateAPageRequestBuilder request")?;
 
-        let _ = self.notion_client.0.pages.create_a_page(request).await?;
+        let page = self.notion_client.0.pages.create_a_page(request).await?;
+        self.append_blocks(&page.id, remaining_blocks).await?;
+
+        Ok(Recipe {
+            id: page_ulid(&page.id).unwrap_or(recipe.id),
+            page_url: url::Url::parse(&page.url).ok(),
+            ..recipe
+        })
+    }
+
+    async fn get_recipe(&self, id: ulid::Ulid) -> Result<Option<Recipe>> {
+        let page = self
+            .notion_client
+            .0
+            .pages
+            .retrieve_a_page(&page_id(id), None)
+            .await?;
+        if page.archived {
+            return Ok(None);
+        }
+        match self.recipe_from_page(&page) {
+            Some(recipe) => Ok(Some(self.read_body_description(recipe).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn find_by_url(&self, recipe_url: &url::Url) -> Result<Option<Recipe>> {
+        let url_property = &self.properties.url;
+        let condition = match url_property.property_type {
+            NotionPropertyType::RichText => {
+                PropertyCondition::RichText(TextCondition::Equals(recipe_url.to_string()))
+            }
+            _ => PropertyCondition::Url(TextCondition::Equals(recipe_url.to_string())),
+        };
+        let request = QueryDatabaseRequest {
+            filter: Some(Filter::Value {
+                filter_type: FilterType::Property {
+                    property: url_property.name.clone(),
+                    condition,
+                },
+            }),
+            page_size: Some(1),
+            ..Default::default()
+        };
+
+        Ok(self.query_recipes(request).await?.into_iter().next())
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<Recipe>> {
+        let property_filter = |property: &NotionProperty| {
+            let condition = match property.property_type {
+                NotionPropertyType::Title => {
+                    PropertyCondition::Title(TextCondition::Contains(query.to_string()))
+                }
+                NotionPropertyType::RichText => {
+                    PropertyCondition::RichText(TextCondition::Contains(query.to_string()))
+                }
+                NotionPropertyType::MultiSelect => PropertyCondition::MultiSelect(
+                    MultiSelectCondition::Contains(query.to_string()),
+                ),
+                NotionPropertyType::Select => {
+                    PropertyCondition::Select(SelectCondition::Equals(query.to_string()))
+                }
+                _ => return None,
+            };
+            Some(Filter::Value {
+                filter_type: FilterType::Property {
+                    property: property.name.clone(),
+                    condition,
+                },
+            })
+        };
+
+        let filters = [Some(&self.properties.title), self.properties.tags.as_ref()]
+            .into_iter()
+            .flatten()
+            .filter_map(property_filter)
+            .collect();
+        let request = QueryDatabaseRequest {
+            filter: Some(Filter::Or { or: filters }),
+            page_size: Some(SEARCH_PAGE_SIZE),
+            ..Default::default()
+        };
+
+        let recipes = self.query_recipes(request).await?;
+        self.read_body_descriptions(recipes).await
+    }
+
+    async fn list_recipes(&self, limit: usize) -> Result<Vec<Recipe>> {
+        let request = QueryDatabaseRequest {
+            sorts: Some(vec![Sort::Timestamp {
+                timestamp: Timestamp::CreatedTime,
+                direction: Direction::Descending,
+            }]),
+            page_size: Some(limit.min(MAX_PAGE_SIZE) as u32),
+            ..Default::default()
+        };
+
+        let recipes = self.query_recipes(request).await?;
+        if limit > SEARCH_PAGE_SIZE as usize {
+            // A page body is read per recipe, too slow for the pools picks are drawn from.
+            return Ok(recipes);
+        }
+        self.read_body_descriptions(recipes).await
+    }
+
+    async fn update_recipe(&self, recipe: Recipe) -> Result<Recipe> {
+        let request = UpdatePagePropertiesRequest {
+            properties: self
+                .page_properties(&recipe)
+                .into_iter()
+                .map(|(name, property)| (name, Some(property)))
+                .collect(),
+            ..Default::default()
+        };
+        let page = self
+            .notion_client
+            .0
+            .pages
+            .update_page_properties(&page_id(recipe.id), request)
+            .await?;
+
+        Ok(Recipe {
+            updated_at: page.last_edited_time,
+            ..recipe
+        })
+    }
+
+    async fn add_note(&self, id: ulid::Ulid, note: &str) -> Result<()> {
+        self.append_blocks(&page_id(id), vec![note_block(note)])
+            .await
+    }
+
+    async fn delete_recipe(&self, id: ulid::Ulid) -> Result<()> {
+        let request = UpdatePagePropertiesRequest {
+            archived: Some(true),
+            ..Default::default()
+        };
+        self.notion_client
+            .0
+            .pages
+            .update_page_properties(&page_id(id), request)
+            .await?;
         Ok(())
     }
 }
 
-fn title_property(name: String) -> PageProperty {
-    let rich_text = RichText::Text {
-        text: Text {
-            content: name,
-            link: None,
-        },
-        annotations: None,
-        plain_text: None,
-        href: None,
-    };
-    PageProperty::Title {
-        title: vec![rich_text],
-        id: None,
+#[async_trait]
+impl RecipeChangeFeed for RecipeRepositoryImpl {
+    /// Notion rounds `last_edited_time` down to the minute, so recipes edited in the minute of
+    /// `since` are returned again.
+    async fn recipes_edited_since(&self, since: Option<DateTime<Utc>>) -> Result<Vec<Recipe>> {
+        let mut recipes = Vec::new();
+        let mut start_cursor = None;
+        loop {
+            let request = QueryDatabaseRequest {
+                filter: since.map(|since| Filter::Value {
+                    filter_type: FilterType::Timestamp {
+                        timestamp: Timestamp::LastEditedTime,
+                        condition: TimestampCondition::LastEditedTime(DateCondition::OnOrAfter(
+                            since,
+                        )),
+                    },
+                }),
+                sorts: Some(vec![Sort::Timestamp {
+                    timestamp: Timestamp::LastEditedTime,
+                    direction: Direction::Ascending,
+                }]),
+                start_cursor,
+                page_size: Some(MAX_PAGE_SIZE as u32),
+                ..Default::default()
+            };
+            let response = self
+                .notion_client
+                .0
+                .databases
+                .query_a_database(&self.db_id, request)
+                .await?;
+            let page_recipes = response
+                .results
+                .iter()
+                .filter_map(|page| self.recipe_from_page(page))
+                .collect();
+            recipes.extend(self.read_body_descriptions(page_recipes).await?);
+            match response.next_cursor {
+                Some(cursor) if response.has_more => start_cursor = Some(cursor),
+                _ => return Ok(recipes),
+            }
+        }
     }
 }
 
-fn link_property(link: String) -> PageProperty {
-    PageProperty::Url {
-        url: Some(link),
-        id: None,
-    }
+/// Recipes stored in Notion are identified by their page ID, which is a UUID of the same width
+/// as a ULID.
+fn page_ulid(page_id: &str) -> Option<ulid::Ulid> {
+    let uuid = uuid::Uuid::parse_str(page_id).ok()?;
+    Some(ulid::Ulid::from(uuid.as_u128()))
+}
+
+fn page_id(id: ulid::Ulid) -> String {
+    uuid::Uuid::from_u128(id.into()).to_string()
 }
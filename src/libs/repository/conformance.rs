@@ -0,0 +1,288 @@
+//! Checks every [`RecipeRepository`] backend has to pass, so they stay interchangeable.
+//!
+//! A backend runs them with `recipe_repository_conformance!(<expression opening an empty
+//! repository>)` inside its test module.
+
+use std::time::Duration;
+
+use chrono::DateTime;
+
+use crate::{
+    domain::{
+        recipe::{Ingredient, Recipe},
+        search::SearchQuery,
+    },
+    infra::repository::recipe::RecipeRepository,
+};
+
+macro_rules! recipe_repository_conformance {
+    ($repository:expr) => {
+        $crate::libs::repository::conformance::recipe_repository_conformance!(
+            @cases $repository;
+            test_insert_and_get_recipe,
+            test_insert_rejects_duplicate_id,
+            test_find_by_url,
+            test_search_matches_name_and_tags,
+            test_search_is_limited,
+            test_search_text,
+            test_list_recipes_newest_first,
+            test_update_recipe,
+            test_update_missing_recipe_fails,
+            test_add_note,
+            test_delete_recipe
+        );
+    };
+    (@cases $repository:expr; $($case:ident),*) => {
+        $(
+            #[tokio::test]
+            async fn $case() {
+                $crate::libs::repository::conformance::$case(&$repository).await;
+            }
+        )*
+    };
+}
+
+pub(crate) use recipe_repository_conformance;
+
+/// A recipe with every field set, saved `seconds` after the epoch.
+pub(crate) fn recipe(name: &str, seconds: i64) -> Recipe {
+    let created_at = DateTime::from_timestamp(seconds, 0).unwrap();
+    Recipe {
+        ingredients: vec![
+            Ingredient::parse("じゃがいも 3個"),
+            Ingredient::parse("しょうゆ 大さじ2"),
+            Ingredient::parse("牛肉（切り落とし） 200g"),
+        ],
+        steps: vec!["切る".to_string(), "煮る".to_string()],
+        servings: Some(2),
+        prep_time: Some(Duration::from_secs(10 * 60)),
+        cook_time: Some(Duration::from_secs(20 * 60)),
+        total_time: Some(Duration::from_secs(30 * 60)),
+        image_url: Some(url::Url::parse("https://example.com/image.jpg").unwrap()),
+        description: Some("ほっくりした煮物".to_string()),
+        source: Some("example".to_string()),
+        tags: vec!["和食".to_string(), "Main".to_string()],
+        rating: Some(4),
+        status: Some("作りたい".to_string()),
+        notes: vec!["甘めが好み".to_string()],
+        page_url: Some(url::Url::parse("https://example.com/page").unwrap()),
+        created_at,
+        updated_at: created_at,
+        ..Recipe::new(
+            name.to_string(),
+            url::Url::parse(&format!("https://example.com/recipes/{seconds}")).unwrap(),
+        )
+    }
+}
+
+fn names(recipes: &[Recipe]) -> Vec<&str> {
+    recipes.iter().map(|recipe| recipe.name.as_str()).collect()
+}
+
+pub(crate) async fn test_insert_and_get_recipe(repository: &impl RecipeRepository) {
+    let recipe = recipe("肉じゃが", 1);
+
+    let inserted = repository.insert_recipe(recipe.clone()).await.unwrap();
+    assert_eq!(inserted, recipe);
+    assert_eq!(
+        repository.get_recipe(recipe.id).await.unwrap(),
+        Some(recipe)
+    );
+    assert_eq!(
+        repository.get_recipe(ulid::Ulid::new()).await.unwrap(),
+        None
+    );
+}
+
+pub(crate) async fn test_insert_rejects_duplicate_id(repository: &impl RecipeRepository) {
+    let recipe = recipe("肉じゃが", 1);
+    repository.insert_recipe(recipe.clone()).await.unwrap();
+
+    let duplicate = Recipe {
+        name: "筑前煮".to_string(),
+        ..recipe.clone()
+    };
+    assert!(repository.insert_recipe(duplicate).await.is_err());
+    assert_eq!(
+        repository.get_recipe(recipe.id).await.unwrap(),
+        Some(recipe)
+    );
+}
+
+pub(crate) async fn test_find_by_url(repository: &impl RecipeRepository) {
+    let saved = recipe("肉じゃが", 1);
+    repository.insert_recipe(saved.clone()).await.unwrap();
+    repository.insert_recipe(recipe("筑前煮", 2)).await.unwrap();
+
+    assert_eq!(
+        repository.find_by_url(&saved.recipe_url).await.unwrap(),
+        Some(saved)
+    );
+    let unknown = url::Url::parse("https://example.com/unknown").unwrap();
+    assert_eq!(repository.find_by_url(&unknown).await.unwrap(), None);
+}
+
+pub(crate) async fn test_search_matches_name_and_tags(repository: &impl RecipeRepository) {
+    for recipe in [
+        recipe("Beef Curry", 1),
+        Recipe {
+            tags: vec!["カレー".to_string()],
+            ..recipe("ドライキーマ", 2)
+        },
+        Recipe {
+            tags: vec!["スープ".to_string()],
+            ..recipe("スープカレー", 3)
+        },
+        Recipe {
+            tags: vec!["煮物".to_string()],
+            ..recipe("肉じゃが", 4)
+        },
+    ] {
+        repository.insert_recipe(recipe).await.unwrap();
+    }
+
+    let found = repository.search("カレー").await.unwrap();
+    assert_eq!(names(&found), ["スープカレー", "ドライキーマ"]);
+    let found = repository.search("curry").await.unwrap();
+    assert_eq!(names(&found), ["Beef Curry"]);
+    assert!(repository.search("ハンバーグ").await.unwrap().is_empty());
+}
+
+pub(crate) async fn test_search_is_limited(repository: &impl RecipeRepository) {
+    for i in 0..=super::SEARCH_LIMIT {
+        let name = format!("カレー{i}");
+        repository
+            .insert_recipe(recipe(&name, i64::try_from(i).unwrap()))
+            .await
+            .unwrap();
+    }
+
+    let found = repository.search("カレー").await.unwrap();
+    assert_eq!(found.len(), super::SEARCH_LIMIT);
+    assert_eq!(found[0].name, format!("カレー{}", super::SEARCH_LIMIT));
+}
+
+pub(crate) async fn test_search_text(repository: &impl RecipeRepository) {
+    for recipe in [
+        recipe("鶏肉の照り焼き", 1),
+        Recipe {
+            ingredients: vec![Ingredient::parse("鶏肉 300g")],
+            ..recipe("親子丼", 2)
+        },
+        recipe("スープカレー", 3),
+        recipe("肉じゃが", 4),
+    ] {
+        repository.insert_recipe(recipe).await.unwrap();
+    }
+    let search = async |query: &str| {
+        repository
+            .search_text(&SearchQuery::parse(query), 10)
+            .await
+            .unwrap()
+    };
+
+    let hits = search("とりにく").await;
+    let found = hits
+        .iter()
+        .map(|hit| hit.recipe.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(names(&found), ["鶏肉の照り焼き", "親子丼"]);
+    assert_eq!(hits[0].snippet, None);
+    assert_eq!(hits[0].highlights, ["鶏肉"]);
+    assert_eq!(hits[1].snippet.as_deref(), Some("鶏肉 300g"));
+    let hits = search("ｶﾚｰ").await;
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].highlights, ["カレー"]);
+    // A kanji without a reading is found as written, also inside other words like "牛肉".
+    assert_eq!(search("肉").await.len(), 4);
+    // Every word has to match, in any field.
+    let hits = search("煮る 親子").await;
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].recipe.name, "親子丼");
+    assert!(search("ハンバーグ").await.is_empty());
+    assert!(search(" ").await.is_empty());
+}
+
+pub(crate) async fn test_list_recipes_newest_first(repository: &impl RecipeRepository) {
+    for (name, created_at) in [("肉じゃが", 2), ("筑前煮", 3), ("豚汁", 1)] {
+        repository
+            .insert_recipe(recipe(name, created_at))
+            .await
+            .unwrap();
+    }
+
+    let recipes = repository.list_recipes(2).await.unwrap();
+    assert_eq!(names(&recipes), ["筑前煮", "肉じゃが"]);
+    let recipes = repository.list_recipes(10).await.unwrap();
+    assert_eq!(names(&recipes), ["筑前煮", "肉じゃが", "豚汁"]);
+}
+
+pub(crate) async fn test_update_recipe(repository: &impl RecipeRepository) {
+    let recipe = recipe("肉じゃが", 1);
+    repository.insert_recipe(recipe.clone()).await.unwrap();
+
+    let changed = Recipe {
+        name: "豚肉じゃが".to_string(),
+        ingredients: vec![Ingredient::parse("豚こま肉 200g")],
+        servings: None,
+        rating: Some(5),
+        tags: Vec::new(),
+        ..recipe.clone()
+    };
+    let updated = repository.update_recipe(changed.clone()).await.unwrap();
+    assert_eq!(updated.created_at, recipe.created_at);
+    assert!(updated.updated_at > recipe.updated_at);
+    assert_eq!(
+        updated,
+        Recipe {
+            updated_at: updated.updated_at,
+            ..changed
+        }
+    );
+    assert_eq!(
+        repository.get_recipe(recipe.id).await.unwrap(),
+        Some(updated)
+    );
+}
+
+pub(crate) async fn test_update_missing_recipe_fails(repository: &impl RecipeRepository) {
+    let recipe = recipe("肉じゃが", 1);
+
+    assert!(repository.update_recipe(recipe.clone()).await.is_err());
+    assert!(repository.add_note(recipe.id, "甘め").await.is_err());
+    assert_eq!(repository.get_recipe(recipe.id).await.unwrap(), None);
+}
+
+pub(crate) async fn test_add_note(repository: &impl RecipeRepository) {
+    let recipe = recipe("肉じゃが", 1);
+    repository.insert_recipe(recipe.clone()).await.unwrap();
+
+    repository.add_note(recipe.id, "次は豚肉で").await.unwrap();
+    let stored = repository.get_recipe(recipe.id).await.unwrap().unwrap();
+    assert_eq!(stored.notes, ["甘めが好み", "次は豚肉で"]);
+    assert!(stored.updated_at > recipe.updated_at);
+}
+
+pub(crate) async fn test_delete_recipe(repository: &impl RecipeRepository) {
+    let deleted = recipe("肉じゃが", 1);
+    let kept = recipe("筑前煮", 2);
+    for recipe in [&deleted, &kept] {
+        repository.insert_recipe(recipe.clone()).await.unwrap();
+    }
+
+    repository.delete_recipe(deleted.id).await.unwrap();
+    assert_eq!(repository.get_recipe(deleted.id).await.unwrap(), None);
+    assert_eq!(
+        names(&repository.list_recipes(10).await.unwrap()),
+        ["筑前煮"]
+    );
+    assert!(
+        repository
+            .find_by_url(&deleted.recipe_url)
+            .await
+            .unwrap()
+            .is_none()
+    );
+    // Deleting twice is not an error.
+    repository.delete_recipe(deleted.id).await.unwrap();
+}
@@ -0,0 +1,155 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::{
+    domain::{
+        recipe::Recipe,
+        search::{SearchHit, SearchQuery, rank},
+    },
+    infra::{repository::recipe::RecipeRepository, sync::RecipeChangeFeed},
+    prelude::*,
+};
+
+use super::{SEARCH_LIMIT, not_found};
+
+/// Keeps recipes in memory. They are lost when the process exits.
+#[derive(Default)]
+pub struct MemoryRecipeRepository {
+    recipes: Mutex<HashMap<ulid::Ulid, Recipe>>,
+}
+
+impl MemoryRecipeRepository {
+    /// Recipes matching the filter, most recently saved first.
+    fn newest_first(&self, filter: impl Fn(&Recipe) -> bool, limit: usize) -> Vec<Recipe> {
+        let mut recipes = self
+            .recipes
+            .lock()
+            .expect("recipe repository lock is poisoned")
+            .values()
+            .filter(|recipe| filter(recipe))
+            .cloned()
+            .collect::<Vec<_>>();
+        recipes.sort_by(|a, b| (b.created_at, b.id).cmp(&(a.created_at, a.id)));
+        recipes.truncate(limit);
+        recipes
+    }
+}
+
+#[async_trait]
+impl RecipeRepository for MemoryRecipeRepository {
+    async fn insert_recipe(&self, recipe: Recipe) -> Result<Recipe> {
+        let mut recipes = self
+            .recipes
+            .lock()
+            .expect("recipe repository lock is poisoned");
+        if recipes.contains_key(&recipe.id) {
+            return Err(Error::Generic(format!(
+                "recipe {} already exists",
+                recipe.id
+            )));
+        }
+        recipes.insert(recipe.id, recipe.clone());
+        Ok(recipe)
+    }
+
+    async fn get_recipe(&self, id: ulid::Ulid) -> Result<Option<Recipe>> {
+        Ok(self
+            .recipes
+            .lock()
+            .expect("recipe repository lock is poisoned")
+            .get(&id)
+            .cloned())
+    }
+
+    async fn find_by_url(&self, recipe_url: &url::Url) -> Result<Option<Recipe>> {
+        Ok(self
+            .newest_first(|recipe| &recipe.recipe_url == recipe_url, 1)
+            .pop())
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<Recipe>> {
+        let query = query.to_lowercase();
+        let matches = |text: &str| text.to_lowercase().contains(&query);
+        Ok(self.newest_first(
+            |recipe| matches(&recipe.name) || recipe.tags.iter().any(|tag| matches(tag)),
+            SEARCH_LIMIT,
+        ))
+    }
+
+    async fn search_text(&self, query: &SearchQuery, limit: usize) -> Result<Vec<SearchHit>> {
+        let recipes = self
+            .recipes
+            .lock()
+            .expect("recipe repository lock is poisoned")
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        Ok(rank(query, recipes, limit))
+    }
+
+    async fn list_recipes(&self, limit: usize) -> Result<Vec<Recipe>> {
+        Ok(self.newest_first(|_| true, limit))
+    }
+
+    async fn update_recipe(&self, recipe: Recipe) -> Result<Recipe> {
+        let mut recipes = self
+            .recipes
+            .lock()
+            .expect("recipe repository lock is poisoned");
+        let stored = recipes
+            .get_mut(&recipe.id)
+            .ok_or_else(|| not_found(recipe.id))?;
+        *stored = Recipe {
+            created_at: stored.created_at,
+            updated_at: Utc::now(),
+            ..recipe
+        };
+        Ok(stored.clone())
+    }
+
+    async fn add_note(&self, id: ulid::Ulid, note: &str) -> Result<()> {
+        let mut recipes = self
+            .recipes
+            .lock()
+            .expect("recipe repository lock is poisoned");
+        let stored = recipes.get_mut(&id).ok_or_else(|| not_found(id))?;
+        stored.notes.push(note.to_string());
+        stored.updated_at = Utc::now();
+        Ok(())
+    }
+
+    async fn delete_recipe(&self, id: ulid::Ulid) -> Result<()> {
+        self.recipes
+            .lock()
+            .expect("recipe repository lock is poisoned")
+            .remove(&id);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RecipeChangeFeed for MemoryRecipeRepository {
+    async fn recipes_edited_since(&self, since: Option<DateTime<Utc>>) -> Result<Vec<Recipe>> {
+        let mut recipes = self
+            .recipes
+            .lock()
+            .expect("recipe repository lock is poisoned")
+            .values()
+            .filter(|recipe| since.is_none_or(|since| recipe.updated_at >= since))
+            .cloned()
+            .collect::<Vec<_>>();
+        recipes.sort_by_key(|recipe| recipe.updated_at);
+        Ok(recipes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::libs::repository::conformance::recipe_repository_conformance;
+
+    use super::*;
+
+    recipe_repository_conformance!(MemoryRecipeRepository::default());
+}
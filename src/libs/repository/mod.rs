@@ -0,0 +1,13 @@
+//! Recipe storage without Notion, for local use and tests.
+
+#[cfg(test)]
+pub(crate) mod conformance;
+pub mod memory;
+pub mod sqlite;
+
+/// Number of recipes returned by a search, the same as with Notion.
+const SEARCH_LIMIT: usize = 10;
+
+pub(crate) fn not_found(id: ulid::Ulid) -> crate::prelude::Error {
+    crate::prelude::Error::Generic(format!("recipe {id} not found"))
+}
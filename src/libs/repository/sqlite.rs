@@ -0,0 +1,548 @@
+use std::{path::Path, time::Duration};
+
+use async_trait::async_trait;
+use chrono::{DateTime, SubsecRound, Utc};
+use rusqlite::{Connection, OptionalExtension, Row, params};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    domain::{
+        recipe::{Ingredient, Recipe},
+        search::{FoldedText, SearchHit, SearchQuery, rank, searchable_text},
+    },
+    infra::repository::recipe::RecipeRepository,
+    libs::sqlite::SqliteConnection,
+    prelude::*,
+};
+
+use super::{SEARCH_LIMIT, not_found};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS recipes (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    recipe_url TEXT NOT NULL,
+    ingredients TEXT NOT NULL,
+    steps TEXT NOT NULL,
+    servings INTEGER,
+    prep_time_secs INTEGER,
+    cook_time_secs INTEGER,
+    total_time_secs INTEGER,
+    image_url TEXT,
+    description TEXT,
+    source TEXT,
+    tags TEXT NOT NULL,
+    rating INTEGER,
+    status TEXT,
+    notes TEXT NOT NULL,
+    page_url TEXT,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS recipes_recipe_url ON recipes (recipe_url);
+CREATE INDEX IF NOT EXISTS recipes_created_at ON recipes (created_at);
+CREATE VIRTUAL TABLE IF NOT EXISTS recipe_terms USING fts5(
+    recipe_id UNINDEXED,
+    terms,
+    tokenize = 'ascii'
+);
+";
+
+/// Version of the index terms, kept in `PRAGMA user_version`. Bumped whenever the folding of
+/// search text changes, so that indexes written before are rebuilt.
+const INDEX_VERSION: i64 = 1;
+
+/// Columns read back into a [`Recipe`], in the order expected by [`recipe_row`].
+const COLUMNS: &str = "id, name, recipe_url, ingredients, steps, servings, prep_time_secs,
+    cook_time_secs, total_time_secs, image_url, description, source, tags, rating, status,
+    notes, page_url, created_at, updated_at";
+
+/// Keeps recipes in a SQLite file. Lists are stored as JSON arrays and timestamps as Unix
+/// milliseconds, so the sub-millisecond part of a timestamp is dropped.
+pub struct SqliteRecipeRepository {
+    connection: SqliteConnection,
+}
+
+impl SqliteRecipeRepository {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(mut connection: Connection) -> Result<Self> {
+        connection.execute_batch(SCHEMA)?;
+        reindex_if_stale(&mut connection)?;
+        Ok(Self {
+            connection: SqliteConnection::new(connection),
+        })
+    }
+
+    /// Stores a recipe exactly as given, replacing the one with the same ID. Used to mirror
+    /// recipes kept elsewhere, whose timestamps must not change.
+    pub async fn put_recipe(&self, recipe: Recipe) -> Result<()> {
+        self.connection
+            .call(move |connection| write_recipe(connection, "INSERT OR REPLACE", &recipe))
+            .await
+    }
+
+    pub async fn recipe_ids(&self) -> Result<Vec<ulid::Ulid>> {
+        self.connection
+            .call(|connection| {
+                let mut statement = connection.prepare("SELECT id FROM recipes")?;
+                let ids = statement.query_map([], |row| row.get::<_, String>(0))?;
+                ids.map(|id| parse_id(&id?)).collect()
+            })
+            .await
+    }
+
+    /// Runs a query returning recipes. The statement is appended to the selected columns.
+    async fn query_recipes(
+        &self,
+        statement: &'static str,
+        parameters: Vec<rusqlite::types::Value>,
+    ) -> Result<Vec<Recipe>> {
+        self.connection
+            .call(move |connection| {
+                let mut statement =
+                    connection.prepare(&format!("SELECT {COLUMNS} FROM recipes {statement}"))?;
+                let rows =
+                    statement.query_map(rusqlite::params_from_iter(parameters), recipe_row)?;
+                rows.map(|row| row?.into_recipe()).collect()
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl RecipeRepository for SqliteRecipeRepository {
+    async fn insert_recipe(&self, recipe: Recipe) -> Result<Recipe> {
+        let recipe = Recipe {
+            created_at: recipe.created_at.trunc_subsecs(3),
+            updated_at: recipe.updated_at.trunc_subsecs(3),
+            ..recipe
+        };
+        let stored = recipe.clone();
+        self.connection
+            .call(move |connection| write_recipe(connection, "INSERT", &stored))
+            .await?;
+        Ok(recipe)
+    }
+
+    async fn get_recipe(&self, id: ulid::Ulid) -> Result<Option<Recipe>> {
+        let id = id.to_string();
+        self.connection
+            .call(move |connection| select_recipe(connection, &id))
+            .await
+    }
+
+    async fn find_by_url(&self, recipe_url: &url::Url) -> Result<Option<Recipe>> {
+        let recipes = self
+            .query_recipes(
+                "WHERE recipe_url = ?1 ORDER BY created_at DESC, id DESC LIMIT 1",
+                vec![recipe_url.to_string().into()],
+            )
+            .await?;
+        Ok(recipes.into_iter().next())
+    }
+
+    /// Finds the candidates in the `recipe_terms` index, then ranks them.
+    async fn search_text(&self, query: &SearchQuery, limit: usize) -> Result<Vec<SearchHit>> {
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+        let pattern = query
+            .terms()
+            .into_iter()
+            .map(|(term, prefix)| {
+                if prefix {
+                    format!("\"{term}\"*")
+                } else {
+                    format!("\"{term}\"")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let recipes = self
+            .query_recipes(
+                "WHERE id IN (SELECT recipe_id FROM recipe_terms WHERE recipe_terms MATCH ?1)",
+                vec![pattern.into()],
+            )
+            .await?;
+        Ok(rank(query, recipes, limit))
+    }
+
+    /// Matches case-insensitively for ASCII letters only, as SQLite's `lower()` does.
+    async fn search(&self, query: &str) -> Result<Vec<Recipe>> {
+        self.query_recipes(
+            "WHERE instr(lower(name), ?1) > 0
+                OR EXISTS (SELECT 1 FROM json_each(recipes.tags)
+                           WHERE instr(lower(json_each.value), ?1) > 0)
+             ORDER BY created_at DESC, id DESC LIMIT ?2",
+            vec![
+                query.to_ascii_lowercase().into(),
+                i64::try_from(SEARCH_LIMIT).unwrap_or(i64::MAX).into(),
+            ],
+        )
+        .await
+    }
+
+    async fn list_recipes(&self, limit: usize) -> Result<Vec<Recipe>> {
+        self.query_recipes(
+            "ORDER BY created_at DESC, id DESC LIMIT ?1",
+            vec![i64::try_from(limit).unwrap_or(i64::MAX).into()],
+        )
+        .await
+    }
+
+    async fn update_recipe(&self, recipe: Recipe) -> Result<Recipe> {
+        let recipe = Recipe {
+            updated_at: Utc::now().trunc_subsecs(3),
+            ..recipe
+        };
+        self.connection
+            .call(move |connection| {
+                let row = StoredRecipe::from_recipe(&recipe)?;
+                let transaction = connection.transaction()?;
+                let updated = transaction.execute(
+                    "UPDATE recipes SET name = ?2, recipe_url = ?3, ingredients = ?4, steps = ?5,
+                         servings = ?6, prep_time_secs = ?7, cook_time_secs = ?8,
+                         total_time_secs = ?9, image_url = ?10, description = ?11, source = ?12,
+                         tags = ?13, rating = ?14, status = ?15, notes = ?16, page_url = ?17,
+                         updated_at = ?18
+                     WHERE id = ?1",
+                    params![
+                        row.id,
+                        row.name,
+                        row.recipe_url,
+                        row.ingredients,
+                        row.steps,
+                        row.servings,
+                        row.prep_time_secs,
+                        row.cook_time_secs,
+                        row.total_time_secs,
+                        row.image_url,
+                        row.description,
+                        row.source,
+                        row.tags,
+                        row.rating,
+                        row.status,
+                        row.notes,
+                        row.page_url,
+                        row.updated_at,
+                    ],
+                )?;
+                if updated == 0 {
+                    return Err(not_found(recipe.id));
+                }
+                let stored =
+                    select_recipe(&transaction, &row.id)?.ok_or_else(|| not_found(recipe.id))?;
+                index_recipe(&transaction, &stored)?;
+                transaction.commit()?;
+                Ok(stored)
+            })
+            .await
+    }
+
+    async fn add_note(&self, id: ulid::Ulid, note: &str) -> Result<()> {
+        let note = note.to_string();
+        let updated = self
+            .connection
+            .call(move |connection| {
+                Ok(connection.execute(
+                    "UPDATE recipes SET notes = json_insert(notes, '$[#]', ?2), updated_at = ?3
+                     WHERE id = ?1",
+                    params![id.to_string(), note, Utc::now().timestamp_millis()],
+                )?)
+            })
+            .await?;
+        if updated == 0 {
+            return Err(not_found(id));
+        }
+        Ok(())
+    }
+
+    async fn delete_recipe(&self, id: ulid::Ulid) -> Result<()> {
+        let id = id.to_string();
+        self.connection
+            .call(move |connection| {
+                let transaction = connection.transaction()?;
+                transaction.execute("DELETE FROM recipes WHERE id = ?1", params![id])?;
+                transaction
+                    .execute("DELETE FROM recipe_terms WHERE recipe_id = ?1", params![id])?;
+                transaction.commit()?;
+                Ok(())
+            })
+            .await
+    }
+}
+
+/// Writes every column of a recipe with `verb`, e.g. `INSERT`, and indexes its text.
+fn write_recipe(connection: &mut Connection, verb: &str, recipe: &Recipe) -> Result<()> {
+    let row = StoredRecipe::from_recipe(recipe)?;
+    let transaction = connection.transaction()?;
+    transaction.execute(
+        &format!(
+            "{verb} INTO recipes ({COLUMNS})
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17,
+                     ?18, ?19)"
+        ),
+        params![
+            row.id,
+            row.name,
+            row.recipe_url,
+            row.ingredients,
+            row.steps,
+            row.servings,
+            row.prep_time_secs,
+            row.cook_time_secs,
+            row.total_time_secs,
+            row.image_url,
+            row.description,
+            row.source,
+            row.tags,
+            row.rating,
+            row.status,
+            row.notes,
+            row.page_url,
+            row.created_at,
+            row.updated_at,
+        ],
+    )?;
+    index_recipe(&transaction, recipe)?;
+    transaction.commit()?;
+    Ok(())
+}
+
+/// Replaces the index terms of a recipe, which are the folded terms of its searchable text.
+fn index_recipe(connection: &Connection, recipe: &Recipe) -> Result<()> {
+    let id = recipe.id.to_string();
+    let terms = searchable_text(recipe)
+        .iter()
+        .flat_map(|text| FoldedText::new(text).terms())
+        .collect::<Vec<_>>()
+        .join(" ");
+    connection.execute("DELETE FROM recipe_terms WHERE recipe_id = ?1", params![id])?;
+    connection.execute(
+        "INSERT INTO recipe_terms (recipe_id, terms) VALUES (?1, ?2)",
+        params![id, terms],
+    )?;
+    Ok(())
+}
+
+/// Rebuilds the index when it does not cover every recipe, e.g. for files written before it
+/// existed, or was built by another [`INDEX_VERSION`].
+fn reindex_if_stale(connection: &mut Connection) -> Result<()> {
+    let count = |table: &str| {
+        connection.query_row(&format!("SELECT count(*) FROM {table}"), [], |row| {
+            row.get::<_, i64>(0)
+        })
+    };
+    let version = connection.query_row("PRAGMA user_version", [], |row| row.get::<_, i64>(0))?;
+    if version == INDEX_VERSION && count("recipes")? == count("recipe_terms")? {
+        return Ok(());
+    }
+    let transaction = connection.transaction()?;
+    transaction.execute("DELETE FROM recipe_terms", [])?;
+    let recipes = {
+        let mut statement = transaction.prepare(&format!("SELECT {COLUMNS} FROM recipes"))?;
+        let rows = statement.query_map([], recipe_row)?;
+        rows.map(|row| row?.into_recipe())
+            .collect::<Result<Vec<_>>>()?
+    };
+    for recipe in &recipes {
+        index_recipe(&transaction, recipe)?;
+    }
+    transaction.pragma_update(None, "user_version", INDEX_VERSION)?;
+    transaction.commit()?;
+    Ok(())
+}
+
+fn select_recipe(connection: &Connection, id: &str) -> Result<Option<Recipe>> {
+    connection
+        .query_row(
+            &format!("SELECT {COLUMNS} FROM recipes WHERE id = ?1"),
+            params![id],
+            recipe_row,
+        )
+        .optional()?
+        .map(StoredRecipe::into_recipe)
+        .transpose()
+}
+
+/// A recipe as laid out in the `recipes` table.
+struct StoredRecipe {
+    id: String,
+    name: String,
+    recipe_url: String,
+    ingredients: String,
+    steps: String,
+    servings: Option<u32>,
+    prep_time_secs: Option<i64>,
+    cook_time_secs: Option<i64>,
+    total_time_secs: Option<i64>,
+    image_url: Option<String>,
+    description: Option<String>,
+    source: Option<String>,
+    tags: String,
+    rating: Option<u8>,
+    status: Option<String>,
+    notes: String,
+    page_url: Option<String>,
+    created_at: i64,
+    updated_at: i64,
+}
+
+/// An ingredient inside the `ingredients` JSON array.
+#[derive(Serialize, Deserialize)]
+struct StoredIngredient {
+    name: String,
+    quantity: Option<String>,
+    unit: Option<String>,
+    note: Option<String>,
+}
+
+impl StoredRecipe {
+    fn from_recipe(recipe: &Recipe) -> Result<Self> {
+        let ingredients = recipe
+            .ingredients
+            .iter()
+            .map(|ingredient| StoredIngredient {
+                name: ingredient.name.clone(),
+                quantity: ingredient.quantity.clone(),
+                unit: ingredient.unit.clone(),
+                note: ingredient.note.clone(),
+            })
+            .collect::<Vec<_>>();
+        Ok(Self {
+            id: recipe.id.to_string(),
+            name: recipe.name.clone(),
+            recipe_url: recipe.recipe_url.to_string(),
+            ingredients: to_json(&ingredients)?,
+            steps: to_json(&recipe.steps)?,
+            servings: recipe.servings,
+            prep_time_secs: recipe.prep_time.map(secs),
+            cook_time_secs: recipe.cook_time.map(secs),
+            total_time_secs: recipe.total_time.map(secs),
+            image_url: recipe.image_url.as_ref().map(ToString::to_string),
+            description: recipe.description.clone(),
+            source: recipe.source.clone(),
+            tags: to_json(&recipe.tags)?,
+            rating: recipe.rating,
+            status: recipe.status.clone(),
+            notes: to_json(&recipe.notes)?,
+            page_url: recipe.page_url.as_ref().map(ToString::to_string),
+            created_at: recipe.created_at.timestamp_millis(),
+            updated_at: recipe.updated_at.timestamp_millis(),
+        })
+    }
+
+    fn into_recipe(self) -> Result<Recipe> {
+        let ingredients = from_json::<Vec<StoredIngredient>>(&self.ingredients)?
+            .into_iter()
+            .map(|ingredient| Ingredient {
+                name: ingredient.name,
+                quantity: ingredient.quantity,
+                unit: ingredient.unit,
+                note: ingredient.note,
+            })
+            .collect();
+        Ok(Recipe {
+            id: parse_id(&self.id)?,
+            name: self.name,
+            recipe_url: url::Url::parse(&self.recipe_url)?,
+            ingredients,
+            steps: from_json(&self.steps)?,
+            servings: self.servings,
+            prep_time: self.prep_time_secs.map(duration),
+            cook_time: self.cook_time_secs.map(duration),
+            total_time: self.total_time_secs.map(duration),
+            image_url: self.image_url.as_deref().map(url::Url::parse).transpose()?,
+            description: self.description,
+            source: self.source,
+            tags: from_json(&self.tags)?,
+            rating: self.rating,
+            status: self.status,
+            notes: from_json(&self.notes)?,
+            page_url: self.page_url.as_deref().map(url::Url::parse).transpose()?,
+            created_at: timestamp(self.created_at),
+            updated_at: timestamp(self.updated_at),
+        })
+    }
+}
+
+fn recipe_row(row: &Row) -> rusqlite::Result<StoredRecipe> {
+    Ok(StoredRecipe {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        recipe_url: row.get(2)?,
+        ingredients: row.get(3)?,
+        steps: row.get(4)?,
+        servings: row.get(5)?,
+        prep_time_secs: row.get(6)?,
+        cook_time_secs: row.get(7)?,
+        total_time_secs: row.get(8)?,
+        image_url: row.get(9)?,
+        description: row.get(10)?,
+        source: row.get(11)?,
+        tags: row.get(12)?,
+        rating: row.get(13)?,
+        status: row.get(14)?,
+        notes: row.get(15)?,
+        page_url: row.get(16)?,
+        created_at: row.get(17)?,
+        updated_at: row.get(18)?,
+    })
+}
+
+fn parse_id(id: &str) -> Result<ulid::Ulid> {
+    Ok(ulid::Ulid::from_string(id).with_context(|| format!("invalid recipe id: {id}"))?)
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String> {
+    Ok(serde_json::to_string(value).with_context(|| "failed to serialize recipe")?)
+}
+
+fn from_json<T: for<'de> Deserialize<'de>>(json: &str) -> Result<T> {
+    Ok(serde_json::from_str(json).with_context(|| "failed to deserialize recipe")?)
+}
+
+fn secs(duration: Duration) -> i64 {
+    i64::try_from(duration.as_secs()).unwrap_or(i64::MAX)
+}
+
+fn duration(secs: i64) -> Duration {
+    Duration::from_secs(u64::try_from(secs).unwrap_or_default())
+}
+
+fn timestamp(millis: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_millis(millis).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::libs::repository::conformance::{self, recipe_repository_conformance};
+
+    use super::*;
+
+    recipe_repository_conformance!(SqliteRecipeRepository::open_in_memory().unwrap());
+
+    #[tokio::test]
+    async fn test_recipes_survive_reopening() {
+        let path =
+            std::env::temp_dir().join(format!("recipena-recipes-{}.sqlite3", ulid::Ulid::new()));
+        let recipe = conformance::recipe("肉じゃが", 1);
+        {
+            let repository = SqliteRecipeRepository::open(&path).unwrap();
+            repository.insert_recipe(recipe.clone()).await.unwrap();
+        }
+        let repository = SqliteRecipeRepository::open(&path).unwrap();
+        assert_eq!(
+            repository.get_recipe(recipe.id).await.unwrap(),
+            Some(recipe)
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,64 +1,269 @@
-use crate::prelude::*;
-use anyhow::Context;
 use async_trait::async_trait;
 
-use crate::infra::html::HtmlClient;
+use crate::{
+    infra::html::{HtmlClient, ScrapedRecipe},
+    libs::{
+        charset::decode_html,
+        extractor::{
+            ExtractorRegistry, attribute, select_all,
+            video::{self, OEmbed, VideoPlatform},
+        },
+        fetch::Fetcher,
+    },
+    prelude::*,
+};
 
-pub struct ReqwestClient(reqwest::Client);
+const CANONICAL_SELECTOR: &str = r#"link[rel="canonical"]"#;
+const OG_URL_SELECTOR: &str = r#"meta[property="og:url"]"#;
 
-impl Default for ReqwestClient {
-    fn default() -> Self {
-        Self::new(reqwest::Client::new())
-    }
+pub struct ReqwestClient {
+    fetcher: Fetcher,
+    extractors: ExtractorRegistry,
+    video_platforms: Vec<VideoPlatform>,
 }
 
 impl ReqwestClient {
-    pub fn new(client: reqwest::Client) -> Self {
-        Self(client)
+    pub fn new(fetcher: Fetcher) -> Self {
+        Self::with_video_platforms(fetcher, video::platforms())
+    }
+
+    /// Uses the given video platforms instead of the built-in ones, e.g. to point their
+    /// oEmbed endpoints elsewhere.
+    pub fn with_video_platforms(fetcher: Fetcher, video_platforms: Vec<VideoPlatform>) -> Self {
+        Self {
+            fetcher,
+            extractors: ExtractorRegistry::with_video_platforms(&video_platforms),
+            video_platforms,
+        }
     }
 
-    async fn get(&self, url: &str) -> Result<String> {
-        Ok(self.0.get(url).send().await?.text().await?)
+    /// Reads a recipe from a page, or `None` when no extractor finds one.
+    async fn read_page(&self, url: &str) -> Result<Option<ScrapedRecipe>> {
+        let page = self.fetcher.get(url).await?;
+        let html = decode_html(&page.body, page.content_type.as_deref());
+
+        let dom = tl::parse(&html, tl::ParserOptions::default())?;
+        // The final URL decides the site, so shortened links get the site-specific rules.
+        let Some(mut recipe) = self.extractors.extract(&page.url, &dom) else {
+            return Ok(None);
+        };
+        tracing::debug!(url = %page.url, strategy = ?recipe.strategy, "extracted recipe");
+        recipe.canonical_url = extract_canonical_url(&dom);
+
+        Ok(Some(recipe))
     }
 
-    fn query_node<'a>(dom: &'a tl::VDom<'_>, selector: &str) -> Result<&'a tl::Node<'a>> {
-        let handle = dom
-            .query_selector(selector)
-            .and_then(|mut m| m.next())
-            .with_context(|| format!("no node found for selector: {selector}"))?;
-        Ok(handle
-            .get(dom.parser())
-            .with_context(|| format!("no node found for selector: {selector}"))?)
+    /// Reads a video from its oEmbed data and its page. Either may be unavailable, e.g. when
+    /// the page is behind a consent or login wall, so only both failing is an error.
+    async fn read_video(
+        &self,
+        platform: &VideoPlatform,
+        url: &url::Url,
+    ) -> Result<Option<ScrapedRecipe>> {
+        let oembed = match platform.oembed_url(url) {
+            Some(endpoint) => self
+                .fetcher
+                .get_json::<OEmbed>(endpoint.as_str())
+                .await
+                .inspect_err(
+                    |e| tracing::warn!(%e, platform = platform.name, "failed to get oEmbed data"),
+                )
+                .ok(),
+            None => None,
+        };
+        let page = match self.read_page(url.as_str()).await {
+            Ok(page) => page,
+            Err(e) if oembed.is_some() => {
+                tracing::warn!(%e, platform = platform.name, "failed to read video page");
+                None
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(match oembed {
+            Some(oembed) => oembed.complete(platform, page),
+            None => page,
+        })
     }
 }
 
 #[async_trait]
 impl HtmlClient for ReqwestClient {
-    async fn get_title(&self, url: &str) -> Result<String> {
-        let html = self.get(url).await?;
-
-        let dom = tl::parse(&html, tl::ParserOptions::default())?;
-        let node = Self::query_node(&dom, "title")?;
-
-        Ok(node.inner_text(dom.parser()).to_string())
+    async fn get_recipe(&self, url: &str) -> Result<ScrapedRecipe> {
+        let parsed = url::Url::parse(url)?;
+        let recipe = match self
+            .video_platforms
+            .iter()
+            .find(|platform| platform.matches(&parsed))
+        {
+            Some(platform) => self.read_video(platform, &parsed).await?,
+            None => self.read_page(url).await?,
+        };
+        recipe.ok_or_else(|| anyhow::anyhow!("no recipe found in {url}").into())
     }
 }
 
+fn extract_canonical_url(dom: &tl::VDom<'_>) -> Option<String> {
+    let first_attribute = |selector, name| {
+        select_all(dom, selector)
+            .into_iter()
+            .find_map(|node| attribute(node, name))
+    };
+    first_attribute(CANONICAL_SELECTOR, "href")
+        .or_else(|| first_attribute(OG_URL_SELECTOR, "content"))
+}
+
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
+    use axum::{
+        Router,
+        extract::Query,
+        http::{StatusCode, header::CONTENT_TYPE},
+        response::IntoResponse,
+        routing::get,
+    };
+    use tokio::net::TcpListener;
+
     use super::*;
+    use crate::{config::FetchConfig, infra::html::ExtractionStrategy};
+    use test_case::test_case;
+
+    const YOUTUBE_PAGE: &str = include_str!("../../tests/fixtures/videos/youtube.html");
+    const YOUTUBE_OEMBED: &str = include_str!("../../tests/fixtures/videos/youtube_oembed.json");
+    const TIKTOK_OEMBED: &str = include_str!("../../tests/fixtures/videos/tiktok_oembed.json");
+
+    /// Answers an oEmbed request for one video.
+    fn oembed(
+        Query(query): Query<HashMap<String, String>>,
+        body: &'static str,
+        video_path: &str,
+    ) -> axum::response::Response {
+        match query.get("url") {
+            Some(url) if url.ends_with(video_path) => {
+                ([(CONTENT_TYPE, "application/json")], body).into_response()
+            }
+            _ => StatusCode::NOT_FOUND.into_response(),
+        }
+    }
+
+    /// Serves recorded video pages and oEmbed responses in place of the platforms, and returns
+    /// its base URL.
+    async fn serve_platforms() -> String {
+        let app = Router::new()
+            .route(
+                "/watch",
+                get(|| async { ([(CONTENT_TYPE, "text/html; charset=utf-8")], YOUTUBE_PAGE) }),
+            )
+            .route(
+                "/youtube/oembed",
+                get(|query: Query<HashMap<String, String>>| async move {
+                    oembed(query, YOUTUBE_OEMBED, "/watch?v=abc123")
+                }),
+            )
+            .route(
+                "/@tamago_kitchen/video/1",
+                get(|| async { StatusCode::FORBIDDEN }),
+            )
+            .route(
+                "/tiktok/oembed",
+                get(|query: Query<HashMap<String, String>>| async move {
+                    oembed(query, TIKTOK_OEMBED, "/video/1")
+                }),
+            );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{addr}")
+    }
+
+    fn client(platform: VideoPlatform) -> ReqwestClient {
+        let fetcher = Fetcher::from_config(&FetchConfig {
+            allow_private_addresses: true,
+            ..Default::default()
+        })
+        .unwrap();
+        ReqwestClient::with_video_platforms(fetcher, vec![platform])
+    }
+
+    #[tokio::test]
+    async fn test_get_recipe_from_video() {
+        let base = serve_platforms().await;
+        let platform = VideoPlatform::new(
+            "youtube",
+            &["127.0.0.1"],
+            Some(&format!("{base}/youtube/oembed")),
+        );
+
+        let recipe = client(platform)
+            .get_recipe(&format!("{base}/watch?v=abc123"))
+            .await
+            .unwrap();
+        assert_eq!(recipe.strategy, ExtractionStrategy::Video("youtube"));
+        assert_eq!(recipe.name, "【簡単】フライパンで作る鶏の照り焼き");
+        assert_eq!(recipe.author.as_deref(), Some("おうちごはんチャンネル"));
+        assert_eq!(
+            recipe.image.as_deref(),
+            Some("https://i.ytimg.com/vi/abc123/hqdefault.jpg")
+        );
+        assert_eq!(recipe.ingredients.len(), 4);
+        assert_eq!(recipe.instructions.len(), 3);
+        assert_eq!(
+            recipe.canonical_url.as_deref(),
+            Some("https://www.youtube.com/watch?v=abc123")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_recipe_from_video_behind_login() {
+        let base = serve_platforms().await;
+        let platform = VideoPlatform::new(
+            "tiktok",
+            &["127.0.0.1"],
+            Some(&format!("{base}/tiktok/oembed")),
+        );
+
+        let recipe = client(platform)
+            .get_recipe(&format!("{base}/@tamago_kitchen/video/1"))
+            .await
+            .unwrap();
+        assert_eq!(recipe.strategy, ExtractionStrategy::Video("tiktok"));
+        assert_eq!(recipe.name, "混ぜて焼くだけ！ふわふわ卵焼き🍳");
+        assert_eq!(recipe.author.as_deref(), Some("たまごキッチン"));
+        assert_eq!(
+            recipe.ingredients,
+            vec![
+                "卵 3個".to_string(),
+                "白だし 小さじ2".to_string(),
+                "砂糖 小さじ1".to_string()
+            ]
+        );
+        assert_eq!(
+            recipe.instructions,
+            vec![
+                "材料を全部混ぜる".to_string(),
+                "3回に分けて巻きながら焼く".to_string()
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_recipe_from_unavailable_video() {
+        let base = serve_platforms().await;
+        let platform = VideoPlatform::new("instagram", &["127.0.0.1"], None);
+
+        let result = client(platform)
+            .get_recipe(&format!("{base}/@tamago_kitchen/video/1"))
+            .await;
+        assert!(matches!(result, Err(Error::FetchError(_))));
+    }
 
-    #[test]
-    fn test_query_node() {
-        let body = r#""
-        <html>
-            <head>
-                <title>Google</title>
-            </head>
-        </html>
-        "#;
-        let dom = tl::parse(body, tl::ParserOptions::default()).unwrap();
-        let node = ReqwestClient::query_node(&dom, "title").unwrap();
-        assert_eq!(node.inner_text(dom.parser()), "Google");
+    #[test_case(r#"<link rel="canonical" href="https://example.com/a"><meta property="og:url" content="https://example.com/b">"# => Some("https://example.com/a".to_string()) ; "canonical link")]
+    #[test_case(r#"<meta property="og:url" content="https://example.com/b">"# => Some("https://example.com/b".to_string()) ; "og url")]
+    #[test_case("<title>no canonical</title>" => None ; "none")]
+    fn test_extract_canonical_url(head: &str) -> Option<String> {
+        let body = format!("<html><head>{head}</head></html>");
+        let dom = tl::parse(&body, tl::ParserOptions::default()).unwrap();
+        extract_canonical_url(&dom)
     }
 }
@@ -0,0 +1,37 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context;
+use rusqlite::Connection;
+
+use crate::prelude::*;
+
+/// A SQLite connection shared by the stores that persist to a database file.
+///
+/// rusqlite is synchronous, so queries run on the blocking thread pool one at a time.
+#[derive(Clone)]
+pub struct SqliteConnection {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteConnection {
+    pub fn new(connection: Connection) -> Self {
+        Self {
+            connection: Arc::new(Mutex::new(connection)),
+        }
+    }
+
+    /// Runs a closure on the connection without blocking the async runtime.
+    pub async fn call<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || {
+            f(&mut connection
+                .lock()
+                .expect("sqlite connection lock is poisoned"))
+        })
+        .await
+        .with_context(|| "sqlite task panicked")?
+    }
+}
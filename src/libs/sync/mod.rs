@@ -0,0 +1,5 @@
+//! Mirroring the recipes kept in Notion to a local SQLite file, so reads do not wait for Notion.
+
+pub mod repository;
+pub mod store;
+pub mod syncer;
@@ -0,0 +1,150 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::sync::{Mutex, Notify};
+
+use crate::{
+    domain::{
+        recipe::{Recipe, RecipeField},
+        search::{SearchHit, SearchQuery},
+    },
+    infra::repository::recipe::RecipeRepository,
+    libs::repository::{not_found, sqlite::SqliteRecipeRepository},
+    prelude::*,
+};
+
+use super::store::SyncStore;
+
+/// Answers reads from the local mirror and queues writes for the [`RecipeSyncer`] to push.
+///
+/// New recipes are still saved remotely right away, since the remote store assigns their IDs
+/// and page URLs.
+///
+/// [`RecipeSyncer`]: super::syncer::RecipeSyncer
+pub struct SyncedRecipeRepository {
+    remote: Arc<dyn RecipeRepository + Send + Sync>,
+    mirror: Arc<SqliteRecipeRepository>,
+    store: Arc<SyncStore>,
+    lock: Arc<Mutex<()>>,
+    changed: Arc<Notify>,
+}
+
+impl SyncedRecipeRepository {
+    pub(super) fn new(
+        remote: Arc<dyn RecipeRepository + Send + Sync>,
+        mirror: Arc<SqliteRecipeRepository>,
+        store: Arc<SyncStore>,
+        lock: Arc<Mutex<()>>,
+        changed: Arc<Notify>,
+    ) -> Self {
+        Self {
+            remote,
+            mirror,
+            store,
+            lock,
+            changed,
+        }
+    }
+}
+
+#[async_trait]
+impl RecipeRepository for SyncedRecipeRepository {
+    /// The mirror stores everything, but only what the remote stores survives a pull.
+    fn stores(&self, field: RecipeField) -> bool {
+        self.remote.stores(field)
+    }
+
+    async fn insert_recipe(&self, recipe: Recipe) -> Result<Recipe> {
+        let _lock = self.lock.lock().await;
+        let stored = self.remote.insert_recipe(recipe).await?;
+        self.mirror.put_recipe(stored.clone()).await?;
+        Ok(stored)
+    }
+
+    async fn get_recipe(&self, id: ulid::Ulid) -> Result<Option<Recipe>> {
+        self.mirror.get_recipe(id).await
+    }
+
+    async fn find_by_url(&self, recipe_url: &url::Url) -> Result<Option<Recipe>> {
+        self.mirror.find_by_url(recipe_url).await
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<Recipe>> {
+        self.mirror.search(query).await
+    }
+
+    async fn search_text(&self, query: &SearchQuery, limit: usize) -> Result<Vec<SearchHit>> {
+        self.mirror.search_text(query, limit).await
+    }
+
+    async fn list_recipes(&self, limit: usize) -> Result<Vec<Recipe>> {
+        self.mirror.list_recipes(limit).await
+    }
+
+    async fn update_recipe(&self, recipe: Recipe) -> Result<Recipe> {
+        let updated = {
+            let _lock = self.lock.lock().await;
+            let current = self
+                .mirror
+                .get_recipe(recipe.id)
+                .await?
+                .ok_or_else(|| not_found(recipe.id))?;
+            self.store
+                .queue_update(recipe.id, current.updated_at)
+                .await?;
+            self.mirror.update_recipe(recipe).await?
+        };
+        self.changed.notify_one();
+        Ok(updated)
+    }
+
+    async fn add_note(&self, id: ulid::Ulid, note: &str) -> Result<()> {
+        {
+            let _lock = self.lock.lock().await;
+            self.mirror.add_note(id, note).await?;
+            self.store.queue_note(id, note).await?;
+        }
+        self.changed.notify_one();
+        Ok(())
+    }
+
+    async fn delete_recipe(&self, id: ulid::Ulid) -> Result<()> {
+        {
+            let _lock = self.lock.lock().await;
+            self.mirror.delete_recipe(id).await?;
+            self.store.queue_delete(id).await?;
+        }
+        self.changed.notify_one();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::SyncConfig,
+        libs::{
+            repository::{
+                conformance::recipe_repository_conformance, memory::MemoryRecipeRepository,
+            },
+            sync::syncer::RecipeSyncer,
+        },
+    };
+
+    use super::*;
+
+    /// A repository over an empty memory remote, whose writes are left queued.
+    fn repository() -> SyncedRecipeRepository {
+        let remote = Arc::new(MemoryRecipeRepository::default());
+        RecipeSyncer::new(
+            remote.clone(),
+            remote,
+            Arc::new(SqliteRecipeRepository::open_in_memory().unwrap()),
+            Arc::new(SyncStore::open_in_memory().unwrap()),
+            &SyncConfig::default(),
+        )
+        .repository()
+    }
+
+    recipe_repository_conformance!(repository());
+}
@@ -0,0 +1,343 @@
+use std::{collections::HashSet, path::Path};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, params};
+
+use crate::{libs::sqlite::SqliteConnection, prelude::*};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS sync_changes (
+    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+    recipe_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    note TEXT,
+    base_updated_at INTEGER,
+    queued_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS sync_changes_recipe_id ON sync_changes (recipe_id);
+CREATE TABLE IF NOT EXISTS sync_state (
+    name TEXT PRIMARY KEY,
+    value INTEGER NOT NULL
+);
+";
+
+/// Name of the `sync_state` row holding the `last_edited_time` pulled so far.
+const CURSOR: &str = "cursor";
+
+/// A local write waiting to be pushed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingChange {
+    pub seq: i64,
+    pub recipe_id: ulid::Ulid,
+    pub kind: ChangeKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// The mirrored recipe was edited. `base_updated_at` is when the copy that was edited was
+    /// last changed remotely, to detect edits made remotely in the meantime.
+    Update {
+        base_updated_at: DateTime<Utc>,
+    },
+    Note(String),
+    Delete,
+}
+
+/// Keeps the writes not pushed yet and the pull cursor in SQLite, so neither is lost on restart.
+pub struct SyncStore {
+    connection: SqliteConnection,
+}
+
+impl SyncStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(connection: Connection) -> Result<Self> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self {
+            connection: SqliteConnection::new(connection),
+        })
+    }
+
+    /// Queues an edit. Further edits before the push are merged into it, keeping the first base.
+    pub async fn queue_update(
+        &self,
+        recipe_id: ulid::Ulid,
+        base_updated_at: DateTime<Utc>,
+    ) -> Result<()> {
+        self.connection
+            .call(move |connection| {
+                connection.execute(
+                    "INSERT INTO sync_changes (recipe_id, kind, base_updated_at, queued_at)
+                     SELECT ?1, 'update', ?2, ?3
+                     WHERE NOT EXISTS (
+                         SELECT 1 FROM sync_changes WHERE recipe_id = ?1 AND kind = 'update'
+                     )",
+                    params![
+                        recipe_id.to_string(),
+                        base_updated_at.timestamp_millis(),
+                        Utc::now().timestamp_millis()
+                    ],
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    pub async fn queue_note(&self, recipe_id: ulid::Ulid, note: &str) -> Result<()> {
+        let note = note.to_string();
+        self.connection
+            .call(move |connection| {
+                connection.execute(
+                    "INSERT INTO sync_changes (recipe_id, kind, note, queued_at)
+                     VALUES (?1, 'note', ?2, ?3)",
+                    params![recipe_id.to_string(), note, Utc::now().timestamp_millis()],
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    /// Queues a deletion, which replaces the changes still queued for the recipe.
+    pub async fn queue_delete(&self, recipe_id: ulid::Ulid) -> Result<()> {
+        self.connection
+            .call(move |connection| {
+                let recipe_id = recipe_id.to_string();
+                let transaction = connection.transaction()?;
+                transaction.execute(
+                    "DELETE FROM sync_changes WHERE recipe_id = ?1",
+                    params![recipe_id],
+                )?;
+                transaction.execute(
+                "INSERT INTO sync_changes (recipe_id, kind, queued_at) VALUES (?1, 'delete', ?2)",
+                params![recipe_id, Utc::now().timestamp_millis()],
+            )?;
+                transaction.commit()?;
+                Ok(())
+            })
+            .await
+    }
+
+    /// Returns the queued changes, oldest first.
+    pub async fn pending_changes(&self) -> Result<Vec<PendingChange>> {
+        self.connection
+            .call(|connection| {
+                let mut statement = connection.prepare(
+                    "SELECT seq, recipe_id, kind, note, base_updated_at FROM sync_changes
+                     ORDER BY seq",
+                )?;
+                let rows = statement.query_map([], |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                        row.get::<_, Option<i64>>(4)?,
+                    ))
+                })?;
+                let mut changes = Vec::new();
+                for row in rows {
+                    let (seq, recipe_id, kind, note, base_updated_at) = row?;
+                    let kind = match (kind.as_str(), note, base_updated_at) {
+                        ("update", _, Some(base_updated_at)) => ChangeKind::Update {
+                            base_updated_at: timestamp(base_updated_at),
+                        },
+                        ("note", Some(note), _) => ChangeKind::Note(note),
+                        ("delete", _, _) => ChangeKind::Delete,
+                        _ => {
+                            return Err(Error::Generic(format!(
+                                "invalid sync change {seq}: {kind}"
+                            )));
+                        }
+                    };
+                    changes.push(PendingChange {
+                        seq,
+                        recipe_id: parse_id(&recipe_id)?,
+                        kind,
+                    });
+                }
+                Ok(changes)
+            })
+            .await
+    }
+
+    /// Returns the recipes with queued changes, which pulls must not overwrite.
+    pub async fn pending_recipe_ids(&self) -> Result<HashSet<ulid::Ulid>> {
+        self.connection
+            .call(|connection| {
+                let mut statement =
+                    connection.prepare("SELECT DISTINCT recipe_id FROM sync_changes")?;
+                let ids = statement.query_map([], |row| row.get::<_, String>(0))?;
+                ids.map(|id| parse_id(&id?)).collect()
+            })
+            .await
+    }
+
+    /// Removes a change once it was pushed.
+    pub async fn complete(&self, seq: i64) -> Result<()> {
+        self.connection
+            .call(move |connection| {
+                connection.execute("DELETE FROM sync_changes WHERE seq = ?1", params![seq])?;
+                Ok(())
+            })
+            .await
+    }
+
+    /// Moves the base of a queued edit from `before` to `after` once a push of ours changed the
+    /// remote recipe in between. Edits based on an older copy keep their base, so a remote edit
+    /// made before the push still counts as a conflict.
+    pub async fn rebase_updates(
+        &self,
+        recipe_id: ulid::Ulid,
+        before: DateTime<Utc>,
+        after: DateTime<Utc>,
+    ) -> Result<()> {
+        self.connection
+            .call(move |connection| {
+                connection.execute(
+                    "UPDATE sync_changes SET base_updated_at = ?3
+                     WHERE recipe_id = ?1 AND kind = 'update' AND base_updated_at >= ?2",
+                    params![
+                        recipe_id.to_string(),
+                        before.timestamp_millis(),
+                        after.timestamp_millis()
+                    ],
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    /// Drops the queued edits of a recipe after they lost a conflict. Notes are kept.
+    pub async fn discard_updates(&self, recipe_id: ulid::Ulid) -> Result<()> {
+        self.connection
+            .call(move |connection| {
+                connection.execute(
+                    "DELETE FROM sync_changes WHERE recipe_id = ?1 AND kind = 'update'",
+                    params![recipe_id.to_string()],
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    /// Drops every change of a recipe that no longer exists remotely.
+    pub async fn discard_all(&self, recipe_id: ulid::Ulid) -> Result<()> {
+        self.connection
+            .call(move |connection| {
+                connection.execute(
+                    "DELETE FROM sync_changes WHERE recipe_id = ?1",
+                    params![recipe_id.to_string()],
+                )?;
+                Ok(())
+            })
+            .await
+    }
+
+    pub async fn cursor(&self) -> Result<Option<DateTime<Utc>>> {
+        self.connection
+            .call(|connection| {
+                Ok(connection
+                    .query_row(
+                        "SELECT value FROM sync_state WHERE name = ?1",
+                        params![CURSOR],
+                        |row| row.get(0),
+                    )
+                    .optional()?
+                    .map(timestamp))
+            })
+            .await
+    }
+
+    pub async fn set_cursor(&self, cursor: DateTime<Utc>) -> Result<()> {
+        self.connection
+            .call(move |connection| {
+                connection.execute(
+                    "INSERT INTO sync_state (name, value) VALUES (?1, ?2)
+                     ON CONFLICT (name) DO UPDATE SET value = excluded.value",
+                    params![CURSOR, cursor.timestamp_millis()],
+                )?;
+                Ok(())
+            })
+            .await
+    }
+}
+
+fn parse_id(id: &str) -> Result<ulid::Ulid> {
+    Ok(ulid::Ulid::from_string(id).with_context(|| format!("invalid recipe id: {id}"))?)
+}
+
+fn timestamp(millis: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_millis(millis).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_queue_changes() {
+        let store = SyncStore::open_in_memory().unwrap();
+        let (edited, deleted) = (ulid::Ulid::new(), ulid::Ulid::new());
+        let base_updated_at = DateTime::from_timestamp(1, 0).unwrap();
+
+        store.queue_update(edited, base_updated_at).await.unwrap();
+        store
+            .queue_update(edited, DateTime::from_timestamp(2, 0).unwrap())
+            .await
+            .unwrap();
+        store.queue_note(edited, "甘め").await.unwrap();
+        store.queue_note(deleted, "辛め").await.unwrap();
+        store.queue_delete(deleted).await.unwrap();
+
+        let kinds = store
+            .pending_changes()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|change| (change.recipe_id, change.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                (edited, ChangeKind::Update { base_updated_at }),
+                (edited, ChangeKind::Note("甘め".to_string())),
+                (deleted, ChangeKind::Delete),
+            ]
+        );
+        assert_eq!(
+            store.pending_recipe_ids().await.unwrap(),
+            HashSet::from([edited, deleted])
+        );
+
+        store.discard_updates(edited).await.unwrap();
+        let changes = store.pending_changes().await.unwrap();
+        assert_eq!(changes.len(), 2);
+        store.complete(changes[0].seq).await.unwrap();
+        store.discard_all(deleted).await.unwrap();
+        assert!(store.pending_changes().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cursor_survives_reopening() {
+        let path =
+            std::env::temp_dir().join(format!("recipena-sync-{}.sqlite3", ulid::Ulid::new()));
+        let cursor = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        {
+            let store = SyncStore::open(&path).unwrap();
+            assert_eq!(store.cursor().await.unwrap(), None);
+            store.set_cursor(cursor).await.unwrap();
+            store.queue_delete(ulid::Ulid::new()).await.unwrap();
+        }
+        let store = SyncStore::open(&path).unwrap();
+        assert_eq!(store.cursor().await.unwrap(), Some(cursor));
+        assert_eq!(store.pending_changes().await.unwrap().len(), 1);
+        std::fs::remove_file(path).unwrap();
+    }
+}
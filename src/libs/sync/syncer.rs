@@ -0,0 +1,484 @@
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use tokio::{
+    sync::{Mutex, Notify},
+    time::Instant,
+};
+
+use crate::{
+    config::SyncConfig,
+    domain::recipe::Recipe,
+    infra::{repository::recipe::RecipeRepository, sync::RecipeChangeFeed},
+    libs::repository::sqlite::SqliteRecipeRepository,
+    prelude::*,
+};
+
+use super::{
+    repository::SyncedRecipeRepository,
+    store::{ChangeKind, PendingChange, SyncStore},
+};
+
+/// Keeps a local SQLite mirror of the remote recipes, e.g. the Notion database, in sync.
+///
+/// Local writes are queued and pushed first, then the recipes edited remotely since the last
+/// pull are mirrored. A full pull, which also drops recipes deleted remotely, runs at startup
+/// and every `full_sync_interval`.
+pub struct RecipeSyncer {
+    remote: Arc<dyn RecipeRepository + Send + Sync>,
+    feed: Arc<dyn RecipeChangeFeed + Send + Sync>,
+    mirror: Arc<SqliteRecipeRepository>,
+    store: Arc<SyncStore>,
+    /// Held while writing, so a push or a pull never sees half of a local write.
+    lock: Arc<Mutex<()>>,
+    changed: Arc<Notify>,
+    interval: Duration,
+    full_sync_interval: Duration,
+}
+
+/// What one sync did, for logs.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    pub pushed: usize,
+    pub conflicts: usize,
+    pub pulled: usize,
+    pub removed: usize,
+}
+
+impl RecipeSyncer {
+    pub fn new(
+        remote: Arc<dyn RecipeRepository + Send + Sync>,
+        feed: Arc<dyn RecipeChangeFeed + Send + Sync>,
+        mirror: Arc<SqliteRecipeRepository>,
+        store: Arc<SyncStore>,
+        config: &SyncConfig,
+    ) -> Self {
+        Self {
+            remote,
+            feed,
+            mirror,
+            store,
+            lock: Arc::new(Mutex::new(())),
+            changed: Arc::new(Notify::new()),
+            interval: Duration::from_secs(config.interval_secs),
+            full_sync_interval: Duration::from_secs(config.full_sync_interval_secs),
+        }
+    }
+
+    /// A repository answering from the mirror and queuing its writes for this syncer.
+    pub fn repository(&self) -> SyncedRecipeRepository {
+        SyncedRecipeRepository::new(
+            self.remote.clone(),
+            self.mirror.clone(),
+            self.store.clone(),
+            self.lock.clone(),
+            self.changed.clone(),
+        )
+    }
+
+    /// Syncs on schedule, and pushes local writes as soon as they are made. Never returns.
+    pub async fn run(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(self.interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut last_full_sync: Option<Instant> = None;
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let full = last_full_sync
+                        .is_none_or(|at| at.elapsed() >= self.full_sync_interval);
+                    match self.sync(full).await {
+                        Ok(report) => {
+                            tracing::info!(full, ?report, "synced recipes");
+                            if full {
+                                last_full_sync = Some(Instant::now());
+                            }
+                        }
+                        Err(error) => {
+                            tracing::warn!(
+                                full,
+                                error_class = error.kind(),
+                                %error,
+                                "failed to sync recipes"
+                            );
+                        }
+                    }
+                }
+                _ = self.changed.notified() => {
+                    let mut report = SyncReport::default();
+                    if let Err(error) = self.push(&mut report).await {
+                        tracing::warn!(
+                            error_class = error.kind(),
+                            %error,
+                            "failed to push recipe changes"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Pushes the queued writes, then pulls the remote changes.
+    pub async fn sync(&self, full: bool) -> Result<SyncReport> {
+        let mut report = SyncReport::default();
+        self.push(&mut report).await?;
+        self.pull(full, &mut report).await?;
+        Ok(report)
+    }
+
+    /// Pushes the queued writes in order, stopping at the first one that fails so it is retried
+    /// by the next sync.
+    async fn push(&self, report: &mut SyncReport) -> Result<()> {
+        let _lock = self.lock.lock().await;
+        for change in self.store.pending_changes().await? {
+            if let Err(error) = self.push_change(&change, report).await {
+                // The recipe may have been deleted remotely, which would fail the change forever.
+                if self.remote.get_recipe(change.recipe_id).await?.is_some() {
+                    return Err(error);
+                }
+                self.forget(change.recipe_id).await?;
+                continue;
+            }
+            self.store.complete(change.seq).await?;
+            report.pushed += 1;
+        }
+        Ok(())
+    }
+
+    async fn push_change(&self, change: &PendingChange, report: &mut SyncReport) -> Result<()> {
+        let id = change.recipe_id;
+        match &change.kind {
+            ChangeKind::Update { base_updated_at } => {
+                let Some(remote) = self.remote.get_recipe(id).await? else {
+                    return self.forget(id).await;
+                };
+                if edited_after(remote.updated_at, *base_updated_at) {
+                    // Edits made remotely win over the local ones.
+                    tracing::warn!(
+                        recipe_id = %id,
+                        "discarded a local edit conflicting with a remote one"
+                    );
+                    self.store.discard_updates(id).await?;
+                    self.mirror.put_recipe(remote).await?;
+                    report.conflicts += 1;
+                    return Ok(());
+                }
+                if let Some(local) = self.mirror.get_recipe(id).await? {
+                    // Notes are only ever appended, by their own changes.
+                    let pushed = self
+                        .remote
+                        .update_recipe(Recipe {
+                            notes: remote.notes,
+                            ..local
+                        })
+                        .await?;
+                    self.mirror.put_recipe(pushed).await?;
+                }
+                Ok(())
+            }
+            ChangeKind::Note(note) => {
+                let Some(remote) = self.remote.get_recipe(id).await? else {
+                    return self.forget(id).await;
+                };
+                self.remote.add_note(id, note).await?;
+                self.rebase(id, remote.updated_at).await
+            }
+            ChangeKind::Delete => self.remote.delete_recipe(id).await,
+        }
+    }
+
+    /// Takes the remote edit time our push left on a recipe, so the push is not mistaken for a
+    /// remote edit conflicting with the changes still queued or made later.
+    async fn rebase(&self, id: ulid::Ulid, before: DateTime<Utc>) -> Result<()> {
+        let Some(remote) = self.remote.get_recipe(id).await? else {
+            return Ok(());
+        };
+        self.store
+            .rebase_updates(id, before, remote.updated_at)
+            .await?;
+        if let Some(local) = self.mirror.get_recipe(id).await? {
+            self.mirror
+                .put_recipe(Recipe {
+                    updated_at: remote.updated_at,
+                    ..local
+                })
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Drops a recipe that no longer exists remotely, with its queued changes.
+    async fn forget(&self, id: ulid::Ulid) -> Result<()> {
+        tracing::info!(recipe_id = %id, "dropped local changes to a recipe deleted remotely");
+        self.store.discard_all(id).await?;
+        self.mirror.delete_recipe(id).await
+    }
+
+    /// Mirrors the recipes edited remotely since the last pull, or all of them with `full`.
+    /// Recipes with queued writes are left alone until the writes are pushed.
+    async fn pull(&self, full: bool, report: &mut SyncReport) -> Result<()> {
+        let _lock = self.lock.lock().await;
+        let since = if full {
+            None
+        } else {
+            self.store.cursor().await?
+        };
+        let recipes = self.feed.recipes_edited_since(since).await?;
+        let pending = self.store.pending_recipe_ids().await?;
+        let cursor = recipes.iter().map(|recipe| recipe.updated_at).max();
+        let pulled = recipes
+            .iter()
+            .map(|recipe| recipe.id)
+            .collect::<HashSet<_>>();
+
+        for recipe in recipes {
+            if !pending.contains(&recipe.id) {
+                self.mirror.put_recipe(recipe).await?;
+                report.pulled += 1;
+            }
+        }
+        if full {
+            for id in self.mirror.recipe_ids().await? {
+                if !pulled.contains(&id) && !pending.contains(&id) {
+                    self.mirror.delete_recipe(id).await?;
+                    report.removed += 1;
+                }
+            }
+        }
+        if let Some(cursor) = cursor {
+            self.store.set_cursor(cursor).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares at the millisecond precision timestamps are mirrored with.
+fn edited_after(updated_at: DateTime<Utc>, base_updated_at: DateTime<Utc>) -> bool {
+    updated_at.timestamp_millis() > base_updated_at.timestamp_millis()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        infra::repository::recipe::MockRecipeRepository,
+        libs::repository::{conformance::recipe, memory::MemoryRecipeRepository},
+    };
+
+    use super::*;
+
+    fn syncer(remote: Arc<MemoryRecipeRepository>) -> RecipeSyncer {
+        RecipeSyncer::new(
+            remote.clone(),
+            remote,
+            Arc::new(SqliteRecipeRepository::open_in_memory().unwrap()),
+            Arc::new(SyncStore::open_in_memory().unwrap()),
+            &SyncConfig::default(),
+        )
+    }
+
+    async fn insert(repository: &impl RecipeRepository, name: &str, seconds: i64) -> Recipe {
+        repository
+            .insert_recipe(recipe(name, seconds))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_pull_mirrors_remote_recipes() {
+        let remote = Arc::new(MemoryRecipeRepository::default());
+        let chikuzenni = insert(remote.as_ref(), "筑前煮", 1).await;
+        let nikujaga = insert(remote.as_ref(), "肉じゃが", 2).await;
+        let syncer = syncer(remote.clone());
+        let local = syncer.repository();
+
+        let report = syncer.sync(true).await.unwrap();
+        assert_eq!(report.pulled, 2);
+        assert_eq!(
+            local.get_recipe(nikujaga.id).await.unwrap(),
+            Some(nikujaga.clone())
+        );
+
+        // Only the recipes edited since the last pull are read again.
+        let renamed = remote
+            .update_recipe(Recipe {
+                name: "豚肉じゃが".to_string(),
+                ..nikujaga.clone()
+            })
+            .await
+            .unwrap();
+        let report = syncer.sync(false).await.unwrap();
+        assert_eq!(report.pulled, 1);
+        assert_eq!(local.search("豚肉").await.unwrap()[0].id, renamed.id);
+
+        // Deletions are noticed by full pulls.
+        remote.delete_recipe(chikuzenni.id).await.unwrap();
+        assert_eq!(syncer.sync(false).await.unwrap().removed, 0);
+        assert_eq!(syncer.sync(true).await.unwrap().removed, 1);
+        assert_eq!(local.get_recipe(chikuzenni.id).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_push_local_writes() {
+        let remote = Arc::new(MemoryRecipeRepository::default());
+        let nikujaga = insert(remote.as_ref(), "肉じゃが", 1).await;
+        let chikuzenni = insert(remote.as_ref(), "筑前煮", 2).await;
+        let syncer = syncer(remote.clone());
+        let local = syncer.repository();
+        syncer.sync(true).await.unwrap();
+
+        let tonjiru = insert(&local, "豚汁", 3).await;
+        assert!(remote.get_recipe(tonjiru.id).await.unwrap().is_some());
+        local
+            .update_recipe(Recipe {
+                rating: Some(5),
+                ..nikujaga.clone()
+            })
+            .await
+            .unwrap();
+        local.add_note(nikujaga.id, "次は豚肉で").await.unwrap();
+        local.delete_recipe(chikuzenni.id).await.unwrap();
+        assert_eq!(local.get_recipe(chikuzenni.id).await.unwrap(), None);
+        assert!(remote.get_recipe(chikuzenni.id).await.unwrap().is_some());
+
+        let report = syncer.sync(false).await.unwrap();
+        assert_eq!(report.pushed, 3);
+        assert_eq!(report.conflicts, 0);
+        let pushed = remote.get_recipe(nikujaga.id).await.unwrap().unwrap();
+        assert_eq!(pushed.rating, Some(5));
+        assert_eq!(pushed.notes, ["甘めが好み", "次は豚肉で"]);
+        assert_eq!(remote.get_recipe(chikuzenni.id).await.unwrap(), None);
+        let mirrored = local.get_recipe(nikujaga.id).await.unwrap().unwrap();
+        assert_eq!(mirrored.rating, pushed.rating);
+        assert_eq!(mirrored.notes, pushed.notes);
+    }
+
+    #[tokio::test]
+    async fn test_pushed_note_does_not_conflict_with_later_update() {
+        let remote = Arc::new(MemoryRecipeRepository::default());
+        let nikujaga = insert(remote.as_ref(), "肉じゃが", 1).await;
+        let syncer = syncer(remote.clone());
+        let local = syncer.repository();
+        syncer.sync(true).await.unwrap();
+
+        local.add_note(nikujaga.id, "次は豚肉で").await.unwrap();
+        let noted = local.get_recipe(nikujaga.id).await.unwrap().unwrap();
+        local
+            .update_recipe(Recipe {
+                rating: Some(5),
+                ..noted
+            })
+            .await
+            .unwrap();
+        // Pushing the note later than it was written moves the remote edit time past it.
+        tokio::time::sleep(Duration::from_millis(5)).await;
+
+        let report = syncer.sync(false).await.unwrap();
+        assert_eq!(report.pushed, 2);
+        assert_eq!(report.conflicts, 0);
+        let pushed = remote.get_recipe(nikujaga.id).await.unwrap().unwrap();
+        assert_eq!(pushed.rating, Some(5));
+        assert_eq!(pushed.notes, ["甘めが好み", "次は豚肉で"]);
+
+        // Later edits are based on the time left by the push as well.
+        let mirrored = local.get_recipe(nikujaga.id).await.unwrap().unwrap();
+        assert_eq!(
+            mirrored.updated_at.timestamp_millis(),
+            pushed.updated_at.timestamp_millis()
+        );
+        local
+            .update_recipe(Recipe {
+                rating: Some(4),
+                ..mirrored
+            })
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        let report = syncer.sync(false).await.unwrap();
+        assert_eq!(report.conflicts, 0);
+        assert_eq!(
+            remote
+                .get_recipe(nikujaga.id)
+                .await
+                .unwrap()
+                .unwrap()
+                .rating,
+            Some(4)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_remote_edits_win_conflicts() {
+        let remote = Arc::new(MemoryRecipeRepository::default());
+        let nikujaga = insert(remote.as_ref(), "肉じゃが", 1).await;
+        let syncer = syncer(remote.clone());
+        let local = syncer.repository();
+        syncer.sync(true).await.unwrap();
+
+        local
+            .update_recipe(Recipe {
+                rating: Some(1),
+                ..nikujaga.clone()
+            })
+            .await
+            .unwrap();
+        remote
+            .update_recipe(Recipe {
+                rating: Some(5),
+                ..nikujaga.clone()
+            })
+            .await
+            .unwrap();
+
+        let report = syncer.sync(false).await.unwrap();
+        assert_eq!(report.conflicts, 1);
+        assert_eq!(
+            remote
+                .get_recipe(nikujaga.id)
+                .await
+                .unwrap()
+                .unwrap()
+                .rating,
+            Some(5)
+        );
+        assert_eq!(
+            local.get_recipe(nikujaga.id).await.unwrap().unwrap().rating,
+            Some(5)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_push_is_retried() {
+        let mut remote = MockRecipeRepository::new();
+        let mut attempts = 0;
+        remote.expect_delete_recipe().times(2).returning(move |_| {
+            attempts += 1;
+            if attempts == 1 {
+                Err(Error::Generic("rate limited".to_string()))
+            } else {
+                Ok(())
+            }
+        });
+        remote.expect_get_recipe().returning(|id| {
+            Ok(Some(Recipe {
+                id,
+                ..recipe("肉じゃが", 1)
+            }))
+        });
+        let syncer = RecipeSyncer::new(
+            Arc::new(remote),
+            Arc::new(MemoryRecipeRepository::default()),
+            Arc::new(SqliteRecipeRepository::open_in_memory().unwrap()),
+            Arc::new(SyncStore::open_in_memory().unwrap()),
+            &SyncConfig::default(),
+        );
+
+        syncer
+            .repository()
+            .delete_recipe(ulid::Ulid::new())
+            .await
+            .unwrap();
+        assert!(syncer.sync(false).await.is_err());
+        assert_eq!(syncer.store.pending_changes().await.unwrap().len(), 1);
+        assert_eq!(syncer.sync(false).await.unwrap().pushed, 1);
+        assert!(syncer.store.pending_changes().await.unwrap().is_empty());
+    }
+}
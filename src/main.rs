@@ -7,7 +7,7 @@ async fn main() -> Result<()> {
     recipena::logger::init_logger(&config)?;
 
     tracing::debug!("Starting Recipena");
-    let server = HttpServer::new(config);
+    let server = HttpServer::new(config).await?;
     server.run().await?;
 
     Ok(())